@@ -1,3 +1,9 @@
 glslc.exe ./shaders/simple.vert -o ./shaders_compiled/vert.spv
 glslc.exe ./shaders/simple.frag -o ./shaders_compiled/frag.spv
+glslc.exe ./shaders/skybox.vert -o ./shaders_compiled/skybox_vert.spv
+glslc.exe ./shaders/skybox.frag -o ./shaders_compiled/skybox_frag.spv
+glslc.exe ./shaders/particle.vert -o ./shaders_compiled/particle_vert.spv
+glslc.exe ./shaders/particle.frag -o ./shaders_compiled/particle_frag.spv
+glslc.exe ./shaders/swirl.comp -o ./shaders_compiled/swirl_comp.spv
+glslc.exe ./shaders/sequence.comp -o ./shaders_compiled/sequence_comp.spv
 pause
@@ -0,0 +1,15 @@
+#version 450
+
+layout(set = 1, binding = 0) uniform sampler2D textureSampler;
+
+layout(location = 0) in vec4 color;
+layout(location = 1) in vec2 uv;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    outColor = color * texture(textureSampler, uv);
+    if (outColor.a <= 0.0) {
+        discard;
+    }
+}
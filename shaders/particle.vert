@@ -0,0 +1,34 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} global;
+
+// One particle per instance
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in float inSize;
+layout(location = 2) in vec4 inColor;
+layout(location = 3) in vec4 inUVRect;
+
+layout(location = 0) out vec4 fragColor;
+layout(location = 1) out vec2 fragUV;
+
+// Two triangles, there is no vertex buffer
+const vec2 CORNERS[6] = vec2[](
+    vec2(-0.5, -0.5), vec2(0.5, -0.5), vec2(-0.5, 0.5),
+    vec2(-0.5, 0.5), vec2(0.5, -0.5), vec2(0.5, 0.5)
+);
+
+void main() {
+    vec2 corner = CORNERS[gl_VertexIndex];
+
+    // Spread the corners out in view space, so the quad always faces the camera
+    vec4 viewPosition = global.view * vec4(inPosition, 1.0);
+    viewPosition.xy += corner * inSize;
+    gl_Position = global.proj * viewPosition;
+
+    fragColor = inColor;
+    // Texture coordinates grow downwards
+    fragUV = inUVRect.xy + vec2(corner.x + 0.5, 0.5 - corner.y) * inUVRect.zw;
+}
@@ -1,17 +1,87 @@
 #version 450
-#
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+    vec4 fogColor;
+    float fogStart;
+    float fogEnd;
+    float fogDensity;
+    uint fogMode;
+    float heightFogBase;
+    float heightFogFalloff;
+    float heightFogDensity;
+    uint heightFogEnabled;
+} global;
+
 layout(set = 1, binding = 1) uniform sampler2D textureSampler;
 layout(set = 1, binding = 2) uniform MeshUniformFrag {
     vec4 tint;
+    float alphaCutoff;
+    float reflectivity;
+    // Negative when the texture holds colors rather than palette indices
+    int paletteRow;
 } instance;
+layout(set = 1, binding = 3) uniform samplerCube environmentSampler;
+layout(set = 1, binding = 4) uniform sampler2D paletteSampler;
 
 layout(location = 1) in vec2 uv;
+layout(location = 2) in vec3 viewDirection;
+layout(location = 3) in float viewDepth;
+layout(location = 4) in float worldHeight;
 
 layout(location = 0) out vec4 outColor;
 
+const uint FOG_LINEAR = 1;
+const uint FOG_EXPONENTIAL = 2;
+const uint FOG_EXPONENTIAL_SQUARED = 3;
+
+// How much of the fog color covers a fragment, from 0 to 1
+float fogAmount() {
+    float distance = max(viewDepth - global.fogStart, 0.0);
+
+    float amount = 0.0;
+    if (global.fogMode == FOG_LINEAR) {
+        amount = distance / max(global.fogEnd - global.fogStart, 0.0001);
+    } else if (global.fogMode == FOG_EXPONENTIAL) {
+        amount = 1.0 - exp(-global.fogDensity * distance);
+    } else if (global.fogMode == FOG_EXPONENTIAL_SQUARED) {
+        float scaled = global.fogDensity * distance;
+        amount = 1.0 - exp(-scaled * scaled);
+    }
+
+    if (global.heightFogEnabled != 0) {
+        // Thickest at the base height and thinning out above it
+        float above = max(worldHeight - global.heightFogBase, 0.0);
+        float density = global.heightFogDensity * exp(-above * global.heightFogFalloff);
+        float heightAmount = 1.0 - exp(-density * viewDepth);
+        amount = 1.0 - (1.0 - amount) * (1.0 - heightAmount);
+    }
+
+    return clamp(amount, 0.0, 1.0);
+}
+
 void main() {
-    outColor = instance.tint * texture(textureSampler, uv);
-    if (outColor.a == 0) {
+    vec4 texel = texture(textureSampler, uv);
+    if (instance.paletteRow >= 0) {
+        // The red channel picks a color from the palette row
+        int index = int(round(texel.r * 255.0));
+        texel.rgb = texelFetch(paletteSampler, ivec2(index, instance.paletteRow), 0).rgb;
+    }
+
+    outColor = instance.tint * texel;
+    if (outColor.a < instance.alphaCutoff) {
         discard;
     }
+
+    // Meshes carry no normals, the face normal comes from how the position changes
+    // across the screen. Differences of the view direction are those of the position.
+    vec3 normal = normalize(cross(dFdx(viewDirection), dFdy(viewDirection)));
+    if (dot(normal, viewDirection) > 0.0) {
+        normal = -normal;
+    }
+    vec3 environment = texture(environmentSampler, reflect(viewDirection, normal)).rgb;
+    outColor.rgb = mix(outColor.rgb, environment, instance.reflectivity);
+
+    outColor.rgb = mix(outColor.rgb, global.fogColor.rgb, fogAmount());
 }
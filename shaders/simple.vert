@@ -7,17 +7,55 @@ layout(set = 0, binding = 0) uniform UniformBufferObject {
 
 layout(set = 1, binding = 0) uniform MeshUniformBufferObject {
     mat4 model;
+    // Offset and size of the part of the texture shown, an atlas region for example
+    vec4 uvRect;
+    uint billboardMode;
 } instance;
 
 layout(location = 0) in vec3 inPosition;
 layout(location = 1) in vec2 inUV;
 
 layout(location = 1) out vec2 fragUV;
+layout(location = 2) out vec3 fragViewDirection;
+layout(location = 3) out float fragViewDepth;
+layout(location = 4) out float fragWorldHeight;
+
+const uint BILLBOARD_NONE = 0;
+const uint BILLBOARD_CYLINDRICAL = 2;
 
 void main() {
-    vec3 worldPosition = vec3(instance.model * vec4(inPosition, 1.0));
+    mat4 cameraToWorld = inverse(global.view);
+
+    vec3 worldPosition;
+    if (instance.billboardMode == BILLBOARD_NONE) {
+        worldPosition = vec3(instance.model * vec4(inPosition, 1.0));
+    } else {
+        // Keep the position and scale of the model, but lay the quad out along the
+        // camera axes instead of its own
+        vec3 center = instance.model[3].xyz;
+        vec2 scale = vec2(length(instance.model[0].xyz), length(instance.model[1].xyz));
+
+        vec3 right = cameraToWorld[0].xyz;
+        vec3 up = cameraToWorld[1].xyz;
+        if (instance.billboardMode == BILLBOARD_CYLINDRICAL) {
+            // Stay upright, the camera has no roll so its right axis is level
+            right = normalize(vec3(right.x, 0.0, right.z));
+            up = vec3(0.0, 1.0, 0.0);
+        }
+
+        worldPosition = center
+            + right * inPosition.x * scale.x
+            + up * inPosition.y * scale.y;
+    }
+
+    vec4 viewPosition = global.view * vec4(worldPosition, 1.0);
+    gl_Position = global.proj * viewPosition;
+
+    fragUV = instance.uvRect.xy + inUV * instance.uvRect.zw;
 
-    gl_Position = global.proj * global.view * instance.model * vec4(inPosition, 1.0);
+    vec3 cameraPosition = cameraToWorld[3].xyz;
+    fragViewDirection = worldPosition - cameraPosition;
 
-    fragUV = inUV;
+    fragViewDepth = abs(viewPosition.z);
+    fragWorldHeight = worldPosition.y;
 }
@@ -0,0 +1,38 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+    vec4 fogColor;
+    float fogStart;
+    float fogEnd;
+    float fogDensity;
+    uint fogMode;
+    float heightFogBase;
+    float heightFogFalloff;
+    float heightFogDensity;
+    uint heightFogEnabled;
+} global;
+
+layout(set = 1, binding = 0) uniform samplerCube skyboxSampler;
+
+layout(location = 0) in vec3 direction;
+
+layout(location = 0) out vec4 outColor;
+
+const uint FOG_NONE = 0;
+
+// How far above the horizon the fog reaches into the sky, as the height of a unit direction
+const float HORIZON_BLEND_HEIGHT = 0.35;
+
+void main() {
+    outColor = texture(skyboxSampler, direction);
+
+    if (global.fogMode != FOG_NONE || global.heightFogEnabled != 0) {
+        // The sky is infinitely far away, so any fog covers it at the horizon. Fade the
+        // fog out upwards so the sky still shows above the fogged world.
+        float height = normalize(direction).y;
+        float amount = 1.0 - smoothstep(0.0, HORIZON_BLEND_HEIGHT, height);
+        outColor.rgb = mix(outColor.rgb, global.fogColor.rgb, amount);
+    }
+}
@@ -0,0 +1,21 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} global;
+
+layout(location = 0) in vec3 inPosition;
+
+layout(location = 0) out vec3 fragDirection;
+
+void main() {
+    // Only the camera rotation, the sky is infinitely far away
+    mat4 rotationOnly = mat4(mat3(global.view));
+    vec4 position = global.proj * rotationOnly * vec4(inPosition, 1.0);
+
+    // z = w puts every fragment on the far plane after the perspective divide
+    gl_Position = position.xyww;
+
+    fragDirection = inPosition;
+}
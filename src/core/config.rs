@@ -6,3 +6,19 @@ pub const VALIDATION_LAYER: vk::ExtensionName = vk::ExtensionName::from_bytes(b"
 pub const PORTABILITY_MACOS_VERSION: Version = Version::new(1, 3, 216);
 pub const DEVICE_EXTENSIONS: &[vk::ExtensionName] = &[vk::KHR_SWAPCHAIN_EXTENSION.name];
 pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+/// Name of the environment variable that forces a physical device, either by its
+/// index in enumeration order or by part of its name, e.g. `llvmpipe` for lavapipe.
+pub const PHYSICAL_DEVICE_OVERRIDE_VARIABLE: &str = "SAGA_PHYSICAL_DEVICE";
+/// Name of the environment variable that turns validation errors into panics when set
+/// to anything but `0`.
+pub const STRICT_VALIDATION_VARIABLE: &str = "SAGA_STRICT_VALIDATION";
+/// Directory under the user data directory where the engine keeps files between runs.
+pub const DATA_DIRECTORY_NAME: &str = "saga";
+/// File in the data directory holding compiled pipelines, so startup skips
+/// recompiling shaders the driver has seen before.
+pub const PIPELINE_CACHE_FILE_NAME: &str = "pipeline_cache.bin";
+/// Copy uploads on a queue family of their own when the device has one, next to rendering.
+pub const USE_DEDICATED_TRANSFER_QUEUE: bool = true;
+/// Bytes of host visible memory uploads are staged in. Larger uploads get a staging
+/// buffer of their own.
+pub const STAGING_RING_SIZE: u64 = 32 * 1024 * 1024;
@@ -6,18 +6,27 @@
 
 mod abstraction;
 mod buffers;
+mod capture;
 mod command_buffers;
+mod compute;
+mod deletion_queue;
 mod descriptor;
 mod errors;
+mod frame_graph;
+mod frame_recorder;
 mod framebuffer;
+mod gltf_loader;
 mod instance;
 mod logical_device;
+mod obj_loader;
 mod physical_device;
 mod pipeline;
+mod pipeline_cache;
 mod queue_families;
-mod renderpass;
+mod render_target;
 mod shader;
 mod swapchain;
+mod upload;
 mod sync_objects;
 mod validation_layers;
 mod window_surface;
@@ -25,3 +34,5 @@ mod wrappers;
 mod graphics;
 
 pub use graphics::*;
+pub use gltf_loader::{GltfPrimitive, GltfScene};
+pub use obj_loader::ObjModel;
@@ -1,5 +1,14 @@
-use crate::core::graphics::descriptor::{self};
+use crate::core::graphics::{
+    deletion_queue::DeviceResource,
+    descriptor::{self},
+};
 use anyhow::Result;
+use std::{
+    collections::HashMap,
+    fmt,
+    ops::Deref,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
 use vulkanalia::prelude::v1_0::*;
 
 pub struct DescriptorAllocator {
@@ -8,6 +17,53 @@ pub struct DescriptorAllocator {
     pool_index: usize,
     minimum_size: u32,
     maximum_size: u32,
+
+    // which pool every live set was allocated from
+    owners: HashMap<vk::DescriptorSet, usize>,
+    total_allocated: u64,
+    total_released: u64,
+}
+
+/// A [`DescriptorAllocator`] the sets it handed out can find their way back to.
+#[derive(Clone)]
+pub struct SharedDescriptorAllocator {
+    allocator: Arc<Mutex<DescriptorAllocator>>,
+}
+
+/// Sets allocated together, usually one per swapchain image. Held as an
+/// [`Owned`](crate::core::graphics::deletion_queue::Owned), so dropping them returns
+/// the sets to their pool once no frame in flight uses them.
+pub struct DescriptorSets {
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    allocator: SharedDescriptorAllocator,
+}
+
+/// A snapshot of how the pools of a [`DescriptorAllocator`] are used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DescriptorAllocatorStats {
+    pub pools: usize,
+    /// Sets all pools can hold together
+    pub capacity: usize,
+    /// Sets currently handed out, including dropped ones still queued for release
+    pub allocated: usize,
+    /// Sets ever allocated
+    pub total_allocated: u64,
+    /// Sets ever returned to their pool
+    pub total_released: u64,
+}
+
+impl fmt::Display for DescriptorAllocatorStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} sets in {} pools, {} allocated and {} released in total",
+            self.allocated,
+            self.capacity,
+            self.pools,
+            self.total_allocated,
+            self.total_released
+        )
+    }
 }
 
 impl DescriptorAllocator {
@@ -23,6 +79,9 @@ impl DescriptorAllocator {
             pool_index: 0,
             minimum_size,
             maximum_size,
+            owners: HashMap::new(),
+            total_allocated: 0,
+            total_released: 0,
         }
     }
 
@@ -32,37 +91,52 @@ impl DescriptorAllocator {
         descriptor_set_layout: vk::DescriptorSetLayout,
         number_of_set_layouts: usize,
     ) -> Result<Vec<vk::DescriptorSet>> {
-        // We perform 2 allocations. If the first one fails
-        // then we have ran out of memory on our current pool
-        // and must move on to the next one, hence the second allocation
-
-        if let Ok(descriptor_sets) =
-            self.allocate_once(device, descriptor_set_layout, number_of_set_layouts)
-        {
-            Ok(descriptor_sets)
-        } else {
-            self.pool_index += 1;
-            if self.pool_index >= self.pools.len() {
-                let pool = self.create_next_pool(device)?;
-                self.pools.push(pool);
-                self.pool_index = self.pools.len() - 1;
+        // Released sets leave holes in older pools, so every pool with enough room
+        // is tried before growing, starting with the one we last allocated from.
+        // A pool can still refuse when its memory is fragmented, then we move on.
+        let pool_count = self.pools.len();
+        for offset in 0..pool_count {
+            let index = (self.pool_index + offset) % pool_count;
+            if self.pools[index].get_num_descriptors_left() < number_of_set_layouts {
+                continue;
             }
 
-            self.allocate_once(device, descriptor_set_layout, number_of_set_layouts)
+            if let Ok(descriptor_sets) =
+                self.allocate_from(index, device, descriptor_set_layout, number_of_set_layouts)
+            {
+                self.pool_index = index;
+                return Ok(descriptor_sets);
+            }
         }
+
+        let pool = self.create_next_pool(device)?;
+        self.pools.push(pool);
+        self.pool_index = self.pools.len() - 1;
+
+        self.allocate_from(
+            self.pool_index,
+            device,
+            descriptor_set_layout,
+            number_of_set_layouts,
+        )
     }
 
-    unsafe fn allocate_once(
+    unsafe fn allocate_from(
         &mut self,
+        pool_index: usize,
         device: &Device,
         descriptor_set_layout: vk::DescriptorSetLayout,
         number_of_set_layouts: usize,
     ) -> Result<Vec<vk::DescriptorSet>> {
-        if let Some(pool) = self.pools.get_mut(self.pool_index) {
-            pool.create(device, descriptor_set_layout, number_of_set_layouts)
-        } else {
-            Err(anyhow::anyhow!("Pool is empty, cannot allocate"))
+        let descriptor_sets =
+            self.pools[pool_index].create(device, descriptor_set_layout, number_of_set_layouts)?;
+
+        for descriptor_set in descriptor_sets.iter() {
+            self.owners.insert(*descriptor_set, pool_index);
         }
+        self.total_allocated += descriptor_sets.len() as u64;
+
+        Ok(descriptor_sets)
     }
 
     unsafe fn create_next_pool(&self, device: &Device) -> Result<descriptor::Pool> {
@@ -83,20 +157,58 @@ impl DescriptorAllocator {
         unsafe { descriptor::pool::create(device, &descriptions, next_pool_size) }
     }
 
+    // No frame in flight may still be using the sets
+    unsafe fn release(
+        &mut self,
+        device: &Device,
+        descriptor_sets: &[vk::DescriptorSet],
+    ) -> Result<()> {
+        let mut sets_by_pool: HashMap<usize, Vec<vk::DescriptorSet>> = HashMap::new();
+        for descriptor_set in descriptor_sets {
+            match self.owners.remove(descriptor_set) {
+                Some(pool_index) => sets_by_pool
+                    .entry(pool_index)
+                    .or_default()
+                    .push(*descriptor_set),
+                None => log::warn!(
+                    "Descriptor set {:?} was released but not allocated here",
+                    descriptor_set
+                ),
+            }
+        }
+
+        for (pool_index, descriptor_sets) in sets_by_pool {
+            let pool = &mut self.pools[pool_index];
+            pool.release(device, &descriptor_sets)?;
+            self.total_released += descriptor_sets.len() as u64;
+
+            // an empty pool is reset to undo any fragmentation
+            if pool.get_num_allocated() == 0 {
+                pool.free(device)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get_stats(&self) -> DescriptorAllocatorStats {
+        DescriptorAllocatorStats {
+            pools: self.pools.len(),
+            capacity: self.pools.iter().map(|pool| pool.get_size()).sum(),
+            allocated: self.pools.iter().map(|pool| pool.get_num_allocated()).sum(),
+            total_allocated: self.total_allocated,
+            total_released: self.total_released,
+        }
+    }
+
     pub unsafe fn free(&mut self, device: &Device) -> Result<()> {
-        let number_of_pools_to_free = self.pool_index
-            + (if self.pool_index < self.pools.len() {
-                1
-            } else {
-                0
-            });
-
-        for i in 0..number_of_pools_to_free {
+        for pool in self.pools.iter_mut() {
             unsafe {
-                self.pools[i].free(device)?;
+                pool.free(device)?;
             }
         }
         self.pool_index = 0;
+        self.owners.clear();
 
         Ok(())
     }
@@ -109,5 +221,51 @@ impl DescriptorAllocator {
         }
         self.pools.clear();
         self.pool_index = 0;
+        self.owners.clear();
+    }
+}
+
+impl SharedDescriptorAllocator {
+    pub fn new(allocator: DescriptorAllocator) -> Self {
+        Self {
+            allocator: Arc::new(Mutex::new(allocator)),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, DescriptorAllocator> {
+        // Sets dropped while unwinding still have to reach their pool
+        self.allocator.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub unsafe fn allocate(
+        &self,
+        device: &Device,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        number_of_set_layouts: usize,
+    ) -> Result<DescriptorSets> {
+        let descriptor_sets =
+            self.lock()
+                .allocate(device, descriptor_set_layout, number_of_set_layouts)?;
+
+        Ok(DescriptorSets {
+            descriptor_sets,
+            allocator: self.clone(),
+        })
+    }
+}
+
+impl Deref for DescriptorSets {
+    type Target = [vk::DescriptorSet];
+
+    fn deref(&self) -> &[vk::DescriptorSet] {
+        &self.descriptor_sets
+    }
+}
+
+impl DeviceResource for DescriptorSets {
+    unsafe fn destroy(&self, device: &Device) {
+        if let Err(e) = self.allocator.lock().release(device, &self.descriptor_sets) {
+            log::error!("Failed to release descriptor sets: {}", e);
+        }
     }
 }
@@ -1,16 +1,18 @@
-use crate::core::graphics::{ImageSampler, LoadedImage};
+use crate::core::graphics::{ImageSampler, LoadedImage, StorageBuffer};
 use vulkanalia::prelude::v1_0::*;
 
 struct ImageWriteInformation {
     info: [vk::DescriptorImageInfoBuilder; 1],
     binding: u32,
     descriptor_set: vk::DescriptorSet,
+    descriptor_type: vk::DescriptorType,
 }
 
 struct UniformWriteInformation {
     info: [vk::DescriptorBufferInfoBuilder; 1],
     binding: u32,
     descriptor_set: vk::DescriptorSet,
+    descriptor_type: vk::DescriptorType,
 }
 
 #[derive(Default)]
@@ -29,7 +31,7 @@ impl DescriptorWriter {
         binding: u32,
     ) {
         let info = vk::DescriptorImageInfo::builder()
-            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_layout(image.get_sampled_layout())
             .image_view(image.get_image_view())
             .sampler(sampler.get_sampler());
 
@@ -38,6 +40,29 @@ impl DescriptorWriter {
                 info: [info],
                 binding,
                 descriptor_set: descriptor_set.clone(),
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            })
+        })
+    }
+
+    /// An image compute shaders load from and store into, in the general layout.
+    pub fn queue_write_storage_image(
+        &mut self,
+        device: &Device,
+        image: &LoadedImage,
+        descriptor_sets: &[vk::DescriptorSet],
+        binding: u32,
+    ) {
+        let info = vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::GENERAL)
+            .image_view(image.get_image_view());
+
+        descriptor_sets.iter().for_each(|descriptor_set| {
+            self.image_writes.push(ImageWriteInformation {
+                info: [info],
+                binding,
+                descriptor_set: *descriptor_set,
+                descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
             })
         })
     }
@@ -60,6 +85,30 @@ impl DescriptorWriter {
                 info: [info],
                 binding,
                 descriptor_set: descriptor_set.clone(),
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+            })
+        })
+    }
+
+    /// The whole of `buffer`, as a storage buffer shaders read and write.
+    pub fn queue_write_storage_buffer(
+        &mut self,
+        device: &Device,
+        buffer: &StorageBuffer,
+        descriptor_sets: &[vk::DescriptorSet],
+        binding: u32,
+    ) {
+        let info = vk::DescriptorBufferInfo::builder()
+            .buffer(buffer.get_buffer())
+            .offset(0)
+            .range(vk::WHOLE_SIZE as u64);
+
+        descriptor_sets.iter().for_each(|descriptor_set| {
+            self.uniform_writes.push(UniformWriteInformation {
+                info: [info],
+                binding,
+                descriptor_set: *descriptor_set,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
             })
         })
     }
@@ -73,7 +122,7 @@ impl DescriptorWriter {
                     .dst_set(info.descriptor_set)
                     .dst_binding(info.binding)
                     .dst_array_element(0)
-                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .descriptor_type(info.descriptor_type)
                     .image_info(&info.info)
             })
             .chain(self.uniform_writes.iter().map(|info| {
@@ -81,7 +130,7 @@ impl DescriptorWriter {
                     .dst_set(info.descriptor_set)
                     .dst_binding(info.binding)
                     .dst_array_element(0)
-                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .descriptor_type(info.descriptor_type)
                     .buffer_info(&info.info)
             }))
             .collect();
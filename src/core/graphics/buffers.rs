@@ -2,8 +2,6 @@ use anyhow::{anyhow, Result};
 use vulkanalia::prelude::v1_0::*;
 use vulkanalia::vk::{self};
 
-use super::command_buffers::{begin_single_time_commands, end_single_time_commands};
-
 type Vec2 = cgmath::Vector2<f32>;
 type Vec3 = cgmath::Vector3<f32>;
 
@@ -59,21 +57,3 @@ pub unsafe fn get_memory_type_index(
         })
         .ok_or_else(|| anyhow!("Failed to find suitable memory type"))
 }
-
-pub unsafe fn copy_buffer(
-    device: &Device,
-    source: vk::Buffer,
-    destination: vk::Buffer,
-    size: vk::DeviceSize,
-    command_pool: vk::CommandPool,
-    graphics_queue: vk::Queue,
-) -> Result<()> {
-    let command_buffer = begin_single_time_commands(device, command_pool)?;
-
-    let regions = vk::BufferCopy::builder().size(size);
-    device.cmd_copy_buffer(command_buffer, source, destination, &[regions]);
-
-    end_single_time_commands(device, graphics_queue, command_pool, command_buffer)?;
-
-    Ok(())
-}
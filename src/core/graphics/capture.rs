@@ -0,0 +1,236 @@
+use anyhow::{anyhow, Result};
+use std::{fs::File, io::BufWriter, path::Path};
+use vulkanalia::prelude::v1_0::*;
+
+use super::buffers::create_buffer;
+
+/// A copy of a presented swapchain image, still in the surface format.
+/// Conversion to RGBA is deferred to [`CapturedFrame::save_png`] so it can
+/// happen off the render thread.
+#[derive(Clone)]
+pub struct CapturedFrame {
+    width: u32,
+    height: u32,
+    format: vk::Format,
+    pixels: Vec<u8>,
+}
+
+impl CapturedFrame {
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    /// Pixels as tightly packed [r, g, b, a] tuples in sRGB
+    pub fn to_rgba(&self) -> Result<Vec<u8>> {
+        let swap_red_and_blue = match self.format {
+            vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => true,
+            vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => false,
+            format => return Err(anyhow!("Cannot convert captured format {:?}", format)),
+        };
+
+        let mut pixels = self.pixels.clone();
+        for pixel in pixels.chunks_exact_mut(4) {
+            if swap_red_and_blue {
+                pixel.swap(0, 2);
+            }
+            // The swapchain is composited as opaque, alpha is meaningless
+            pixel[3] = u8::MAX;
+        }
+
+        Ok(pixels)
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<()> {
+        let pixels = self.to_rgba()?;
+
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&pixels)?;
+        Ok(())
+    }
+}
+
+/// A copy of a swapchain image into host memory, submitted along with the frame that
+/// draws the image. Its pixels can be read once that frame's in-flight fence signals,
+/// so capturing never waits for the device.
+pub struct PendingCapture {
+    command_buffer: vk::CommandBuffer,
+    buffer: vk::Buffer,
+    buffer_memory: vk::DeviceMemory,
+    format: vk::Format,
+    extent: vk::Extent2D,
+}
+
+const BYTES_PER_PIXEL: u64 = 4;
+
+impl PendingCapture {
+    /// Record copying a swapchain image, to be submitted right after the command
+    /// buffer drawing it. The image is left in the `PRESENT_SRC_KHR` layout the frame
+    /// ends with.
+    pub unsafe fn record(
+        instance: &Instance,
+        device: &Device,
+        physical_device: vk::PhysicalDevice,
+        command_pool: vk::CommandPool,
+        image: vk::Image,
+        format: vk::Format,
+        extent: vk::Extent2D,
+    ) -> Result<Self> {
+        let size = extent.width as u64 * extent.height as u64 * BYTES_PER_PIXEL;
+        let (buffer, buffer_memory) = create_buffer(
+            instance,
+            device,
+            physical_device,
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+
+        let info = vk::CommandBufferAllocateInfo::builder()
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_pool(command_pool)
+            .command_buffer_count(1);
+        let command_buffer = device.allocate_command_buffers(&info)?[0];
+        let info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        device.begin_command_buffer(command_buffer, &info)?;
+        record_copy(device, command_buffer, image, buffer, extent);
+        device.end_command_buffer(command_buffer)?;
+
+        Ok(Self {
+            command_buffer,
+            buffer,
+            buffer_memory,
+            format,
+            extent,
+        })
+    }
+
+    pub fn get_command_buffer(&self) -> vk::CommandBuffer {
+        self.command_buffer
+    }
+
+    /// Only once the submission carrying the copy has completed.
+    pub unsafe fn read(
+        self,
+        device: &Device,
+        command_pool: vk::CommandPool,
+    ) -> Result<CapturedFrame> {
+        use std::ptr::copy_nonoverlapping as memcpy;
+
+        let size = self.extent.width as u64 * self.extent.height as u64 * BYTES_PER_PIXEL;
+        let pixels = device
+            .map_memory(self.buffer_memory, 0, size, vk::MemoryMapFlags::empty())
+            .map(|memory| {
+                let mut pixels = vec![0u8; size as usize];
+                memcpy(memory.cast(), pixels.as_mut_ptr(), size as usize);
+                device.unmap_memory(self.buffer_memory);
+                pixels
+            });
+        let frame = CapturedFrame {
+            width: self.extent.width,
+            height: self.extent.height,
+            format: self.format,
+            pixels: pixels?,
+        };
+        self.destroy(device, command_pool);
+        Ok(frame)
+    }
+
+    /// Only once the submission carrying the copy has completed, or was never made.
+    pub unsafe fn destroy(self, device: &Device, command_pool: vk::CommandPool) {
+        device.free_command_buffers(command_pool, &[self.command_buffer]);
+        device.destroy_buffer(self.buffer, None);
+        device.free_memory(self.buffer_memory, None);
+    }
+}
+
+/// The image must be in the `PRESENT_SRC_KHR` layout and is returned to it.
+unsafe fn record_copy(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    buffer: vk::Buffer,
+    extent: vk::Extent2D,
+) {
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1);
+
+    let to_transfer_source = vk::ImageMemoryBarrier::builder()
+        .old_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+        .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource_range)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_READ);
+
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[to_transfer_source],
+    );
+
+    let subresource = vk::ImageSubresourceLayers::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(0)
+        .layer_count(1);
+
+    let region = vk::BufferImageCopy::builder()
+        .buffer_offset(0)
+        .buffer_row_length(0) // means they are tightly packed in memory
+        .buffer_image_height(0) // means they are tightly packed in memory
+        .image_subresource(subresource)
+        .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+        .image_extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        });
+
+    device.cmd_copy_image_to_buffer(
+        command_buffer,
+        image,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        buffer,
+        &[region],
+    );
+
+    let to_present_source = vk::ImageMemoryBarrier::builder()
+        .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource_range)
+        .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+        .dst_access_mask(vk::AccessFlags::empty());
+
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[to_present_source],
+    );
+}
@@ -23,10 +23,6 @@ pub unsafe fn allocate_command_buffers(
 pub unsafe fn record_command_buffers<F>(
     device: &Device,
     command_buffers: &[vk::CommandBuffer],
-    swapchain_extent: vk::Extent2D,
-    render_pass: vk::RenderPass,
-    pipeline: vk::Pipeline,
-    framebuffers: &[vk::Framebuffer],
     record_function: F,
     graphics: &Graphics,
 ) -> Result<()>
@@ -38,42 +34,15 @@ where
 
         device.begin_command_buffer(*command_buffer, &info)?;
 
-        let render_area = vk::Rect2D::builder()
-            .offset(vk::Offset2D::default())
-            .extent(swapchain_extent);
-
-        let color_clear_value = vk::ClearValue {
-            color: vk::ClearColorValue {
-                float32: [0.0, 0.0, 0.0, 1.0],
-            },
-        };
-
-        let depth_clear_value = vk::ClearValue {
-            depth_stencil: vk::ClearDepthStencilValue {
-                depth: 1.0,
-                stencil: 0,
-            },
-        };
-
-        let clear_values = &[color_clear_value, depth_clear_value];
-        let info = vk::RenderPassBeginInfo::builder()
-            .render_pass(render_pass)
-            .framebuffer(framebuffers[i])
-            .render_area(render_area)
-            .clear_values(clear_values);
-
-        device.cmd_begin_render_pass(*command_buffer, &info, vk::SubpassContents::INLINE);
-        device.cmd_bind_pipeline(*command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
-
         record_function(graphics, *command_buffer, i);
 
-        device.cmd_end_render_pass(*command_buffer);
         device.end_command_buffer(*command_buffer)?;
     }
 
     Ok(())
 }
 
+/// Begin a render pass clearing the whole framebuffer, then bind `pipeline`.
 pub unsafe fn create_command_pool(
     instance: &Instance,
     device: &Device,
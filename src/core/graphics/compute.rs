@@ -0,0 +1,400 @@
+use anyhow::{anyhow, Result};
+use std::mem::size_of;
+use std::ptr::copy_nonoverlapping as memcpy;
+use vulkanalia::prelude::v1_0::*;
+
+use super::buffers;
+use super::deletion_queue::DeviceResource;
+use super::descriptor;
+use super::shader;
+
+/// A compute shader with the layout of its only descriptor set, set 0.
+pub struct ComputePipeline {
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+}
+
+impl ComputePipeline {
+    /// `bindings` are the descriptor types of set 0, binding 0 first.
+    pub unsafe fn create(
+        device: &Device,
+        pipeline_cache: vk::PipelineCache,
+        spirv: &[u8],
+        bindings: &[vk::DescriptorType],
+    ) -> Result<Self> {
+        let descriptors = bindings
+            .iter()
+            .enumerate()
+            .map(|(binding, &descriptor_type)| descriptor::layout::DescriptorInfo {
+                binding: binding as u32,
+                descriptor_type,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+            })
+            .collect::<Vec<_>>();
+        let descriptor_set_layout = descriptor::layout::create(device, &descriptors)?;
+
+        let set_layouts = &[descriptor_set_layout];
+        let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts);
+        let pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+
+        let module = shader::create_shader_module(device, spirv)?;
+        let stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(module)
+            .name(b"main\0");
+        let info = vk::ComputePipelineCreateInfo::builder()
+            .stage(stage)
+            .layout(pipeline_layout)
+            .base_pipeline_handle(vk::Pipeline::null())
+            .base_pipeline_index(-1);
+        let pipeline = device.create_compute_pipelines(pipeline_cache, &[info], None);
+        shader::destroy_shader_module(device, module);
+
+        Ok(Self {
+            pipeline: pipeline?.0[0],
+            pipeline_layout,
+            descriptor_set_layout,
+        })
+    }
+
+    pub fn get_descriptor_set_layout(&self) -> vk::DescriptorSetLayout {
+        self.descriptor_set_layout
+    }
+
+    /// Run `group_counts` work groups of the shader over `descriptor_set`. Follow it
+    /// with a [`ComputeBarrier`] before anything reads what it wrote.
+    pub unsafe fn dispatch(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+        group_counts: [u32; 3],
+    ) {
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.pipeline_layout,
+            0,
+            &[descriptor_set],
+            &[],
+        );
+        let [x, y, z] = group_counts;
+        device.cmd_dispatch(command_buffer, x, y, z);
+    }
+}
+
+impl DeviceResource for ComputePipeline {
+    unsafe fn destroy(&self, device: &Device) {
+        device.destroy_pipeline(self.pipeline, None);
+        device.destroy_pipeline_layout(self.pipeline_layout, None);
+        descriptor::layout::destroy(device, self.descriptor_set_layout);
+    }
+}
+
+/// Work groups needed to cover `items` with groups of `group_size`, the last one
+/// partially filled.
+pub fn get_group_count(items: u32, group_size: u32) -> u32 {
+    items.div_ceil(group_size)
+}
+
+/// Waits between a dispatch and the work after it that reads what it wrote, or
+/// between rendering and a dispatch reading what was drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ComputeBarrier {
+    /// Another dispatch reads or writes it
+    Compute,
+    /// Drawn from, as vertex or instance data or as indirect draw commands
+    VertexInput,
+    /// Read by fragment shaders, post effects for example
+    Fragment,
+    /// Read back by the host once the submission finished
+    Host,
+    /// The dispatch reads what render passes recorded before it drew, or overwrites
+    /// what they sampled
+    AfterRendering,
+}
+
+impl ComputeBarrier {
+    pub unsafe fn record(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        let compute_write = (
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+        );
+        let ((src_stage, src_access), (dst_stage, dst_access)) = match self {
+            ComputeBarrier::Compute => (
+                compute_write,
+                (
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                ),
+            ),
+            ComputeBarrier::VertexInput => (
+                compute_write,
+                (
+                    vk::PipelineStageFlags::VERTEX_INPUT | vk::PipelineStageFlags::DRAW_INDIRECT,
+                    vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                        | vk::AccessFlags::INDIRECT_COMMAND_READ,
+                ),
+            ),
+            ComputeBarrier::Fragment => (
+                compute_write,
+                (
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::AccessFlags::SHADER_READ,
+                ),
+            ),
+            ComputeBarrier::Host => (
+                compute_write,
+                (vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ),
+            ),
+            ComputeBarrier::AfterRendering => (
+                (
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                ),
+                (
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                ),
+            ),
+        };
+
+        let barrier = vk::MemoryBarrier::builder()
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access);
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            src_stage,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            &[barrier],
+            &[] as &[vk::BufferMemoryBarrier],
+            &[] as &[vk::ImageMemoryBarrier],
+        );
+    }
+}
+
+/// A buffer compute shaders read and write, which can be drawn from as vertex or
+/// indirect draw input as well. Host visible, so it is filled and read back
+/// directly, images included once they are copied into it.
+pub struct StorageBuffer {
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    size: u64,
+}
+
+impl StorageBuffer {
+    pub unsafe fn create(
+        instance: &Instance,
+        device: &Device,
+        physical_device: vk::PhysicalDevice,
+        size: u64,
+    ) -> Result<Self> {
+        let (buffer, memory) = buffers::create_buffer(
+            instance,
+            device,
+            physical_device,
+            size.max(1),
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::VERTEX_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+
+        Ok(Self {
+            buffer,
+            memory,
+            size,
+        })
+    }
+
+    pub fn get_buffer(&self) -> vk::Buffer {
+        self.buffer
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    /// Replace the start of the buffer with `data`.
+    pub unsafe fn write<T: Copy>(&self, device: &Device, data: &[T]) -> Result<()> {
+        let size = std::mem::size_of_val(data) as u64;
+        if size > self.size {
+            return Err(anyhow!(
+                "Writing {} bytes into a storage buffer of {}",
+                size,
+                self.size
+            ));
+        }
+        if size == 0 {
+            return Ok(());
+        }
+
+        let mapped = device.map_memory(self.memory, 0, size, vk::MemoryMapFlags::empty())?;
+        memcpy(data.as_ptr(), mapped.cast(), data.len());
+        device.unmap_memory(self.memory);
+        Ok(())
+    }
+
+    /// As many whole `T` as fit in the buffer. Only meaningful once the dispatches
+    /// writing it finished, behind a [`ComputeBarrier::Host`].
+    pub unsafe fn read<T: Copy>(&self, device: &Device) -> Result<Vec<T>> {
+        let count = self.size as usize / size_of::<T>();
+        let mut data = Vec::with_capacity(count);
+        if count == 0 {
+            return Ok(data);
+        }
+
+        let size = (count * size_of::<T>()) as u64;
+        let mapped = device.map_memory(self.memory, 0, size, vk::MemoryMapFlags::empty())?;
+        memcpy(mapped.cast::<T>(), data.as_mut_ptr(), count);
+        data.set_len(count);
+        device.unmap_memory(self.memory);
+        Ok(data)
+    }
+}
+
+impl DeviceResource for StorageBuffer {
+    unsafe fn destroy(&self, device: &Device) {
+        device.destroy_buffer(self.buffer, None);
+        device.free_memory(self.memory, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::graphics::abstraction::descriptor_writer::DescriptorWriter;
+    use crate::core::graphics::command_buffers::{
+        begin_single_time_commands, end_single_time_commands,
+    };
+    use crate::core::graphics::descriptor::pool::PoolDescription;
+    use crate::core::graphics::physical_device::{
+        find_overridden_physical_device, get_physical_device_override,
+    };
+    use crate::core::graphics::queue_families::get_compute_family;
+    use vulkanalia::loader::{LibloadingLoader, LIBRARY};
+    use vulkanalia::Entry;
+
+    /// Writes `index * 3 + 1` to every `uint` of the storage buffer at binding 0.
+    const SEQUENCE_SHADER: &[u8] = include_bytes!("../../../shaders_compiled/sequence_comp.spv");
+    const SEQUENCE_GROUP_SIZE: u32 = 64;
+    // Not a multiple of the group size, so the last group is partially filled
+    const COUNT: u32 = 100;
+
+    /// Run the sequence shader once over a storage buffer, on a device without a
+    /// window, and read the buffer back. The device is the one forced through
+    /// `SAGA_PHYSICAL_DEVICE` (e.g. `llvmpipe` for lavapipe), or else the first one
+    /// that can dispatch compute shaders. `None` when there is no such device, or
+    /// no Vulkan at all.
+    unsafe fn dispatch_sequence_and_read_back() -> Result<Option<Vec<u32>>> {
+        let Ok(loader) = LibloadingLoader::new(LIBRARY) else {
+            return Ok(None);
+        };
+        let entry = Entry::new(loader).map_err(|b| anyhow!("{}", b))?;
+        let application_info =
+            vk::ApplicationInfo::builder().api_version(vk::make_version(1, 0, 0));
+        let instance_info = vk::InstanceCreateInfo::builder().application_info(&application_info);
+        let Ok(instance) = entry.create_instance(&instance_info, None) else {
+            return Ok(None);
+        };
+
+        let get_family = |physical_device| {
+            let properties = instance.get_physical_device_queue_family_properties(physical_device);
+            get_compute_family(&properties).map(|family| (physical_device, family))
+        };
+        let physical_devices = instance.enumerate_physical_devices()?;
+        let selected = match get_physical_device_override() {
+            // Running on a device that was asked for by name must not quietly skip
+            Some(device_override) => {
+                let index =
+                    find_overridden_physical_device(&instance, &physical_devices, &device_override)?;
+                let selected = get_family(physical_devices[index]).ok_or_else(|| {
+                    anyhow!("{} cannot dispatch compute shaders", device_override)
+                })?;
+                Some(selected)
+            }
+            None => physical_devices.into_iter().find_map(get_family),
+        };
+        let Some((physical_device, family)) = selected else {
+            instance.destroy_instance(None);
+            return Ok(None);
+        };
+
+        let queue_priorities = &[1.0];
+        let queue_infos = &[vk::DeviceQueueCreateInfo::builder()
+            .queue_family_index(family)
+            .queue_priorities(queue_priorities)];
+        let device_info = vk::DeviceCreateInfo::builder().queue_create_infos(queue_infos);
+        let device = instance.create_device(physical_device, &device_info, None)?;
+        let queue = device.get_device_queue(family, 0);
+        let pool_info = vk::CommandPoolCreateInfo::builder().queue_family_index(family);
+        let command_pool = device.create_command_pool(&pool_info, None)?;
+
+        let pipeline = ComputePipeline::create(
+            &device,
+            vk::PipelineCache::null(),
+            SEQUENCE_SHADER,
+            &[vk::DescriptorType::STORAGE_BUFFER],
+        )?;
+        let values = StorageBuffer::create(
+            &instance,
+            &device,
+            physical_device,
+            (COUNT as usize * size_of::<u32>()) as u64,
+        )?;
+        values.write(&device, &vec![0u32; COUNT as usize])?;
+
+        let mut descriptor_pool = descriptor::pool::create(
+            &device,
+            &[PoolDescription {
+                type_: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+            }],
+            1,
+        )?;
+        let descriptor_set =
+            descriptor_pool.create(&device, pipeline.get_descriptor_set_layout(), 1)?[0];
+        let mut descriptor_writer = DescriptorWriter::default();
+        descriptor_writer.queue_write_storage_buffer(&device, &values, &[descriptor_set], 0);
+        descriptor_writer.write(&device);
+
+        let command_buffer = begin_single_time_commands(&device, command_pool)?;
+        let group_count = get_group_count(COUNT, SEQUENCE_GROUP_SIZE);
+        pipeline.dispatch(&device, command_buffer, descriptor_set, [group_count, 1, 1]);
+        ComputeBarrier::Host.record(&device, command_buffer);
+        end_single_time_commands(&device, queue, command_pool, command_buffer)?;
+
+        let read_back = values.read::<u32>(&device)?;
+
+        values.destroy(&device);
+        descriptor::pool::destroy(&device, &descriptor_pool);
+        pipeline.destroy(&device);
+        device.destroy_command_pool(command_pool, None);
+        device.destroy_device(None);
+        instance.destroy_instance(None);
+
+        Ok(Some(read_back))
+    }
+
+    #[test]
+    fn dispatch_writes_every_value_of_a_storage_buffer() {
+        if SEQUENCE_SHADER.is_empty() {
+            eprintln!("skipped: shaders/sequence.comp is not compiled");
+            return;
+        }
+        let Some(values) = unsafe { dispatch_sequence_and_read_back() }.unwrap() else {
+            eprintln!("skipped: no Vulkan device can dispatch compute shaders");
+            return;
+        };
+        let expected = (0..COUNT).map(|index| index * 3 + 1).collect::<Vec<_>>();
+        assert_eq!(values, expected);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::core::config::MAX_FRAMES_IN_FLIGHT;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use vulkanalia::prelude::v1_0::*;
+
+/// Device objects that can be destroyed on their own, once nothing uses them.
+pub trait DeviceResource: Send + Sync + 'static {
+    unsafe fn destroy(&self, device: &Device);
+}
+
+struct PendingDeletion {
+    frame: u64,
+    resource: Box<dyn DeviceResource>,
+}
+
+#[derive(Default)]
+struct Queue {
+    pending: Vec<PendingDeletion>,
+    frame: u64,
+    // set once the device is gone, later drops can only leak
+    closed: bool,
+}
+
+/// Where dropped [`Owned`] handles leave their resources. They are destroyed once
+/// every frame that could still be using them has finished.
+#[derive(Clone, Default)]
+pub struct DeletionQueue {
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl DeletionQueue {
+    /// Hand `resource` to a handle that queues it for destruction when dropped.
+    pub fn own<T: DeviceResource>(&self, resource: T) -> Owned<T> {
+        Owned {
+            resource: ManuallyDrop::new(resource),
+            queue: self.clone(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        // A panic elsewhere must not stop resources from being released
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn push(&self, resource: Box<dyn DeviceResource>) {
+        let mut queue = self.lock();
+        if queue.closed {
+            log::warn!("Device resource dropped after the graphics were destroyed");
+            return;
+        }
+
+        let frame = queue.frame;
+        queue.pending.push(PendingDeletion { frame, resource });
+    }
+
+    /// Call once per submitted frame. Destroys what was dropped at least
+    /// `MAX_FRAMES_IN_FLIGHT` frames ago.
+    pub unsafe fn advance_frame(&self, device: &Device) {
+        let ready: Vec<PendingDeletion> = {
+            let mut queue = self.lock();
+            queue.frame += 1;
+
+            let frame = queue.frame;
+            let (ready, pending) = queue
+                .pending
+                .drain(..)
+                .partition(|deletion| frame >= deletion.frame + MAX_FRAMES_IN_FLIGHT as u64);
+            queue.pending = pending;
+            ready
+        };
+
+        for deletion in ready {
+            deletion.resource.destroy(device);
+        }
+    }
+
+    /// Destroy everything still queued and refuse anything dropped afterwards.
+    /// The device must be idle.
+    pub unsafe fn close(&self, device: &Device) {
+        let mut destroyed = 0;
+        loop {
+            // Resources holding handles of their own queue those as they are dropped
+            let pending = std::mem::take(&mut self.lock().pending);
+            if pending.is_empty() {
+                break;
+            }
+
+            destroyed += pending.len();
+            for deletion in pending {
+                deletion.resource.destroy(device);
+            }
+        }
+        self.lock().closed = true;
+
+        log::info!("Destroyed {} remaining device resources", destroyed);
+    }
+}
+
+/// Sole owner of a device resource created through [`Graphics`](super::Graphics).
+/// Dropping it queues the resource for destruction instead of destroying it right
+/// away, since a frame in flight may still be using it.
+pub struct Owned<T: DeviceResource> {
+    resource: ManuallyDrop<T>,
+    queue: DeletionQueue,
+}
+
+impl<T: DeviceResource> Deref for Owned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.resource
+    }
+}
+
+impl<T: DeviceResource> Drop for Owned<T> {
+    fn drop(&mut self) {
+        // Safe as the resource is never touched again after this
+        let resource = unsafe { ManuallyDrop::take(&mut self.resource) };
+        self.queue.push(Box::new(resource));
+    }
+}
@@ -80,6 +80,21 @@ pub mod pool {
             Ok(descriptor_sets)
         }
 
+        pub fn get_num_allocated(&self) -> usize {
+            self.currently_allocated
+        }
+
+        /// Return individual sets to the pool. They must not be in use by the GPU anymore.
+        pub unsafe fn release(
+            &mut self,
+            device: &Device,
+            descriptor_sets: &[vk::DescriptorSet],
+        ) -> Result<()> {
+            device.free_descriptor_sets(self.pool, descriptor_sets)?;
+            self.currently_allocated -= descriptor_sets.len();
+            Ok(())
+        }
+
         pub unsafe fn free(&mut self, device: &Device) -> Result<()> {
             device.reset_descriptor_pool(self.pool, vk::DescriptorPoolResetFlags::empty())?;
             self.currently_allocated = 0;
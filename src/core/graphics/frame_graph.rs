@@ -0,0 +1,823 @@
+use anyhow::{anyhow, Result};
+use std::fmt;
+use vulkanalia::prelude::v1_0::*;
+
+use super::deletion_queue::{DeletionQueue, DeviceResource, Owned};
+use super::framebuffer::{create_framebuffer, destroy_framebuffers};
+use super::wrappers::{get_depth_format, DepthBuffer, LoadedImage};
+
+/// An image passes of a [`FrameGraph`] draw into or sample from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PassId(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AttachmentFormat {
+    /// Whatever the surface picked, so the same pipelines draw into it and the swapchain
+    Swapchain,
+    /// The best depth format the device supports
+    Depth,
+    Fixed(vk::Format),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AttachmentSize {
+    Swapchain,
+    /// A fraction of the swapchain size, half resolution effects for example
+    SwapchainScaled(f32),
+    Fixed(vk::Extent2D),
+}
+
+impl AttachmentSize {
+    fn resolve(&self, swapchain_extent: vk::Extent2D) -> vk::Extent2D {
+        let scale = |size: u32, factor: f32| ((size as f32 * factor).round() as u32).max(1);
+        match *self {
+            AttachmentSize::Swapchain => swapchain_extent,
+            AttachmentSize::SwapchainScaled(factor) => vk::Extent2D {
+                width: scale(swapchain_extent.width, factor),
+                height: scale(swapchain_extent.height, factor),
+            },
+            AttachmentSize::Fixed(extent) => extent,
+        }
+    }
+}
+
+enum ResourceKind {
+    /// Owned by the swapchain, one image per swapchain image
+    Swapchain,
+    /// Owned by the graph, which creates it and creates it again whenever its size changes
+    Transient {
+        format: AttachmentFormat,
+        size: AttachmentSize,
+    },
+    /// Made and kept alive elsewhere, a render target for example. The graph only
+    /// draws into it
+    Imported {
+        format: AttachmentFormat,
+        extent: vk::Extent2D,
+        image_view: vk::ImageView,
+    },
+}
+
+struct ResourceDescription {
+    name: String,
+    kind: ResourceKind,
+}
+
+/// One render pass, declaring the attachments it draws into and the images earlier
+/// passes drew that it samples.
+pub struct PassDescription {
+    name: String,
+    color_attachments: Vec<ResourceId>,
+    depth_attachment: Option<ResourceId>,
+    sampled: Vec<ResourceId>,
+}
+
+impl PassDescription {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            color_attachments: vec![],
+            depth_attachment: None,
+            sampled: vec![],
+        }
+    }
+
+    pub fn with_color_attachment(mut self, resource: ResourceId) -> Self {
+        self.color_attachments.push(resource);
+        self
+    }
+
+    pub fn with_depth_attachment(mut self, resource: ResourceId) -> Self {
+        self.depth_attachment = Some(resource);
+        self
+    }
+
+    /// Read `resource` in the fragment shader, after the passes before this one
+    /// are done drawing into it.
+    pub fn with_sampled(mut self, resource: ResourceId) -> Self {
+        self.sampled.push(resource);
+        self
+    }
+
+    fn get_attachments(&self) -> impl Iterator<Item = ResourceId> + '_ {
+        self.color_attachments
+            .iter()
+            .chain(self.depth_attachment.iter())
+            .copied()
+    }
+
+    fn touches(&self, resource: ResourceId) -> bool {
+        self.get_attachments().any(|attachment| attachment == resource)
+            || self.sampled.contains(&resource)
+    }
+}
+
+/// Passes in the order they are recorded each frame, and the images they use.
+pub struct FrameGraphBuilder {
+    resources: Vec<ResourceDescription>,
+    passes: Vec<PassDescription>,
+}
+
+impl Default for FrameGraphBuilder {
+    fn default() -> Self {
+        Self {
+            resources: vec![ResourceDescription {
+                name: "swapchain".to_owned(),
+                kind: ResourceKind::Swapchain,
+            }],
+            passes: vec![],
+        }
+    }
+}
+
+impl FrameGraphBuilder {
+    /// The image being presented this frame.
+    pub const SWAPCHAIN: ResourceId = ResourceId(0);
+
+    pub fn add_transient(
+        &mut self,
+        name: &str,
+        format: AttachmentFormat,
+        size: AttachmentSize,
+    ) -> ResourceId {
+        self.resources.push(ResourceDescription {
+            name: name.to_owned(),
+            kind: ResourceKind::Transient { format, size },
+        });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    /// An image the graph draws into without owning it. It must outlive the graph.
+    pub fn import(
+        &mut self,
+        name: &str,
+        format: AttachmentFormat,
+        extent: vk::Extent2D,
+        image_view: vk::ImageView,
+    ) -> ResourceId {
+        self.resources.push(ResourceDescription {
+            name: name.to_owned(),
+            kind: ResourceKind::Imported {
+                format,
+                extent,
+                image_view,
+            },
+        });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    pub fn add_pass(&mut self, pass: PassDescription) -> PassId {
+        self.passes.push(pass);
+        PassId(self.passes.len() - 1)
+    }
+}
+
+/// What the graph draws into, handed over again whenever the swapchain is recreated.
+pub struct FrameGraphTarget<'a> {
+    pub image_views: &'a [vk::ImageView],
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+}
+
+/// Dropped with the graph or when a resize replaces it, once no frame uses it.
+enum TransientImage {
+    Color(Owned<LoadedImage>),
+    Depth(Owned<DepthBuffer>),
+}
+
+impl TransientImage {
+    fn get_image_view(&self) -> vk::ImageView {
+        match self {
+            TransientImage::Color(image) => image.get_image_view(),
+            TransientImage::Depth(image) => image.get_image_view(),
+        }
+    }
+}
+
+struct CompiledResource {
+    description: ResourceDescription,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    image: Option<TransientImage>,
+}
+
+/// How a pass finds an attachment and leaves it, worked out from its neighbours.
+#[derive(Copy, Clone, Debug)]
+struct AttachmentUsage {
+    resource: ResourceId,
+    load_op: vk::AttachmentLoadOp,
+    store_op: vk::AttachmentStoreOp,
+    initial_layout: vk::ImageLayout,
+    final_layout: vk::ImageLayout,
+}
+
+struct CompiledPass {
+    description: PassDescription,
+    /// Color attachments first, then depth, the order of the framebuffer attachments
+    attachments: Vec<AttachmentUsage>,
+    render_pass: vk::RenderPass,
+    /// One per swapchain image when the pass draws into the swapchain, otherwise one
+    framebuffers: Vec<vk::Framebuffer>,
+    extent: vk::Extent2D,
+}
+
+/// Render passes, their transient attachments and the layout transitions and barriers
+/// between them, built from the images each pass declares it uses.
+#[derive(Default)]
+pub struct FrameGraph {
+    resources: Vec<CompiledResource>,
+    passes: Vec<CompiledPass>,
+}
+
+impl FrameGraph {
+    pub unsafe fn compile(
+        builder: FrameGraphBuilder,
+        instance: &Instance,
+        device: &Device,
+        physical_device: vk::PhysicalDevice,
+        graphics_queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        deletion_queue: &DeletionQueue,
+        target: &FrameGraphTarget,
+    ) -> Result<Self> {
+        let FrameGraphBuilder { resources, passes } = builder;
+        let depth_format = get_depth_format(instance, physical_device)?;
+
+        let mut graph = Self {
+            resources: resources
+                .into_iter()
+                .map(|description| CompiledResource {
+                    format: resolve_format(&description, target.format, depth_format),
+                    extent: resolve_extent(&description, target.extent),
+                    description,
+                    image: None,
+                })
+                .collect(),
+            passes: vec![],
+        };
+
+        let attachments = graph.plan_attachments(&passes)?;
+        for (description, attachments) in passes.into_iter().zip(attachments) {
+            graph.passes.push(CompiledPass {
+                description,
+                attachments,
+                render_pass: vk::RenderPass::null(),
+                framebuffers: vec![],
+                extent: vk::Extent2D::default(),
+            });
+        }
+        for index in 0..graph.passes.len() {
+            graph.passes[index].extent = graph.get_pass_extent(index)?;
+            graph.passes[index].render_pass = graph.create_render_pass(device, index)?;
+        }
+
+        graph.create_size_dependent(
+            instance,
+            device,
+            physical_device,
+            graphics_queue,
+            command_pool,
+            deletion_queue,
+            target,
+        )?;
+        Ok(graph)
+    }
+
+    /// Follow a new swapchain. Transient images whose size changed are created again,
+    /// along with the framebuffers using them. Returns whether the render passes were
+    /// rebuilt too because the swapchain format changed, which invalidates every
+    /// pipeline created against them.
+    pub unsafe fn resize(
+        &mut self,
+        instance: &Instance,
+        device: &Device,
+        physical_device: vk::PhysicalDevice,
+        graphics_queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        deletion_queue: &DeletionQueue,
+        target: &FrameGraphTarget,
+    ) -> Result<bool> {
+        let depth_format = get_depth_format(instance, physical_device)?;
+        let mut formats_changed = false;
+        for resource in &mut self.resources {
+            let format = resolve_format(&resource.description, target.format, depth_format);
+            let extent = resolve_extent(&resource.description, target.extent);
+            if format != resource.format || extent != resource.extent {
+                formats_changed |= format != resource.format;
+                resource.format = format;
+                resource.extent = extent;
+                resource.image = None;
+            }
+        }
+
+        if formats_changed {
+            for index in 0..self.passes.len() {
+                device.destroy_render_pass(self.passes[index].render_pass, None);
+                self.passes[index].render_pass = self.create_render_pass(device, index)?;
+            }
+        }
+        for index in 0..self.passes.len() {
+            self.passes[index].extent = self.get_pass_extent(index)?;
+        }
+
+        self.create_size_dependent(
+            instance,
+            device,
+            physical_device,
+            graphics_queue,
+            command_pool,
+            deletion_queue,
+            target,
+        )?;
+        Ok(formats_changed)
+    }
+
+    pub fn get_render_pass(&self, pass: PassId) -> vk::RenderPass {
+        self.passes[pass.0].render_pass
+    }
+
+    pub fn get_extent(&self, pass: PassId) -> vk::Extent2D {
+        self.passes[pass.0].extent
+    }
+
+    /// The view of a transient image, for binding it to the passes that sample it.
+    /// A new view replaces it whenever the image is resized.
+    pub fn get_image_view(&self, resource: ResourceId) -> Option<vk::ImageView> {
+        self.resources[resource.0]
+            .image
+            .as_ref()
+            .map(TransientImage::get_image_view)
+    }
+
+    /// Begin `pass`, drawing into the swapchain image at `swapchain_index` if it uses
+    /// the swapchain. Every attachment it is the first to touch this frame is cleared.
+    pub unsafe fn begin_pass(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        pass: PassId,
+        swapchain_index: usize,
+    ) {
+        let pass = &self.passes[pass.0];
+        let framebuffer = pass.framebuffers[swapchain_index.min(pass.framebuffers.len() - 1)];
+
+        let clear_values = pass
+            .attachments
+            .iter()
+            .map(|usage| {
+                if self.is_depth(usage.resource) {
+                    vk::ClearValue {
+                        depth_stencil: vk::ClearDepthStencilValue {
+                            depth: 1.0,
+                            stencil: 0,
+                        },
+                    }
+                } else {
+                    vk::ClearValue {
+                        color: vk::ClearColorValue {
+                            float32: [0.0, 0.0, 0.0, 1.0],
+                        },
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        let render_area = vk::Rect2D::builder()
+            .offset(vk::Offset2D::default())
+            .extent(pass.extent);
+        let info = vk::RenderPassBeginInfo::builder()
+            .render_pass(pass.render_pass)
+            .framebuffer(framebuffer)
+            .render_area(render_area)
+            .clear_values(&clear_values);
+
+        device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+    }
+
+    fn is_depth(&self, resource: ResourceId) -> bool {
+        matches!(
+            self.resources[resource.0].description.kind,
+            ResourceKind::Transient {
+                format: AttachmentFormat::Depth,
+                ..
+            } | ResourceKind::Imported {
+                format: AttachmentFormat::Depth,
+                ..
+            }
+        )
+    }
+
+    /// The view a framebuffer of a pass draws into, for the given swapchain image.
+    fn get_attachment_view(
+        &self,
+        resource: ResourceId,
+        swapchain_index: usize,
+        target: &FrameGraphTarget,
+    ) -> vk::ImageView {
+        let resource = &self.resources[resource.0];
+        match (&resource.description.kind, &resource.image) {
+            (ResourceKind::Imported { image_view, .. }, _) => *image_view,
+            (_, Some(image)) => image.get_image_view(),
+            (_, None) => target.image_views[swapchain_index],
+        }
+    }
+
+    fn get_resource_name(&self, resource: ResourceId) -> &str {
+        &self.resources[resource.0].description.name
+    }
+
+    /// Walk the passes in order, giving every attachment the layout its next user
+    /// needs. Attachments are cleared by their first user in a frame, and only kept
+    /// past a pass when someone after it uses them.
+    fn plan_attachments(&self, passes: &[PassDescription]) -> Result<Vec<Vec<AttachmentUsage>>> {
+        let mut layouts: Vec<Option<vk::ImageLayout>> = vec![None; self.resources.len()];
+        let mut planned = vec![];
+
+        for (index, pass) in passes.iter().enumerate() {
+            for &resource in &pass.sampled {
+                if pass.get_attachments().any(|attachment| attachment == resource) {
+                    return Err(anyhow!(
+                        "Pass {:?} samples {:?} while drawing into it",
+                        pass.name,
+                        self.get_resource_name(resource)
+                    ));
+                }
+                if self.is_depth(resource) || resource == FrameGraphBuilder::SWAPCHAIN {
+                    return Err(anyhow!(
+                        "Pass {:?} samples {:?}, which can only be drawn into",
+                        pass.name,
+                        self.get_resource_name(resource)
+                    ));
+                }
+                if layouts[resource.0] != Some(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) {
+                    return Err(anyhow!(
+                        "Pass {:?} samples {:?} before any pass draws into it",
+                        pass.name,
+                        self.get_resource_name(resource)
+                    ));
+                }
+            }
+
+            let mut attachments = vec![];
+            for resource in pass.get_attachments() {
+                if pass.depth_attachment == Some(resource) && !self.is_depth(resource) {
+                    return Err(anyhow!(
+                        "Pass {:?} uses {:?} as depth, it is not in the depth format",
+                        pass.name,
+                        self.get_resource_name(resource)
+                    ));
+                }
+
+                let attachment_layout = self.get_attachment_layout(resource);
+                let next_use = passes[index + 1..].iter().find(|later| later.touches(resource));
+                let is_swapchain = resource == FrameGraphBuilder::SWAPCHAIN;
+                let final_layout = match next_use {
+                    Some(later) if later.sampled.contains(&resource) => {
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                    }
+                    Some(_) => attachment_layout,
+                    None if is_swapchain => vk::ImageLayout::PRESENT_SRC_KHR,
+                    None => attachment_layout,
+                };
+
+                let previous_layout = layouts[resource.0];
+                attachments.push(AttachmentUsage {
+                    resource,
+                    load_op: match previous_layout {
+                        Some(_) => vk::AttachmentLoadOp::LOAD,
+                        None => vk::AttachmentLoadOp::CLEAR,
+                    },
+                    store_op: if next_use.is_some() || is_swapchain {
+                        vk::AttachmentStoreOp::STORE
+                    } else {
+                        vk::AttachmentStoreOp::DONT_CARE
+                    },
+                    initial_layout: previous_layout.unwrap_or(vk::ImageLayout::UNDEFINED),
+                    final_layout,
+                });
+                layouts[resource.0] = Some(final_layout);
+            }
+            planned.push(attachments);
+        }
+
+        Ok(planned)
+    }
+
+    fn get_attachment_layout(&self, resource: ResourceId) -> vk::ImageLayout {
+        if self.is_depth(resource) {
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        } else {
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        }
+    }
+
+    fn get_pass_extent(&self, index: usize) -> Result<vk::Extent2D> {
+        let pass = &self.passes[index];
+        let mut extents = pass
+            .description
+            .get_attachments()
+            .map(|resource| self.resources[resource.0].extent);
+        let extent = extents
+            .next()
+            .ok_or_else(|| anyhow!("Pass {:?} draws into nothing", pass.description.name))?;
+        if extents.any(|other| other != extent) {
+            return Err(anyhow!(
+                "Attachments of pass {:?} differ in size",
+                pass.description.name
+            ));
+        }
+        Ok(extent)
+    }
+
+    /// The layouts of the attachments are the transitions, the dependencies with the
+    /// passes around it are the barriers.
+    unsafe fn create_render_pass(&self, device: &Device, index: usize) -> Result<vk::RenderPass> {
+        let pass = &self.passes[index];
+
+        let descriptions = pass
+            .attachments
+            .iter()
+            .map(|usage| {
+                vk::AttachmentDescription::builder()
+                    .format(self.resources[usage.resource.0].format)
+                    .samples(vk::SampleCountFlags::_1)
+                    .load_op(usage.load_op)
+                    .store_op(usage.store_op)
+                    .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(usage.initial_layout)
+                    .final_layout(usage.final_layout)
+            })
+            .collect::<Vec<_>>();
+
+        let color_references = (0..pass.description.color_attachments.len())
+            .map(|attachment| {
+                vk::AttachmentReference::builder()
+                    .attachment(attachment as u32)
+                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            })
+            .collect::<Vec<_>>();
+        let depth_reference = vk::AttachmentReference::builder()
+            .attachment(pass.description.color_attachments.len() as u32)
+            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+        let mut subpass = vk::SubpassDescription::builder()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_references);
+        if pass.description.depth_attachment.is_some() {
+            subpass = subpass.depth_stencil_attachment(&depth_reference);
+        }
+
+        let dependencies = self.get_dependencies(index);
+        let subpasses = &[subpass];
+        let info = vk::RenderPassCreateInfo::builder()
+            .attachments(&descriptions)
+            .subpasses(subpasses)
+            .dependencies(&dependencies);
+
+        Ok(device.create_render_pass(&info, None)?)
+    }
+
+    /// Wait for whoever last drew into or sampled the attachments, in this frame or
+    /// the one before, and make what this pass draws visible to later passes
+    /// sampling it.
+    fn get_dependencies(&self, index: usize) -> Vec<vk::SubpassDependency> {
+        let pass = &self.passes[index];
+        let attachment_stages = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+        let attachment_writes = vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+
+        let is_sampled_anywhere = pass.description.get_attachments().any(|resource| {
+            self.passes
+                .iter()
+                .any(|other| other.description.sampled.contains(&resource))
+        });
+        let is_loaded = pass
+            .attachments
+            .iter()
+            .any(|usage| usage.load_op == vk::AttachmentLoadOp::LOAD);
+        let samples = !pass.description.sampled.is_empty();
+
+        let mut src_stage = attachment_stages;
+        let mut src_access = vk::AccessFlags::empty();
+        if is_sampled_anywhere {
+            src_stage |= vk::PipelineStageFlags::FRAGMENT_SHADER;
+        }
+        if is_loaded || samples {
+            src_access |= attachment_writes;
+        }
+
+        let mut dst_stage = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS;
+        let mut dst_access = attachment_writes;
+        if is_loaded {
+            dst_access |= vk::AccessFlags::COLOR_ATTACHMENT_READ
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ;
+        }
+        if samples {
+            dst_stage |= vk::PipelineStageFlags::FRAGMENT_SHADER;
+            dst_access |= vk::AccessFlags::SHADER_READ;
+        }
+
+        let mut dependencies = vec![vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(src_stage)
+            .src_access_mask(src_access)
+            .dst_stage_mask(dst_stage)
+            .dst_access_mask(dst_access)
+            .build()];
+
+        let is_sampled_later = pass
+            .attachments
+            .iter()
+            .any(|usage| usage.final_layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        if is_sampled_later {
+            dependencies.push(
+                vk::SubpassDependency::builder()
+                    .src_subpass(0)
+                    .dst_subpass(vk::SUBPASS_EXTERNAL)
+                    .src_stage_mask(attachment_stages)
+                    .src_access_mask(attachment_writes)
+                    .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                    .build(),
+            );
+        }
+
+        dependencies
+    }
+
+    /// Create the transient images that are missing, and the framebuffers of the
+    /// passes using them or the swapchain, which are new on every resize.
+    unsafe fn create_size_dependent(
+        &mut self,
+        instance: &Instance,
+        device: &Device,
+        physical_device: vk::PhysicalDevice,
+        graphics_queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        deletion_queue: &DeletionQueue,
+        target: &FrameGraphTarget,
+    ) -> Result<()> {
+        // The swapchain images are new every time, imported images never change
+        let mut recreated = vec![false; self.resources.len()];
+        recreated[FrameGraphBuilder::SWAPCHAIN.0] = true;
+        for (index, resource) in self.resources.iter_mut().enumerate() {
+            let ResourceKind::Transient { format, .. } = resource.description.kind else {
+                continue;
+            };
+            if resource.image.is_some() {
+                continue;
+            }
+            let image = if format == AttachmentFormat::Depth {
+                TransientImage::Depth(deletion_queue.own(DepthBuffer::new(
+                    instance,
+                    device,
+                    physical_device,
+                    resource.extent,
+                    graphics_queue,
+                    command_pool,
+                )?))
+            } else {
+                TransientImage::Color(deletion_queue.own(LoadedImage::create_render_target(
+                    instance,
+                    device,
+                    physical_device,
+                    graphics_queue,
+                    command_pool,
+                    resource.format,
+                    resource.extent,
+                )?))
+            };
+            resource.image = Some(image);
+            recreated[index] = true;
+        }
+
+        for index in 0..self.passes.len() {
+            let pass = &self.passes[index];
+            let uses_recreated = pass
+                .description
+                .get_attachments()
+                .any(|resource| recreated[resource.0]);
+            if !pass.framebuffers.is_empty() && !uses_recreated {
+                continue;
+            }
+            destroy_framebuffers(device, &pass.framebuffers);
+
+            let uses_swapchain = pass
+                .description
+                .get_attachments()
+                .any(|resource| resource == FrameGraphBuilder::SWAPCHAIN);
+            let framebuffer_count = if uses_swapchain { target.image_views.len() } else { 1 };
+            let framebuffers = (0..framebuffer_count)
+                .map(|swapchain_index| {
+                    let attachments = pass
+                        .attachments
+                        .iter()
+                        .map(|usage| {
+                            self.get_attachment_view(usage.resource, swapchain_index, target)
+                        })
+                        .collect::<Vec<_>>();
+                    create_framebuffer(device, pass.render_pass, &attachments, pass.extent)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            self.passes[index].framebuffers = framebuffers;
+        }
+
+        Ok(())
+    }
+}
+
+impl DeviceResource for FrameGraph {
+    // Transient images queue themselves as the graph is dropped, imported images are
+    // left to whoever made them
+    unsafe fn destroy(&self, device: &Device) {
+        for pass in &self.passes {
+            destroy_framebuffers(device, &pass.framebuffers);
+            device.destroy_render_pass(pass.render_pass, None);
+        }
+    }
+}
+
+fn resolve_format(
+    description: &ResourceDescription,
+    swapchain_format: vk::Format,
+    depth_format: vk::Format,
+) -> vk::Format {
+    let format = match description.kind {
+        ResourceKind::Swapchain => return swapchain_format,
+        ResourceKind::Transient { format, .. } | ResourceKind::Imported { format, .. } => format,
+    };
+    match format {
+        AttachmentFormat::Swapchain => swapchain_format,
+        AttachmentFormat::Depth => depth_format,
+        AttachmentFormat::Fixed(format) => format,
+    }
+}
+
+fn resolve_extent(description: &ResourceDescription, swapchain_extent: vk::Extent2D) -> vk::Extent2D {
+    match &description.kind {
+        ResourceKind::Swapchain => swapchain_extent,
+        ResourceKind::Transient { size, .. } => size.resolve(swapchain_extent),
+        ResourceKind::Imported { extent, .. } => *extent,
+    }
+}
+
+/// Every resource and pass with the layouts they go through, to check what the
+/// graph made of its description.
+impl fmt::Display for FrameGraph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Frame graph of {} resources and {} passes",
+            self.resources.len(),
+            self.passes.len()
+        )?;
+        for resource in &self.resources {
+            let origin = match resource.description.kind {
+                ResourceKind::Swapchain => "swapchain".to_owned(),
+                ResourceKind::Transient { size, .. } => format!("transient, sized {:?}", size),
+                ResourceKind::Imported { .. } => "imported".to_owned(),
+            };
+            writeln!(
+                f,
+                "  resource {:?}: {:?} {}x{}, {}",
+                resource.description.name,
+                resource.format,
+                resource.extent.width,
+                resource.extent.height,
+                origin
+            )?;
+        }
+        for pass in &self.passes {
+            writeln!(
+                f,
+                "  pass {:?}: {}x{}, {} framebuffers",
+                pass.description.name,
+                pass.extent.width,
+                pass.extent.height,
+                pass.framebuffers.len()
+            )?;
+            for usage in &pass.attachments {
+                writeln!(
+                    f,
+                    "    draws into {:?}: {:?} -> {:?}, {:?} then {:?}",
+                    self.get_resource_name(usage.resource),
+                    usage.initial_layout,
+                    usage.final_layout,
+                    usage.load_op,
+                    usage.store_op
+                )?;
+            }
+            for &resource in &pass.description.sampled {
+                writeln!(f, "    samples {:?}", self.get_resource_name(resource))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+use vulkanalia::prelude::v1_0::*;
+
+use super::compute::{ComputeBarrier, ComputePipeline};
+use super::graphics::{GPUMesh, Graphics};
+use super::pipeline::BlendMode;
+use super::render_target::{RenderTarget, ViewportRect};
+use super::wrappers::ParticleBufferSeries;
+
+/// The command buffer of one swapchain image while it is being recorded, see
+/// [`Graphics::record_command_buffers`]. Only handed out between beginning and
+/// ending the command buffer, so everything recorded through it is valid to record.
+pub struct FrameRecorder<'a> {
+    graphics: &'a Graphics,
+    command_buffer: vk::CommandBuffer,
+    index: usize,
+}
+
+impl<'a> FrameRecorder<'a> {
+    pub(super) fn new(graphics: &'a Graphics, command_buffer: vk::CommandBuffer, index: usize) -> Self {
+        Self {
+            graphics,
+            command_buffer,
+            index,
+        }
+    }
+
+    /// The swapchain image this command buffer presents, which also picks the
+    /// per-image descriptor sets and buffers to use.
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+
+    /// Begin drawing into the swapchain image, with the opaque pipeline bound.
+    pub fn begin_swapchain_render_pass(&mut self) {
+        unsafe {
+            self.graphics
+                .begin_swapchain_render_pass(self.command_buffer, self.index);
+        }
+    }
+
+    /// Begin drawing into an offscreen target, with the opaque pipeline bound. Returns
+    /// false without recording anything when the target is not part of the frame
+    /// graph, see [`Graphics::update_render_targets`].
+    pub fn begin_target_render_pass(&mut self, target: &RenderTarget) -> bool {
+        unsafe {
+            self.graphics
+                .begin_target_render_pass(self.command_buffer, target)
+        }
+    }
+
+    pub fn end_render_pass(&mut self) {
+        unsafe { self.graphics.end_render_pass(self.command_buffer) }
+    }
+
+    /// Restrict subsequent draws to part of the target being rendered, the swapchain
+    /// when `target` is `None`.
+    pub fn set_viewport(&mut self, target: Option<&RenderTarget>, rect: &ViewportRect) {
+        unsafe { self.graphics.set_viewport(self.command_buffer, target, rect) }
+    }
+
+    /// Reset depth within part of the target being rendered.
+    pub fn clear_depth(&mut self, target: Option<&RenderTarget>, rect: &ViewportRect) {
+        unsafe { self.graphics.clear_depth(self.command_buffer, target, rect) }
+    }
+
+    pub fn bind_pipeline(&mut self, blend_mode: BlendMode) {
+        unsafe { self.graphics.bind_pipeline(self.command_buffer, blend_mode) }
+    }
+
+    pub fn bind_descriptor_set(&mut self, descriptor_sets: &[vk::DescriptorSet], first_set: u32) {
+        unsafe {
+            self.graphics
+                .bind_descriptor_set(self.command_buffer, descriptor_sets, first_set)
+        }
+    }
+
+    /// Draw a mesh with whatever pipeline and descriptor sets are bound.
+    pub fn draw_mesh(&mut self, mesh: &GPUMesh) {
+        unsafe {
+            mesh.bind(self.graphics, self.command_buffer);
+            mesh.draw(self.graphics, self.command_buffer);
+        }
+    }
+
+    /// Draw the skybox cube with the camera of `global_descriptor_set`. Leaves the
+    /// skybox pipeline bound, so rebind a mesh pipeline before drawing meshes again.
+    pub fn draw_skybox(
+        &mut self,
+        global_descriptor_set: vk::DescriptorSet,
+        skybox_descriptor_set: vk::DescriptorSet,
+        cube: &GPUMesh,
+    ) {
+        unsafe {
+            self.graphics.draw_skybox(
+                self.command_buffer,
+                global_descriptor_set,
+                skybox_descriptor_set,
+                cube,
+            )
+        }
+    }
+
+    /// Draw the particles last written to `particles` for this swapchain image. Leaves
+    /// the particle pipeline bound.
+    pub fn draw_particles(
+        &mut self,
+        global_descriptor_set: vk::DescriptorSet,
+        particle_descriptor_set: vk::DescriptorSet,
+        particles: &ParticleBufferSeries,
+    ) {
+        unsafe {
+            self.graphics.draw_particles(
+                self.command_buffer,
+                self.index,
+                global_descriptor_set,
+                particle_descriptor_set,
+                particles,
+            )
+        }
+    }
+
+    /// Only outside of render passes.
+    pub fn dispatch_compute(
+        &mut self,
+        pipeline: &ComputePipeline,
+        descriptor_set: vk::DescriptorSet,
+        group_counts: [u32; 3],
+    ) {
+        unsafe {
+            self.graphics.dispatch_compute(
+                self.command_buffer,
+                pipeline,
+                descriptor_set,
+                group_counts,
+            )
+        }
+    }
+
+    pub fn record_compute_barrier(&mut self, barrier: ComputeBarrier) {
+        unsafe {
+            self.graphics
+                .record_compute_barrier(self.command_buffer, barrier)
+        }
+    }
+}
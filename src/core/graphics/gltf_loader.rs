@@ -0,0 +1,315 @@
+use anyhow::{anyhow, Result};
+use cgmath::{Quaternion, Vector3, Vector4};
+use log::{info, warn};
+use std::path::Path;
+use vulkanalia::vk;
+
+use super::graphics::CPUMesh;
+use super::wrappers::{AlphaMode, Image, SamplerDescription, Vertex};
+
+/// Everything read from a glTF or GLB file: geometry, decoded textures,
+/// material factors and the node hierarchy of the default scene.
+pub struct GltfScene {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<GltfMaterial>,
+    /// Decoded images, indexed by [`GltfMaterial::base_color_texture`]
+    pub images: Vec<Image>,
+    pub nodes: Vec<GltfNode>,
+    /// Nodes without a parent in the default scene, indices into `nodes`
+    pub root_nodes: Vec<usize>,
+}
+
+pub struct GltfMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+pub struct GltfPrimitive {
+    pub cpu_mesh: CPUMesh,
+    /// Index into [`GltfScene::materials`], `None` means the glTF default material
+    pub material: Option<usize>,
+}
+
+pub struct GltfMaterial {
+    pub name: Option<String>,
+    pub base_color_factor: Vector4<f32>,
+    /// Index into [`GltfScene::images`]
+    pub base_color_texture: Option<usize>,
+    /// Filtering and wrapping of the base color texture
+    pub base_color_sampler: SamplerDescription,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub emissive_factor: Vector3<f32>,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+/// A node with its transform relative to its parent.
+pub struct GltfNode {
+    pub name: Option<String>,
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    /// Index into [`GltfScene::meshes`]
+    pub mesh: Option<usize>,
+    /// Indices into [`GltfScene::nodes`]
+    pub children: Vec<usize>,
+}
+
+impl GltfScene {
+    /// Load a `.gltf` (with external or embedded buffers) or a `.glb` file.
+    /// Referenced files are resolved relative to `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        info!("Loading glTF scene at {:?}", path);
+
+        let (document, buffers, images) = gltf::import(path)
+            .map_err(|e| anyhow!("Failed to load glTF file {}: {}", path.display(), e))?;
+
+        let images = images
+            .into_iter()
+            .map(convert_image)
+            .collect::<Result<Vec<Image>>>()?;
+
+        let materials = document.materials().map(convert_material).collect();
+
+        let mut meshes = vec![];
+        for mesh in document.meshes() {
+            let mut primitives = vec![];
+            for primitive in mesh.primitives() {
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    warn!(
+                        "Skipping primitive {} of mesh {:?} with unsupported mode {:?}",
+                        primitive.index(),
+                        mesh.name(),
+                        primitive.mode()
+                    );
+                    continue;
+                }
+
+                primitives.push(GltfPrimitive {
+                    cpu_mesh: convert_primitive(&primitive, &buffers)?,
+                    material: primitive.material().index(),
+                });
+            }
+
+            meshes.push(GltfMesh {
+                name: mesh.name().map(String::from),
+                primitives,
+            });
+        }
+
+        let nodes = document
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                let [x, y, z, w] = rotation;
+                GltfNode {
+                    name: node.name().map(String::from),
+                    translation: translation.into(),
+                    rotation: Quaternion::new(w, x, y, z),
+                    scale: scale.into(),
+                    mesh: node.mesh().map(|mesh| mesh.index()),
+                    children: node.children().map(|child| child.index()).collect(),
+                }
+            })
+            .collect();
+
+        let root_nodes = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .map(|scene| scene.nodes().map(|node| node.index()).collect())
+            .unwrap_or_default();
+
+        info!(
+            "Loaded glTF scene with {} meshes {} materials {} images {} nodes",
+            meshes.len(),
+            document.materials().len(),
+            images.len(),
+            document.nodes().len()
+        );
+
+        Ok(Self {
+            meshes,
+            materials,
+            images,
+            nodes,
+            root_nodes,
+        })
+    }
+
+    pub fn get_material(&self, primitive: &GltfPrimitive) -> Option<&GltfMaterial> {
+        primitive.material.and_then(|index| self.materials.get(index))
+    }
+
+    pub fn get_base_color_image(&self, material: &GltfMaterial) -> Option<&Image> {
+        material
+            .base_color_texture
+            .and_then(|index| self.images.get(index))
+    }
+}
+
+fn convert_primitive(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+) -> Result<CPUMesh> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+    let positions: Vec<[f32; 3]> = reader
+        .read_positions()
+        .ok_or_else(|| anyhow!("Primitive {} has no positions", primitive.index()))?
+        .collect();
+
+    if positions.len() > u16::MAX as usize + 1 {
+        return Err(anyhow!(
+            "Primitive {} has {} vertices, more than 16 bit indices can address",
+            primitive.index(),
+            positions.len()
+        ));
+    }
+
+    let uvs: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+        Some(uvs) => uvs.into_f32().collect(),
+        None => vec![[0.0, 0.0]; positions.len()],
+    };
+
+    // glTF texture coordinates already start at the top left like Vulkan's
+    let vertices = positions
+        .iter()
+        .zip(uvs.iter())
+        .map(|(position, uv)| Vertex::new((*position).into(), (*uv).into()))
+        .collect();
+
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().map(|index| index as u16).collect(),
+        None => (0..positions.len() as u16).collect(),
+    };
+
+    Ok(CPUMesh { vertices, indices })
+}
+
+fn convert_material(material: gltf::Material) -> GltfMaterial {
+    let pbr = material.pbr_metallic_roughness();
+
+    let alpha_mode = match material.alpha_mode() {
+        gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+        gltf::material::AlphaMode::Mask => AlphaMode::Cutout,
+        gltf::material::AlphaMode::Blend => AlphaMode::Translucent,
+    };
+
+    GltfMaterial {
+        name: material.name().map(String::from),
+        base_color_factor: pbr.base_color_factor().into(),
+        base_color_texture: pbr
+            .base_color_texture()
+            .map(|info| info.texture().source().index()),
+        base_color_sampler: pbr
+            .base_color_texture()
+            .map(|info| convert_sampler(info.texture().sampler()))
+            .unwrap_or_default(),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        emissive_factor: material.emissive_factor().into(),
+        alpha_mode,
+        alpha_cutoff: material
+            .alpha_cutoff()
+            .unwrap_or(alpha_mode.get_alpha_cutoff()),
+        double_sided: material.double_sided(),
+    }
+}
+
+/// Unset filters keep our nearest default. glTF has no third texture coordinate,
+/// so W repeats like it does by default.
+fn convert_sampler(sampler: gltf::texture::Sampler) -> SamplerDescription {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let convert_wrapping_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+    };
+
+    let mut description = SamplerDescription::default();
+    if let Some(mag_filter) = sampler.mag_filter() {
+        description.mag_filter = match mag_filter {
+            MagFilter::Nearest => vk::Filter::NEAREST,
+            MagFilter::Linear => vk::Filter::LINEAR,
+        };
+    }
+    if let Some(min_filter) = sampler.min_filter() {
+        let (min_filter, mipmap_mode, uses_mips) = match min_filter {
+            MinFilter::Nearest => (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST, false),
+            MinFilter::Linear => (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST, false),
+            MinFilter::NearestMipmapNearest => {
+                (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST, true)
+            }
+            MinFilter::LinearMipmapNearest => {
+                (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST, true)
+            }
+            MinFilter::NearestMipmapLinear => {
+                (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR, true)
+            }
+            MinFilter::LinearMipmapLinear => {
+                (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR, true)
+            }
+        };
+        description.min_filter = min_filter;
+        description.mipmap_mode = mipmap_mode;
+        if !uses_mips {
+            description.max_lod = 0.0;
+        }
+    }
+    description.address_mode_u = convert_wrapping_mode(sampler.wrap_s());
+    description.address_mode_v = convert_wrapping_mode(sampler.wrap_t());
+
+    description
+}
+
+/// Expand any of the formats glTF decodes to into 8 bit RGBA.
+fn convert_image(data: gltf::image::Data) -> Result<Image> {
+    use gltf::image::Format;
+
+    let (channels, bytes_per_channel) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    let to_u8 = |channel: &[u8]| -> u8 {
+        match bytes_per_channel {
+            1 => channel[0],
+            2 => (u16::from_ne_bytes([channel[0], channel[1]]) >> 8) as u8,
+            _ => {
+                let value = f32::from_ne_bytes([channel[0], channel[1], channel[2], channel[3]]);
+                (value.clamp(0.0, 1.0) * 255.0).round() as u8
+            }
+        }
+    };
+
+    let mut pixels = Vec::with_capacity(data.width as usize * data.height as usize * 4);
+    for pixel in data.pixels.chunks_exact(channels * bytes_per_channel) {
+        let mut channel_values = pixel.chunks_exact(bytes_per_channel).map(to_u8);
+        let rgba = match channels {
+            1 => {
+                let luminance = channel_values.next().unwrap_or(0);
+                [luminance, luminance, luminance, u8::MAX]
+            }
+            _ => [
+                channel_values.next().unwrap_or(0),
+                channel_values.next().unwrap_or(0),
+                channel_values.next().unwrap_or(0),
+                channel_values.next().unwrap_or(u8::MAX),
+            ],
+        };
+        pixels.extend_from_slice(&rgba);
+    }
+
+    Image::from_rgba(data.width, data.height, pixels)
+}
@@ -1,18 +1,31 @@
-use super::abstraction::descriptor_allocator::DescriptorAllocator;
+use super::abstraction::descriptor_allocator::{
+    DescriptorAllocator, DescriptorAllocatorStats, SharedDescriptorAllocator,
+};
 use super::abstraction::descriptor_writer::DescriptorWriter;
-use super::wrappers::{bind_sampler_to_descriptor_sets, DepthBuffer};
+use super::capture::PendingCapture;
+use super::command_buffers::{begin_single_time_commands, end_single_time_commands};
+use super::deletion_queue::{DeletionQueue, DeviceResource};
+use super::pipeline_cache::PipelineCache;
+use super::queue_families::QueueFamilyIndices;
+use super::upload::UploadContext;
+use super::frame_graph::{
+    AttachmentFormat, AttachmentSize, FrameGraph, FrameGraphBuilder, FrameGraphTarget,
+    PassDescription, PassId,
+};
+use super::wrappers::SamplerCache;
 use super::{
     command_buffers::{self, record_command_buffers},
-    descriptor, framebuffer, instance, logical_device, physical_device, pipeline,
+    descriptor, instance, logical_device, physical_device,
+    pipeline,
     swapchain::{self, Swapchain},
     sync_objects::GraphicsBarriers,
     validation_layers, window_surface,
     wrappers::{uniform_buffer, IndexBuffer, Vertex, VertexBuffer},
 };
-use crate::core::{config::MAX_FRAMES_IN_FLIGHT, graphics::renderpass};
+use crate::core::config::{MAX_FRAMES_IN_FLIGHT, STAGING_RING_SIZE};
 use anyhow::{anyhow, Result};
 use cgmath::{vec2, vec3};
-use log::{info, trace};
+use log::{error, info, trace, warn};
 use std::{fmt::Debug, path::Path, time::Instant};
 use tobj::{self};
 use vulkanalia::loader::{LibloadingLoader, LIBRARY};
@@ -26,9 +39,23 @@ type Vec3 = cgmath::Vector3<f32>;
 type Vec2 = cgmath::Vector2<f32>;
 type Index = u16;
 
-pub use super::wrappers::{Image, ImageSampler, LoadedImage};
+pub use super::capture::CapturedFrame;
+pub use super::compute::{get_group_count, ComputeBarrier, ComputePipeline, StorageBuffer};
+pub use super::abstraction::descriptor_allocator::DescriptorSets;
+pub use super::deletion_queue::Owned;
+pub use super::frame_recorder::FrameRecorder;
+pub use super::validation_layers::{ValidationCounts, ValidationMessage};
+pub use super::pipeline::BlendMode;
+pub use super::render_target::{RenderTarget, ViewportRect};
+pub use super::upload::UploadHandle;
+pub use super::wrappers::{
+    create_palette_image, AlphaMode, CubemapImage, Image, ImageSampler, LoadedImage,
+    Palette, ParticleBufferSeries, ParticleInstance, SamplerDescription, TextureAtlas,
+    TextureAtlasBuilder,
+};
 pub use uniform_buffer::UniformBufferSeries;
 
+#[derive(Clone)]
 pub struct CPUMesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<Index>,
@@ -48,20 +75,55 @@ impl CPUMesh {
             ],
         }
     }
+
+    /// A unit cube around the origin, only its positions matter to the skybox shader.
+    pub fn get_skybox_cube() -> Self {
+        let corners = [
+            (-1.0, -1.0, -1.0), (1.0, -1.0, -1.0), (-1.0, 1.0, -1.0), (1.0, 1.0, -1.0),
+            (-1.0, -1.0, 1.0), (1.0, -1.0, 1.0), (-1.0, 1.0, 1.0), (1.0, 1.0, 1.0),
+        ];
+        CPUMesh {
+            vertices: corners
+                .iter()
+                .map(|&(x, y, z)| Vertex::new(cgmath::vec3(x, y, z), cgmath::vec2(0.0, 0.0)))
+                .collect(),
+            indices: vec![
+                0, 1, 2, 2, 1, 3, // -Z
+                4, 6, 5, 5, 6, 7, // +Z
+                0, 2, 4, 4, 2, 6, // -X
+                1, 5, 3, 3, 5, 7, // +X
+                0, 4, 1, 1, 4, 5, // -Y
+                2, 3, 6, 6, 3, 7, // +Y
+            ],
+        }
+    }
 }
 
 pub struct GPUMesh {
     triangles_count: usize,
     vertex_buffer: VertexBuffer,
     index_buffer: IndexBuffer,
+    upload: UploadHandle,
+}
+
+impl DeviceResource for GPUMesh {
+    unsafe fn destroy(&self, device: &Device) {
+        VertexBuffer::destroy(self.vertex_buffer, device);
+        IndexBuffer::destroy(self.index_buffer, device);
+    }
 }
 
 impl GPUMesh {
-    pub unsafe fn bind(&self, graphics: &Graphics, command_buffer: vk::CommandBuffer) {
+    /// The batch the vertices and indices are copied in, see [`Graphics::is_upload_finished`].
+    pub fn get_upload(&self) -> UploadHandle {
+        self.upload
+    }
+
+    pub(super) unsafe fn bind(&self, graphics: &Graphics, command_buffer: vk::CommandBuffer) {
         self.bind_manual(graphics.get_device(), command_buffer);
     }
 
-    pub unsafe fn draw(&self, graphics: &Graphics, command_buffer: vk::CommandBuffer) {
+    pub(super) unsafe fn draw(&self, graphics: &Graphics, command_buffer: vk::CommandBuffer) {
         self.draw_manual(graphics.get_device(), command_buffer)
     }
 
@@ -106,28 +168,62 @@ pub struct Graphics {
     graphics_queue: vk::Queue,
     present_queue: vk::Queue,
     command_pool: vk::CommandPool,
+    // whether compute work can be recorded into the command buffers of frames
+    can_dispatch_in_frames: bool,
 
     graphics_barriers: GraphicsBarriers,
 
     pub mesh_descriptor_set_layout: vk::DescriptorSetLayout,
     pub global_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub skybox_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub particle_descriptor_set_layout: vk::DescriptorSetLayout,
 
-    pub global_descriptor_allocator: DescriptorAllocator,
-    pub mesh_descriptor_allocator: DescriptorAllocator,
+    global_descriptor_allocator: SharedDescriptorAllocator,
+    mesh_descriptor_allocator: SharedDescriptorAllocator,
+    compute_descriptor_allocator: SharedDescriptorAllocator,
     pub descriptor_writer: DescriptorWriter,
-    pub global_descriptor_sets: Vec<vk::DescriptorSet>,
 
     // on swapchain
     pub swapchain: Swapchain,
-    depth_buffer: DepthBuffer,
-
-    pipeline: vk::Pipeline,
-    render_pass: vk::RenderPass,
+    // every render pass of a frame and the attachments they draw into
+    frame_graph: FrameGraph,
+    main_pass: PassId,
+    // each drawn into by its own pass, before the main pass samples them
+    render_targets: Vec<(RenderTarget, PassId)>,
+
+    opaque_pipeline: vk::Pipeline,
+    translucent_pipeline: vk::Pipeline,
+    decal_pipeline: vk::Pipeline,
+    skybox_pipeline: vk::Pipeline,
+    particle_pipeline: vk::Pipeline,
+    pipeline_cache: PipelineCache,
     pipeline_layout: vk::PipelineLayout,
-    framebuffers: Vec<vk::Framebuffer>,
+    skybox_pipeline_layout: vk::PipelineLayout,
+    particle_pipeline_layout: vk::PipelineLayout,
+
+    // bound to materials that have no environment of their own
+    default_cubemap: LoadedImage,
+    cubemap_sampler: ImageSampler,
+    default_palette: LoadedImage,
+    palette_sampler: ImageSampler,
+
+    // shared by every texture with the same sampler description
+    sampler_cache: SamplerCache,
+
+    // where dropped resource handles wait for the frames using them to finish
+    deletion_queue: DeletionQueue,
+    // stages and batches copies into device local buffers and images
+    upload_context: UploadContext,
 
     // on mesh change
     command_buffers: Vec<vk::CommandBuffer>,
+    // between start_render and end_render, when its command buffer is not pending
+    acquired_image: Option<usize>,
+
+    // frame capture, copied along with each frame in flight
+    capture_requested: bool,
+    pending_captures: Vec<Option<PendingCapture>>,
+    captured_frame: Option<CapturedFrame>,
 
     start: Instant,
 }
@@ -143,35 +239,64 @@ impl Graphics {
             unsafe { window_surface::create_window_surface(&instance, window)? };
         let physical_device: vk::PhysicalDevice =
             unsafe { physical_device::pick_physical_device(&instance, surface) }?;
-        let (device, graphics_queue, present_queue) = unsafe {
+        unsafe { physical_device::log_capability_report(&instance, surface, physical_device)? };
+        let (device, graphics_queue, present_queue, transfer_queue) = unsafe {
             logical_device::create_logical_device(&entry, &instance, surface, physical_device)?
         };
+        let indices = unsafe { QueueFamilyIndices::get(&instance, surface, physical_device)? };
+        let can_dispatch_in_frames = indices.compute == Some(indices.graphics);
+        if !can_dispatch_in_frames {
+            warn!("The graphics queue can't dispatch compute shaders, frames can't either");
+        }
+        let upload_context = unsafe {
+            UploadContext::create(
+                &instance,
+                &device,
+                physical_device,
+                graphics_queue,
+                indices.graphics,
+                transfer_queue,
+                STAGING_RING_SIZE,
+            )?
+        };
+        info!(
+            "Uploading through the {} queue",
+            if upload_context.has_dedicated_transfer_queue() {
+                "dedicated transfer"
+            } else {
+                "graphics"
+            }
+        );
         let swapchain: Swapchain = unsafe {
-            swapchain::Swapchain::new(window, &instance, &device, surface, physical_device)?
+            swapchain::Swapchain::new(
+                window,
+                &instance,
+                &device,
+                surface,
+                physical_device,
+                vk::SwapchainKHR::null(),
+            )?
         };
+        let deletion_queue = DeletionQueue::default();
 
         let command_pool = unsafe {
             command_buffers::create_command_pool(&instance, &device, surface, physical_device)?
         };
-        let depth_buffer: DepthBuffer = unsafe {
-            DepthBuffer::new(
+        let (frame_graph_builder, main_pass, _) = describe_frame_graph(&[]);
+        let frame_graph = unsafe {
+            FrameGraph::compile(
+                frame_graph_builder,
                 &instance,
                 &device,
                 physical_device,
-                &swapchain,
                 graphics_queue,
                 command_pool,
+                &deletion_queue,
+                &get_frame_graph_target(&swapchain),
             )?
         };
-
-        let render_pass = unsafe {
-            renderpass::create_render_pass(
-                &instance,
-                &device,
-                physical_device,
-                swapchain.get_format(),
-            )?
-        };
+        info!("{}", frame_graph);
+        let render_pass = frame_graph.get_render_pass(main_pass);
 
         let global_descriptor_set_layout: vk::DescriptorSetLayout = unsafe {
             descriptor::layout::create(
@@ -180,7 +305,8 @@ impl Graphics {
                     binding: 0,
                     descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
                     descriptor_count: 1,
-                    stage_flags: vk::ShaderStageFlags::VERTEX,
+                    // The fragment shader reads the fog
+                    stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
                 }],
             )?
         };
@@ -207,25 +333,107 @@ impl Graphics {
                         descriptor_count: 1,
                         stage_flags: vk::ShaderStageFlags::FRAGMENT,
                     },
+                    descriptor::layout::DescriptorInfo {
+                        binding: 3,
+                        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                        descriptor_count: 1,
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
+                    descriptor::layout::DescriptorInfo {
+                        binding: 4,
+                        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                        descriptor_count: 1,
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    },
                 ],
             )?
         };
 
-        let (pipeline_layout, pipeline) = unsafe {
-            pipeline::create_pipeline(
+        let skybox_descriptor_set_layout: vk::DescriptorSetLayout = unsafe {
+            descriptor::layout::create(
+                &device,
+                &[descriptor::layout::DescriptorInfo {
+                    binding: 0,
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                }],
+            )?
+        };
+
+        let particle_descriptor_set_layout: vk::DescriptorSetLayout = unsafe {
+            descriptor::layout::create(
+                &device,
+                &[descriptor::layout::DescriptorInfo {
+                    binding: 0,
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                }],
+            )?
+        };
+
+        let pipeline_layout = unsafe {
+            pipeline::create_pipeline_layout(
                 &device,
-                swapchain.get_extent(),
                 &[global_descriptor_set_layout, mesh_descriptor_set_layout],
+            )?
+        };
+        let skybox_pipeline_layout = unsafe {
+            pipeline::create_pipeline_layout(
+                &device,
+                &[global_descriptor_set_layout, skybox_descriptor_set_layout],
+            )?
+        };
+        let particle_pipeline_layout = unsafe {
+            pipeline::create_pipeline_layout(
+                &device,
+                &[global_descriptor_set_layout, particle_descriptor_set_layout],
+            )?
+        };
+        let pipeline_cache =
+            unsafe { PipelineCache::load(&instance, &device, physical_device)? };
+        let opaque_pipeline = unsafe {
+            pipeline::create_pipeline(
+                &device,
+                pipeline_cache.get(),
+                pipeline_layout,
                 render_pass,
+                BlendMode::Opaque,
             )?
         };
-        let framebuffers = unsafe {
-            framebuffer::create_framebuffers(
+        let translucent_pipeline = unsafe {
+            pipeline::create_pipeline(
+                &device,
+                pipeline_cache.get(),
+                pipeline_layout,
+                render_pass,
+                BlendMode::Translucent,
+            )?
+        };
+        let decal_pipeline = unsafe {
+            pipeline::create_pipeline(
                 &device,
-                swapchain.get_image_views(),
+                pipeline_cache.get(),
+                pipeline_layout,
+                render_pass,
+                BlendMode::Decal,
+            )?
+        };
+        let skybox_pipeline = unsafe {
+            pipeline::create_skybox_pipeline(
+                &device,
+                pipeline_cache.get(),
+                skybox_pipeline_layout,
+                render_pass,
+            )?
+        };
+        let particle_pipeline = unsafe {
+            pipeline::create_particle_pipeline(
+                &device,
+                pipeline_cache.get(),
+                particle_pipeline_layout,
                 render_pass,
-                &depth_buffer,
-                swapchain.get_extent(),
             )?
         };
         let graphics_barriers = GraphicsBarriers::new(&device, swapchain.get_images())?;
@@ -238,7 +446,7 @@ impl Graphics {
             )?
         };
 
-        let mut global_descriptor_allocator = DescriptorAllocator::new(
+        let global_descriptor_allocator = DescriptorAllocator::new(
             &device,
             &[descriptor::pool::PoolDescription {
                 type_: vk::DescriptorType::UNIFORM_BUFFER,
@@ -257,20 +465,67 @@ impl Graphics {
                 },
                 descriptor::pool::PoolDescription {
                     type_: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                    descriptor_count: 1,
+                    descriptor_count: 3,
                 },
             ],
             swapchain.get_length() as u32,
             1024,
         );
 
+        let compute_descriptor_allocator = DescriptorAllocator::new(
+            &device,
+            &[
+                descriptor::pool::PoolDescription {
+                    type_: vk::DescriptorType::STORAGE_BUFFER,
+                    descriptor_count: 4,
+                },
+                descriptor::pool::PoolDescription {
+                    type_: vk::DescriptorType::STORAGE_IMAGE,
+                    descriptor_count: 2,
+                },
+                descriptor::pool::PoolDescription {
+                    type_: vk::DescriptorType::UNIFORM_BUFFER,
+                    descriptor_count: 1,
+                },
+            ],
+            swapchain.get_length() as u32,
+            256,
+        );
+
         let descriptor_writer = DescriptorWriter::default();
 
-        let global_descriptor_sets = unsafe {
-            global_descriptor_allocator.allocate(
+        let default_cubemap = unsafe {
+            LoadedImage::load_cubemap_into_memory(
+                &CubemapImage::from_color([0, 0, 0, 255]),
+                &instance,
                 &device,
-                global_descriptor_set_layout,
-                swapchain.get_length(),
+                physical_device,
+                &upload_context,
+            )?
+        };
+        let max_anisotropy = unsafe {
+            physical_device::OptionalFeatures::get(&instance, physical_device)
+                .sampler_anisotropy
+                .then(|| {
+                    instance
+                        .get_physical_device_properties(physical_device)
+                        .limits
+                        .max_sampler_anisotropy
+                })
+        };
+        let mut sampler_cache = SamplerCache::new(max_anisotropy);
+        let cubemap_sampler =
+            unsafe { sampler_cache.get_or_create(&device, &SamplerDescription::cubemap())? };
+        // Only read with texelFetch, the filtering doesn't matter
+        let palette_sampler =
+            unsafe { sampler_cache.get_or_create(&device, &SamplerDescription::nearest())? };
+        let default_palette = unsafe {
+            LoadedImage::load_into_memory(
+                &Image::from_rgba(1, 1, vec![u8::MAX; 4])?,
+                &instance,
+                &device,
+                physical_device,
+                &upload_context,
             )?
         };
 
@@ -289,25 +544,97 @@ impl Graphics {
             graphics_queue,
             present_queue,
             command_pool,
+            can_dispatch_in_frames,
             graphics_barriers,
             mesh_descriptor_set_layout,
             global_descriptor_set_layout,
+            skybox_descriptor_set_layout,
+            particle_descriptor_set_layout,
             swapchain,
-            depth_buffer,
-            pipeline,
-            render_pass,
+            frame_graph,
+            main_pass,
+            render_targets: vec![],
+            opaque_pipeline,
+            translucent_pipeline,
+            decal_pipeline,
+            skybox_pipeline,
+            particle_pipeline,
+            pipeline_cache,
             pipeline_layout,
-            framebuffers,
+            skybox_pipeline_layout,
+            particle_pipeline_layout,
+            default_cubemap,
+            cubemap_sampler,
+            default_palette,
+            palette_sampler,
+            sampler_cache,
+            deletion_queue,
+            upload_context,
             command_buffers,
+            acquired_image: None,
+            capture_requested: false,
+            pending_captures: (0..MAX_FRAMES_IN_FLIGHT).map(|_| None).collect(),
+            captured_frame: None,
             start: Instant::now(),
-            global_descriptor_allocator,
-            mesh_descriptor_allocator,
+            global_descriptor_allocator: SharedDescriptorAllocator::new(global_descriptor_allocator),
+            mesh_descriptor_allocator: SharedDescriptorAllocator::new(mesh_descriptor_allocator),
+            compute_descriptor_allocator: SharedDescriptorAllocator::new(
+                compute_descriptor_allocator,
+            ),
             descriptor_writer,
-            global_descriptor_sets,
         })
     }
 }
 
+/// Every render pass drawn each frame, one per render target and then the main pass
+/// sampling them. New effects add their passes and the attachments they share here,
+/// in the order they are recorded.
+fn describe_frame_graph(targets: &[RenderTarget]) -> (FrameGraphBuilder, PassId, Vec<PassId>) {
+    let mut builder = FrameGraphBuilder::default();
+
+    let mut main_pass = PassDescription::new("main");
+    let mut target_passes = vec![];
+    for (index, target) in targets.iter().enumerate() {
+        let extent = target.get_extent();
+        let color = builder.import(
+            &format!("target {} color", index),
+            AttachmentFormat::Fixed(target.get_format()),
+            extent,
+            target.get_texture().get_image_view(),
+        );
+        let depth = builder.import(
+            &format!("target {} depth", index),
+            AttachmentFormat::Depth,
+            extent,
+            target.get_depth_image_view(),
+        );
+        target_passes.push(
+            builder.add_pass(
+                PassDescription::new(&format!("target {}", index))
+                    .with_color_attachment(color)
+                    .with_depth_attachment(depth),
+            ),
+        );
+        main_pass = main_pass.with_sampled(color);
+    }
+
+    let depth = builder.add_transient("depth", AttachmentFormat::Depth, AttachmentSize::Swapchain);
+    let main_pass = builder.add_pass(
+        main_pass
+            .with_color_attachment(FrameGraphBuilder::SWAPCHAIN)
+            .with_depth_attachment(depth),
+    );
+    (builder, main_pass, target_passes)
+}
+
+fn get_frame_graph_target(swapchain: &Swapchain) -> FrameGraphTarget<'_> {
+    FrameGraphTarget {
+        image_views: swapchain.get_image_views(),
+        format: swapchain.get_format(),
+        extent: swapchain.get_extent(),
+    }
+}
+
 pub enum StartRenderResult {
     Normal(Result<usize>),
     ShouldRecreateSwapchain,
@@ -326,219 +653,540 @@ impl Graphics {
         self.swapchain.get_extent()
     }
 
-    pub unsafe fn record_command_buffers<F>(&self, record_function: F) -> Result<()>
+    pub fn get_frame_graph(&self) -> &FrameGraph {
+        &self.frame_graph
+    }
+
+    /// One set of the camera layout per swapchain image.
+    pub fn allocate_global_descriptor_sets(&self) -> Result<Owned<DescriptorSets>> {
+        self.allocate_descriptor_sets(
+            &self.global_descriptor_allocator,
+            self.global_descriptor_set_layout,
+        )
+    }
+
+    /// One set of the mesh layout per swapchain image.
+    pub fn allocate_mesh_descriptor_sets(&self) -> Result<Owned<DescriptorSets>> {
+        self.allocate_descriptor_sets(
+            &self.mesh_descriptor_allocator,
+            self.mesh_descriptor_set_layout,
+        )
+    }
+
+    /// One set of the skybox layout per swapchain image.
+    pub fn allocate_skybox_descriptor_sets(&self) -> Result<Owned<DescriptorSets>> {
+        self.allocate_descriptor_sets(
+            &self.mesh_descriptor_allocator,
+            self.skybox_descriptor_set_layout,
+        )
+    }
+
+    /// One set of the particle layout per swapchain image.
+    pub fn allocate_particle_descriptor_sets(&self) -> Result<Owned<DescriptorSets>> {
+        self.allocate_descriptor_sets(
+            &self.mesh_descriptor_allocator,
+            self.particle_descriptor_set_layout,
+        )
+    }
+
+    /// How the pools behind meshes, skyboxes and particles are used.
+    pub fn get_mesh_descriptor_stats(&self) -> DescriptorAllocatorStats {
+        self.mesh_descriptor_allocator.lock().get_stats()
+    }
+
+    fn allocate_descriptor_sets(
+        &self,
+        allocator: &SharedDescriptorAllocator,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+    ) -> Result<Owned<DescriptorSets>> {
+        let descriptor_sets = unsafe {
+            allocator.allocate(
+                &self.device,
+                descriptor_set_layout,
+                self.swapchain.get_length(),
+            )?
+        };
+
+        Ok(self.deletion_queue.own(descriptor_sets))
+    }
+
+    /// Record the command buffer of every swapchain image again. Waits for the frames
+    /// in flight first, as their command buffers can't be reset while pending.
+    pub fn record_command_buffers<F>(&self, record_function: F) -> Result<()>
     where
-        F: Fn(&Self, vk::CommandBuffer, usize) -> (),
+        F: Fn(&mut FrameRecorder),
     {
-        record_command_buffers(
-            &self.device,
-            &self.command_buffers,
-            self.swapchain.get_extent(),
-            self.render_pass,
-            self.pipeline,
-            &self.framebuffers,
-            record_function,
-            self,
-        )?;
+        self.device_wait_idle()?;
+        unsafe {
+            record_command_buffers(
+                &self.device,
+                &self.command_buffers,
+                |graphics, command_buffer, index| {
+                    record_function(&mut FrameRecorder::new(graphics, command_buffer, index))
+                },
+                self,
+            )
+        }
+    }
+
+    /// Record the command buffer of the swapchain image acquired by
+    /// [`Graphics::start_render`] again, before `end_render` submits it. Nothing is
+    /// waited for, the last frame drawing that image finished before it was acquired.
+    pub fn record_acquired_command_buffer<F>(&self, record_function: F) -> Result<()>
+    where
+        F: Fn(&mut FrameRecorder),
+    {
+        let index = self
+            .acquired_image
+            .ok_or_else(|| anyhow!("No swapchain image is acquired outside of a frame"))?;
+        unsafe {
+            record_command_buffers(
+                &self.device,
+                &self.command_buffers[index..=index],
+                |graphics, command_buffer, _| {
+                    record_function(&mut FrameRecorder::new(graphics, command_buffer, index))
+                },
+                self,
+            )
+        }
+    }
+
+    /// Begin drawing into the swapchain image the command buffer at `index` presents.
+    pub(super) unsafe fn begin_swapchain_render_pass(&self, command_buffer: vk::CommandBuffer, index: usize) {
+        self.frame_graph
+            .begin_pass(&self.device, command_buffer, self.main_pass, index);
+        self.device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.opaque_pipeline,
+        );
+    }
+
+    /// Make every target a pass of the frame graph, recorded before the main pass so
+    /// that it can sample them. The graph is only rebuilt when the targets changed.
+    pub fn update_render_targets(&mut self, targets: &[&RenderTarget]) -> Result<()> {
+        let is_unchanged = targets.len() == self.render_targets.len()
+            && targets
+                .iter()
+                .zip(&self.render_targets)
+                .all(|(&target, (current, _))| target == current);
+        if is_unchanged {
+            return Ok(());
+        }
 
+        let targets: Vec<RenderTarget> = targets.iter().map(|&target| target.clone()).collect();
+        let (frame_graph_builder, main_pass, target_passes) = describe_frame_graph(&targets);
+        let frame_graph = unsafe {
+            FrameGraph::compile(
+                frame_graph_builder,
+                &self.instance,
+                &self.device,
+                self.physical_device,
+                self.graphics_queue,
+                self.command_pool,
+                &self.deletion_queue,
+                &get_frame_graph_target(&self.swapchain),
+            )?
+        };
+        trace!("{}", frame_graph);
+
+        // Frames in flight may still be drawing with the old graph
+        let previous = std::mem::replace(&mut self.frame_graph, frame_graph);
+        drop(self.deletion_queue.own(previous));
+        self.main_pass = main_pass;
+        self.render_targets = targets.into_iter().zip(target_passes).collect();
         Ok(())
     }
 
-    pub unsafe fn start_render(&mut self, window: &Window) -> StartRenderResult {
-        match self
-            .graphics_barriers
-            .wait_for_in_flight_fence(&self.device, self.current_frame)
-        {
-            Ok(it) => it,
-            Err(e) => return StartRenderResult::Normal(Err(e)),
+    /// Begin drawing into an offscreen target. Its texture can be sampled by any
+    /// render pass recorded after this one ends. Returns false without recording
+    /// anything when the target is not part of the frame graph, see
+    /// [`Graphics::update_render_targets`].
+    pub(super) unsafe fn begin_target_render_pass(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        target: &RenderTarget,
+    ) -> bool {
+        let Some((_, pass)) = self.render_targets.iter().find(|(current, _)| current == target)
+        else {
+            return false;
         };
+        self.frame_graph
+            .begin_pass(&self.device, command_buffer, *pass, 0);
+        self.device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.opaque_pipeline,
+        );
+        true
+    }
+
+    pub(super) unsafe fn end_render_pass(&self, command_buffer: vk::CommandBuffer) {
+        self.device.cmd_end_render_pass(command_buffer);
+    }
+
+    /// Restrict subsequent draws to part of the target being rendered, the swapchain
+    /// when `target` is `None`.
+    pub(super) unsafe fn set_viewport(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        target: Option<&RenderTarget>,
+        rect: &ViewportRect,
+    ) {
+        let scissor = rect.get_pixel_rect(self.get_target_extent(target));
+        let viewport = vk::Viewport::builder()
+            .x(scissor.offset.x as f32)
+            .y(scissor.offset.y as f32)
+            .width(scissor.extent.width as f32)
+            .height(scissor.extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0);
+
+        unsafe {
+            self.device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+            self.device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+        }
+    }
+
+    /// Reset depth within part of the target being rendered, so that what is drawn
+    /// next ends up on top of what is already there.
+    pub(super) unsafe fn clear_depth(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        target: Option<&RenderTarget>,
+        rect: &ViewportRect,
+    ) {
+        let attachment = vk::ClearAttachment::builder()
+            .aspect_mask(vk::ImageAspectFlags::DEPTH)
+            .clear_value(vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            });
+        let clear_rect = vk::ClearRect::builder()
+            .rect(rect.get_pixel_rect(self.get_target_extent(target)))
+            .base_array_layer(0)
+            .layer_count(1);
+
+        unsafe {
+            self.device
+                .cmd_clear_attachments(command_buffer, &[attachment], &[clear_rect]);
+        }
+    }
+
+    fn get_target_extent(&self, target: Option<&RenderTarget>) -> vk::Extent2D {
+        target.map_or(self.swapchain.get_extent(), |target| target.get_extent())
+    }
+
+    /// Create an offscreen target in the swapchain format, so that the same
+    /// pipelines draw into both.
+    pub fn create_render_target(&self, width: u32, height: u32) -> Result<RenderTarget> {
+        unsafe {
+            RenderTarget::new(
+                &self.instance,
+                &self.device,
+                self.physical_device,
+                self.graphics_queue,
+                self.command_pool,
+                &self.deletion_queue,
+                self.swapchain.get_format(),
+                vk::Extent2D { width, height },
+            )
+        }
+    }
 
-        let result =
+    pub fn start_render(&mut self, window: &Window) -> StartRenderResult {
+        self.acquired_image = None;
+        unsafe {
             match self
                 .graphics_barriers
-                .get_image_available_semaphore(self.current_frame)
+                .wait_for_in_flight_fence(&self.device, self.current_frame)
             {
-                Some(semaphore) => self.device.acquire_next_image_khr(
-                    self.swapchain.get_chain(),
-                    u64::MAX,
-                    semaphore.clone(),
-                    vk::Fence::null(),
-                ),
-                None => {
-                    return StartRenderResult::Normal(Err(anyhow!(
-                "Current frame index {} is out of range of available semaphores structure {}", 
-                self.current_frame, self.graphics_barriers.get_length())))
-                }
+                Ok(it) => it,
+                Err(e) => return StartRenderResult::Normal(Err(e)),
             };
 
-        let image_index = match result {
-            Ok((image_index, _)) => image_index as usize,
-            Err(vk::ErrorCode::OUT_OF_DATE_KHR) => {
-                trace!("Image index grab error");
-                return StartRenderResult::ShouldRecreateSwapchain;
+            // A copy submitted with the frame that last used this slot is done now
+            if let Some(capture) = self.pending_captures[self.current_frame].take() {
+                match capture.read(&self.device, self.command_pool) {
+                    Ok(frame) => self.captured_frame = Some(frame),
+                    Err(e) => return StartRenderResult::Normal(Err(e)),
+                }
             }
-            Err(e) => return StartRenderResult::Normal(Err(anyhow!(e))),
-        };
 
-        match self
-            .graphics_barriers
-            .wait_for_image_in_flight(&self.device, image_index)
-        {
-            Ok(it) => it,
-            Err(e) => return StartRenderResult::Normal(Err(e)),
-        };
+            let result =
+                match self
+                    .graphics_barriers
+                    .get_image_available_semaphore(self.current_frame)
+                {
+                    Some(semaphore) => self.device.acquire_next_image_khr(
+                        self.swapchain.get_chain(),
+                        u64::MAX,
+                        *semaphore,
+                        vk::Fence::null(),
+                    ),
+                    None => {
+                        return StartRenderResult::Normal(Err(anyhow!(
+                    "Current frame index {} is out of range of available semaphores structure {}", 
+                    self.current_frame, self.graphics_barriers.get_length())))
+                    }
+                };
 
-        self.graphics_barriers
-            .slot_in_flight_fence_to_image_in_flight(self.current_frame, image_index);
-
-        StartRenderResult::Normal(Ok(image_index))
-    }
-
-    pub unsafe fn end_render(&mut self, window: &Window, image_index: usize) -> Result<bool> {
-        let command_buffers = &[self.command_buffers[image_index]];
-        let wait_semaphores = &[self
-            .graphics_barriers
-            .get_image_available_semaphore_unchecked(self.current_frame)];
-        let wait_stages = &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-        let signal_semaphores = &[self
-            .graphics_barriers
-            .get_render_finished_semaphores_unchecked(self.current_frame)];
-        let submit_info = vk::SubmitInfo::builder()
-            .wait_semaphores(wait_semaphores)
-            .wait_dst_stage_mask(wait_stages)
-            .command_buffers(command_buffers)
-            .signal_semaphores(signal_semaphores);
-
-        let in_flight_fence = self
-            .graphics_barriers
-            .get_in_flight_fence_unchecked(self.current_frame);
-        self.device.reset_fences(&[in_flight_fence])?;
-
-        self.device
-            .queue_submit(self.graphics_queue, &[submit_info], in_flight_fence)?;
-
-        let swapchains = &[self.swapchain.get_chain()];
-        let image_indices = &[image_index as u32];
-        let present_info = vk::PresentInfoKHR::builder()
-            .wait_semaphores(signal_semaphores)
-            .swapchains(swapchains)
-            .image_indices(image_indices);
-
-        let result = self
-            .device
-            .queue_present_khr(self.present_queue, &present_info);
-        let present_queue_changed = result == Ok(vk::SuccessCode::SUBOPTIMAL_KHR)
-            || result == Err(vk::ErrorCode::OUT_OF_DATE_KHR);
-
-        let should_recreate_swapchain = self.resized || present_queue_changed;
-        if should_recreate_swapchain {
-            trace!(
-                "Swapchain recreation queued resized: {} suboptimal {} out of date {}",
-                self.resized,
-                result == Ok(vk::SuccessCode::SUBOPTIMAL_KHR),
-                result == Err(vk::ErrorCode::OUT_OF_DATE_KHR)
-            );
-            self.resized = false;
-        } else if let Err(e) = result {
-            return Err(anyhow!(e));
-        }
+            let image_index = match result {
+                Ok((image_index, _)) => image_index as usize,
+                Err(vk::ErrorCode::OUT_OF_DATE_KHR) => {
+                    trace!("Image index grab error");
+                    return StartRenderResult::ShouldRecreateSwapchain;
+                }
+                Err(e) => return StartRenderResult::Normal(Err(anyhow!(e))),
+            };
 
-        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+            match self
+                .graphics_barriers
+                .wait_for_image_in_flight(&self.device, image_index)
+            {
+                Ok(it) => it,
+                Err(e) => return StartRenderResult::Normal(Err(e)),
+            };
 
-        Ok(should_recreate_swapchain)
-    }
+            self.graphics_barriers
+                .slot_in_flight_fence_to_image_in_flight(self.current_frame, image_index);
+            self.acquired_image = Some(image_index);
 
-    pub unsafe fn device_wait_idle(&self) -> Result<()> {
-        self.device.device_wait_idle()?;
-        Ok(())
+            StartRenderResult::Normal(Ok(image_index))
+        }
     }
 
-    pub unsafe fn recreate_swapchain(&mut self, window: &Window) -> Result<()> {
+    pub fn end_render(&mut self, window: &Window, image_index: usize) -> Result<bool> {
+        self.acquired_image = None;
         unsafe {
-            self.destroy_swapchain();
-            self.swapchain = unsafe {
-                swapchain::Swapchain::new(
-                    window,
+            // Copied right after drawing, before presenting waits on the same semaphore
+            if self.capture_requested {
+                self.capture_requested = false;
+                self.pending_captures[self.current_frame] = Some(PendingCapture::record(
                     &self.instance,
                     &self.device,
-                    self.surface,
                     self.physical_device,
-                )?
-            };
-            self.depth_buffer = unsafe {
-                DepthBuffer::new(
-                    &self.instance,
-                    &self.device,
-                    self.physical_device,
-                    &self.swapchain,
-                    self.graphics_queue,
                     self.command_pool,
-                )?
-            };
-            self.render_pass = unsafe {
-                renderpass::create_render_pass(
-                    &self.instance,
-                    &self.device,
-                    self.physical_device,
+                    self.swapchain.get_images()[image_index],
                     self.swapchain.get_format(),
-                )?
-            };
-            (self.pipeline_layout, self.pipeline) = unsafe {
-                pipeline::create_pipeline(
-                    &self.device,
-                    self.swapchain.get_extent(),
-                    &[
-                        self.global_descriptor_set_layout,
-                        self.mesh_descriptor_set_layout,
-                    ],
-                    self.render_pass,
-                )?
-            };
-            self.framebuffers = unsafe {
-                framebuffer::create_framebuffers(
-                    &self.device,
-                    self.swapchain.get_image_views(),
-                    self.render_pass,
-                    &self.depth_buffer,
                     self.swapchain.get_extent(),
-                )?
-            };
+                )?);
+            }
+            let capture = self.pending_captures[self.current_frame].as_ref();
+            let command_buffers = &[
+                self.command_buffers[image_index],
+                capture.map_or(vk::CommandBuffer::null(), PendingCapture::get_command_buffer),
+            ][..1 + capture.is_some() as usize];
+            let wait_semaphores = &[self
+                .graphics_barriers
+                .get_image_available_semaphore_unchecked(self.current_frame)];
+            let wait_stages = &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+            let signal_semaphores = &[self
+                .graphics_barriers
+                .get_render_finished_semaphores_unchecked(self.current_frame)];
+            let submit_info = vk::SubmitInfo::builder()
+                .wait_semaphores(wait_semaphores)
+                .wait_dst_stage_mask(wait_stages)
+                .command_buffers(command_buffers)
+                .signal_semaphores(signal_semaphores);
+
+            // Whatever this frame draws was uploaded by now, or is copied before it starts.
+            // Flushed before the fence is reset, so a failure can't leave it unsignaled.
+            self.upload_context.flush(&self.device)?;
+
+            let in_flight_fence = self
+                .graphics_barriers
+                .get_in_flight_fence_unchecked(self.current_frame);
+            self.device.reset_fences(&[in_flight_fence])?;
+
+            self.device
+                .queue_submit(self.graphics_queue, &[submit_info], in_flight_fence)?;
+
+            let swapchains = &[self.swapchain.get_chain()];
+            let image_indices = &[image_index as u32];
+            let present_info = vk::PresentInfoKHR::builder()
+                .wait_semaphores(signal_semaphores)
+                .swapchains(swapchains)
+                .image_indices(image_indices);
+
+            let result = self
+                .device
+                .queue_present_khr(self.present_queue, &present_info);
+            let present_queue_changed = result == Ok(vk::SuccessCode::SUBOPTIMAL_KHR)
+                || result == Err(vk::ErrorCode::OUT_OF_DATE_KHR);
+
+            let should_recreate_swapchain = self.resized || present_queue_changed;
+            if should_recreate_swapchain {
+                trace!(
+                    "Swapchain recreation queued resized: {} suboptimal {} out of date {}",
+                    self.resized,
+                    result == Ok(vk::SuccessCode::SUBOPTIMAL_KHR),
+                    result == Err(vk::ErrorCode::OUT_OF_DATE_KHR)
+                );
+                self.resized = false;
+            } else if let Err(e) = result {
+                return Err(anyhow!(e));
+            }
+
+            self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+            self.deletion_queue.advance_frame(&self.device);
+            self.upload_context.collect(&self.device)?;
+
+            Ok(should_recreate_swapchain)
         }
+    }
 
-        Ok(())
+    /// Take the validation messages reported since the last call, with their counts
+    /// per severity. Always empty when validation is disabled. Fails after a validation
+    /// error in strict mode.
+    pub fn drain_validation_messages(&self) -> Result<(Vec<ValidationMessage>, ValidationCounts)> {
+        validation_layers::drain_validation_messages()
     }
 
-    pub unsafe fn continue_after_swapchain_construction(&mut self) {
-        self.graphics_barriers.reset_images_in_flight();
+    /// Copy the next rendered frame to host memory before it is presented. Retrieve it
+    /// with [`Graphics::take_captured_frame`] once that frame finished, which
+    /// `start_render` notices when it reuses its frame in flight.
+    pub fn request_frame_capture(&mut self) -> Result<()> {
+        if !self.swapchain.supports_capture() {
+            return Err(anyhow!("Surface does not support copying from the swapchain"));
+        }
+        self.capture_requested = true;
+        Ok(())
     }
 
-    pub fn trigger_resize(&mut self) {
-        self.resized = true;
+    pub fn take_captured_frame(&mut self) -> Option<CapturedFrame> {
+        self.captured_frame.take()
     }
 
-    pub unsafe fn free_command_buffers(&self) {
-        self.device
-            .free_command_buffers(self.command_pool, &self.command_buffers);
+    /// Block until the device finished everything submitted to it.
+    pub fn device_wait_idle(&self) -> Result<()> {
+        unsafe { self.device.device_wait_idle()? };
+        Ok(())
     }
 
-    unsafe fn destroy_swapchain(&mut self) {
+    /// Viewport and scissor are dynamic state, so only the attachments that depend
+    /// on the window size are rebuilt by the frame graph. The render passes and
+    /// pipelines are kept unless the new swapchain picked a different surface format.
+    /// Returns whether it did, render targets made before then are in the old format
+    /// and have to be created again.
+    pub fn recreate_swapchain(&mut self, window: &Window) -> Result<bool> {
+        // The framebuffers using the old swapchain images are destroyed right away
+        self.device_wait_idle()?;
         unsafe {
-            framebuffer::destroy_framebuffers(&self.device, &self.framebuffers);
-            pipeline::destroy_pipeline(&self.device, self.pipeline, self.pipeline_layout);
-            renderpass::destroy_render_pass(&self.device, self.render_pass);
-            self.depth_buffer.destroy(&self.device);
-            self.swapchain.destroy(&self.device);
+            let swapchain = swapchain::Swapchain::new(
+                window,
+                &self.instance,
+                &self.device,
+                self.surface,
+                self.physical_device,
+                self.swapchain.get_chain(),
+            )?;
+            // The old swapchain is retired but may still be presenting
+            let previous = std::mem::replace(&mut self.swapchain, swapchain);
+            drop(self.deletion_queue.own(previous));
+            let render_passes_changed = self.frame_graph.resize(
+                &self.instance,
+                &self.device,
+                self.physical_device,
+                self.graphics_queue,
+                self.command_pool,
+                &self.deletion_queue,
+                &get_frame_graph_target(&self.swapchain),
+            )?;
+            if render_passes_changed {
+                info!("Swapchain format changed, rebuilding pipelines");
+                self.recreate_pipelines()?;
+            }
+            trace!("{}", self.frame_graph);
+            self.graphics_barriers.reset_images_in_flight();
+            Ok(render_passes_changed)
         }
     }
 
+    unsafe fn recreate_pipelines(&mut self) -> Result<()> {
+        self.destroy_pipelines();
+        let render_pass = self.frame_graph.get_render_pass(self.main_pass);
+        self.opaque_pipeline = pipeline::create_pipeline(
+            &self.device,
+            self.pipeline_cache.get(),
+            self.pipeline_layout,
+            render_pass,
+            BlendMode::Opaque,
+        )?;
+        self.translucent_pipeline = pipeline::create_pipeline(
+            &self.device,
+            self.pipeline_cache.get(),
+            self.pipeline_layout,
+            render_pass,
+            BlendMode::Translucent,
+        )?;
+        self.decal_pipeline = pipeline::create_pipeline(
+            &self.device,
+            self.pipeline_cache.get(),
+            self.pipeline_layout,
+            render_pass,
+            BlendMode::Decal,
+        )?;
+        self.skybox_pipeline = pipeline::create_skybox_pipeline(
+            &self.device,
+            self.pipeline_cache.get(),
+            self.skybox_pipeline_layout,
+            render_pass,
+        )?;
+        self.particle_pipeline = pipeline::create_particle_pipeline(
+            &self.device,
+            self.pipeline_cache.get(),
+            self.particle_pipeline_layout,
+            render_pass,
+        )?;
+        Ok(())
+    }
+
+    pub fn trigger_resize(&mut self) {
+        self.resized = true;
+    }
+
+    unsafe fn destroy_pipelines(&self) {
+        pipeline::destroy_pipeline(&self.device, self.opaque_pipeline);
+        pipeline::destroy_pipeline(&self.device, self.translucent_pipeline);
+        pipeline::destroy_pipeline(&self.device, self.decal_pipeline);
+        pipeline::destroy_pipeline(&self.device, self.skybox_pipeline);
+        pipeline::destroy_pipeline(&self.device, self.particle_pipeline);
+    }
+
     pub fn destroy(&mut self) {
         unsafe {
-            self.destroy_swapchain();
+            self.upload_context.destroy(&self.device);
+            for capture in self.pending_captures.iter_mut().filter_map(Option::take) {
+                capture.destroy(&self.device, self.command_pool);
+            }
+            // Queued along with the transient images of the graph
+            drop(self.deletion_queue.own(std::mem::take(&mut self.frame_graph)));
+            self.render_targets.clear();
+            self.deletion_queue.close(&self.device);
+            self.destroy_pipelines();
+            self.swapchain.destroy(&self.device);
+            if let Err(e) = self.pipeline_cache.save(&self.device) {
+                error!("Failed to save pipeline cache: {}", e);
+            }
+            self.pipeline_cache.destroy(&self.device);
+            pipeline::destroy_pipeline_layout(&self.device, self.pipeline_layout);
+            pipeline::destroy_pipeline_layout(&self.device, self.skybox_pipeline_layout);
+            pipeline::destroy_pipeline_layout(&self.device, self.particle_pipeline_layout);
             descriptor::layout::destroy(&self.device, self.mesh_descriptor_set_layout);
             descriptor::layout::destroy(&self.device, self.global_descriptor_set_layout);
+            descriptor::layout::destroy(&self.device, self.skybox_descriptor_set_layout);
+            descriptor::layout::destroy(&self.device, self.particle_descriptor_set_layout);
+
+            self.default_cubemap.destroy(&self.device);
+            self.default_palette.destroy(&self.device);
+            self.sampler_cache.destroy(&self.device);
 
-            self.global_descriptor_allocator.destroy(&self.device);
-            self.mesh_descriptor_allocator.destroy(&self.device);
+            self.global_descriptor_allocator.lock().destroy(&self.device);
+            self.mesh_descriptor_allocator.lock().destroy(&self.device);
+            self.compute_descriptor_allocator.lock().destroy(&self.device);
 
             self.graphics_barriers.destroy(&self.device);
 
@@ -617,93 +1265,260 @@ impl Graphics {
         results
     }
 
-    pub unsafe fn load_into_gpu(&self, mesh: &CPUMesh) -> Result<GPUMesh> {
-        let vertex_buffer = VertexBuffer::create(
-            &self.instance,
-            &self.device,
-            self.physical_device,
-            self.command_pool,
-            self.graphics_queue,
-            &mesh.vertices,
-        )?;
-
-        let index_buffer = IndexBuffer::create(
-            &self.instance,
-            &self.device,
-            self.physical_device,
-            self.command_pool,
-            self.graphics_queue,
-            &mesh.indices,
-        )?;
+    pub fn load_into_gpu(&self, mesh: &CPUMesh) -> Result<Owned<GPUMesh>> {
+        GPUMesh::create(self, mesh)
+    }
 
-        let triangles_count: usize = mesh.indices.len() / 3;
+    pub fn load_texture_to_gpu(&self, image: &Image) -> Result<Owned<LoadedImage>> {
+        LoadedImage::create(self, image)
+    }
 
-        Ok(GPUMesh {
-            triangles_count,
-            vertex_buffer,
-            index_buffer,
-        })
+    /// Get the shared sampler for a description, creating it on first use. It stays
+    /// alive until the graphics are destroyed.
+    pub fn get_image_sampler(&mut self, description: &SamplerDescription) -> Result<ImageSampler> {
+        unsafe { self.sampler_cache.get_or_create(&self.device, description) }
     }
 
-    pub unsafe fn unload_from_gpu(&self, mesh: &GPUMesh) -> Result<()> {
-        VertexBuffer::destroy(mesh.vertex_buffer, &self.device);
-        IndexBuffer::destroy(mesh.index_buffer, &self.device);
-        Ok(())
+    pub fn create_uniform_buffer_series<T>(&self) -> Result<Owned<UniformBufferSeries>> {
+        UniformBufferSeries::create_from_graphics::<T>(self)
     }
 
-    pub unsafe fn load_texture_to_gpu(&self, image: &Image) -> Result<LoadedImage> {
-        let loaded_image = unsafe {
-            LoadedImage::load_into_memory(
-                image,
-                &self.instance,
+    pub fn update_uniform_buffer_series<T>(
+        &self,
+        uniform_buffer_series: &UniformBufferSeries,
+        image_index: usize,
+        data: &T,
+    ) -> Result<()> {
+        unsafe {
+            uniform_buffer::update_uniform_buffer_series(
                 &self.device,
-                self.physical_device,
-                self.graphics_queue,
-                self.command_pool,
-            )?
+                data,
+                uniform_buffer_series,
+                image_index,
+            )
+        }
+    }
+
+    /// Switch the pipeline used by subsequent draws in a command buffer being recorded.
+    /// Every render pass starts out with the opaque pipeline bound.
+    pub(super) unsafe fn bind_pipeline(&self, command_buffer: vk::CommandBuffer, blend_mode: BlendMode) {
+        let pipeline = match blend_mode {
+            BlendMode::Opaque => self.opaque_pipeline,
+            BlendMode::Translucent => self.translucent_pipeline,
+            BlendMode::Decal => self.decal_pipeline,
         };
+        unsafe {
+            self.device
+                .cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+        }
+    }
 
-        Ok(loaded_image)
+    /// Draw the skybox cube with the camera of `global_descriptor_set`. Leaves the
+    /// skybox pipeline bound, so rebind a mesh pipeline before drawing meshes again.
+    pub(super) unsafe fn draw_skybox(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        global_descriptor_set: vk::DescriptorSet,
+        skybox_descriptor_set: vk::DescriptorSet,
+        cube: &GPUMesh,
+    ) {
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.skybox_pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.skybox_pipeline_layout,
+                0,
+                &[global_descriptor_set, skybox_descriptor_set],
+                &[],
+            );
+            cube.bind(self, command_buffer);
+            cube.draw(self, command_buffer);
+        }
     }
 
-    pub unsafe fn unload_texture_from_gpu(&self, loaded_image: &LoadedImage) -> Result<()> {
-        loaded_image.destroy(&self.device);
-        Ok(())
+    /// Draw the particles last written to `particles` for this command buffer, with
+    /// the camera of `global_descriptor_set`. Leaves the particle pipeline bound.
+    pub(super) unsafe fn draw_particles(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        index: usize,
+        global_descriptor_set: vk::DescriptorSet,
+        particle_descriptor_set: vk::DescriptorSet,
+        particles: &ParticleBufferSeries,
+    ) {
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.particle_pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.particle_pipeline_layout,
+                0,
+                &[global_descriptor_set, particle_descriptor_set],
+                &[],
+            );
+            self.device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[particles.get_instance_buffer(index)],
+                &[0],
+            );
+            // The count is read from the buffer when the frame runs, not now
+            self.device.cmd_draw_indirect(
+                command_buffer,
+                particles.get_indirect_buffer(index),
+                0,
+                1,
+                0,
+            );
+        }
     }
 
-    pub unsafe fn unload_sampler_from_gpu(&self, image_sampler: &ImageSampler) -> Result<()> {
-        image_sampler.destroy(&self.device);
-        Ok(())
+    pub fn update_particle_buffer_series(
+        &self,
+        particle_buffer_series: &ParticleBufferSeries,
+        image_index: usize,
+        particles: &[ParticleInstance],
+    ) -> Result<()> {
+        unsafe { particle_buffer_series.update(&self.device, image_index, particles) }
     }
 
-    pub unsafe fn create_image_sampler(&self) -> Result<ImageSampler> {
-        ImageSampler::create(&self.device)
+    /// Queue writing a cubemap, or the default black one, to a `samplerCube` binding.
+    pub fn queue_write_cubemap(
+        &mut self,
+        cubemap: Option<&LoadedImage>,
+        descriptor_sets: &[vk::DescriptorSet],
+        binding: u32,
+    ) {
+        let cubemap = cubemap.unwrap_or(&self.default_cubemap);
+        self.descriptor_writer.queue_write_image(
+            &self.device,
+            &self.cubemap_sampler,
+            cubemap,
+            descriptor_sets,
+            binding,
+        );
     }
 
-    pub unsafe fn create_uniform_buffer_series<T>(&self) -> Result<UniformBufferSeries> {
-        uniform_buffer::create_series::<T>(
-            &self.instance,
+    /// Queue writing a palette texture, or a single white color for meshes without one,
+    /// to the palette binding of the mesh shader.
+    pub fn queue_write_palette(
+        &mut self,
+        palette: Option<&LoadedImage>,
+        descriptor_sets: &[vk::DescriptorSet],
+        binding: u32,
+    ) {
+        let palette = palette.unwrap_or(&self.default_palette);
+        self.descriptor_writer.queue_write_image(
             &self.device,
-            self.physical_device,
-            self.swapchain.get_length(),
+            &self.palette_sampler,
+            palette,
+            descriptor_sets,
+            binding,
+        );
+    }
+
+    /// Queue writing a storage buffer to a binding of a compute shader.
+    pub fn queue_write_storage_buffer(
+        &mut self,
+        buffer: &StorageBuffer,
+        descriptor_sets: &[vk::DescriptorSet],
+        binding: u32,
+    ) {
+        self.descriptor_writer
+            .queue_write_storage_buffer(&self.device, buffer, descriptor_sets, binding);
+    }
+
+    /// Queue writing an image made by [`LoadedImage::create_storage`] to an `image2D`
+    /// binding of a compute shader.
+    pub fn queue_write_storage_image(
+        &mut self,
+        image: &LoadedImage,
+        descriptor_sets: &[vk::DescriptorSet],
+        binding: u32,
+    ) {
+        self.descriptor_writer
+            .queue_write_storage_image(&self.device, image, descriptor_sets, binding);
+    }
+
+    /// Whether the graphics queue can dispatch compute shaders, which frames need to
+    /// record their dispatches. Vulkan only promises that some graphics family can.
+    pub fn can_dispatch_in_frames(&self) -> bool {
+        self.can_dispatch_in_frames
+    }
+
+    /// One descriptor set of the compute pipeline per swapchain image.
+    pub fn allocate_compute_descriptor_sets(
+        &self,
+        pipeline: &ComputePipeline,
+    ) -> Result<Owned<DescriptorSets>> {
+        self.allocate_descriptor_sets(
+            &self.compute_descriptor_allocator,
+            pipeline.get_descriptor_set_layout(),
         )
     }
 
-    pub unsafe fn update_uniform_buffer_series<T>(
+    pub(super) unsafe fn dispatch_compute(
         &self,
-        uniform_buffer_series: &UniformBufferSeries,
-        image_index: usize,
-        data: &T,
-    ) -> Result<()> {
-        uniform_buffer::update_uniform_buffer_series(
+        command_buffer: vk::CommandBuffer,
+        pipeline: &ComputePipeline,
+        descriptor_set: vk::DescriptorSet,
+        group_counts: [u32; 3],
+    ) {
+        pipeline.dispatch(&self.device, command_buffer, descriptor_set, group_counts);
+    }
+
+    pub(super) unsafe fn record_compute_barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        barrier: ComputeBarrier,
+    ) {
+        barrier.record(&self.device, command_buffer);
+    }
+
+    /// Record and submit a one-off command buffer, and wait for it to finish. For
+    /// setup work, and for reading back storage buffers outside of the frame loop.
+    pub(super) unsafe fn submit_now<F>(&self, record_function: F) -> Result<()>
+    where
+        F: FnOnce(&Graphics, vk::CommandBuffer),
+    {
+        self.upload_context.flush(&self.device)?;
+        let command_buffer = begin_single_time_commands(&self.device, self.command_pool)?;
+        record_function(self, command_buffer);
+        end_single_time_commands(
             &self.device,
-            data,
-            uniform_buffer_series,
-            image_index,
+            self.graphics_queue,
+            self.command_pool,
+            command_buffer,
         )
     }
 
-    pub unsafe fn bind_descriptor_set(
+    /// Submit the uploads recorded so far without waiting for them. Frames flush on
+    /// their own, this is for work submitted outside of them.
+    pub fn flush_uploads(&self) -> Result<UploadHandle> {
+        unsafe { self.upload_context.flush(&self.device) }
+    }
+
+    /// Whether the buffers and images uploaded in `handle` can be used. They can be
+    /// drawn from right away regardless, frames wait for their uploads on the device.
+    pub fn is_upload_finished(&self, handle: UploadHandle) -> Result<bool> {
+        unsafe { self.upload_context.is_finished(&self.device, handle) }
+    }
+
+    /// Block until the uploads in `handle` finished, submitting them if needed.
+    pub fn wait_for_upload(&self, handle: UploadHandle) -> Result<()> {
+        unsafe { self.upload_context.wait(&self.device, handle) }
+    }
+
+    pub(super) unsafe fn bind_descriptor_set(
         &self,
         command_buffer: vk::CommandBuffer,
         descriptor_sets: &[vk::DescriptorSet],
@@ -721,35 +1536,6 @@ impl Graphics {
         }
     }
 
-    pub unsafe fn bind_image_sampler(
-        &self,
-        descriptor_sets: &[vk::DescriptorSet],
-        sampler: &ImageSampler,
-        image: &LoadedImage,
-        binding: u32,
-    ) {
-        bind_sampler_to_descriptor_sets(&self.device, sampler, image, descriptor_sets, binding);
-    }
-
-    pub unsafe fn destroy_uniform_buffer_series(&self, uniform_buffers: &UniformBufferSeries) {
-        uniform_buffer::destroy_series(&self.device, uniform_buffers);
-    }
-
-    pub unsafe fn bind_uniform_buffer<T>(
-        &self,
-        uniform_buffers: &UniformBufferSeries,
-        descriptor_sets: &[vk::DescriptorSet],
-        binding: u32,
-    ) {
-        uniform_buffers.bind_to_descriptor_sets::<T>(&self.device, descriptor_sets, binding)
-    }
-
-    pub unsafe fn reset_command_buffers(&self) -> Result<()> {
-        self.command_buffers.iter().for_each(|command_buffer: &vk::CommandBuffer| {
-            command_buffers::reset_command_buffer(&self.device, command_buffer.clone()).unwrap()
-        });
-        Ok(())
-    }
 }
 
 pub mod graphics_utility {
@@ -762,30 +1548,91 @@ pub mod graphics_utility {
     };
 
     use super::{
-        CPUMesh, GPUMesh, Graphics, Image, ImageSampler, LoadedImage, UniformBufferSeries,
+        CPUMesh, ComputePipeline, CubemapImage, GPUMesh, Graphics, Image, ImageSampler,
+        LoadedImage, Owned, ParticleBufferSeries, SamplerDescription, StorageBuffer,
+        UniformBufferSeries,
     };
+    use vulkanalia::vk;
 
     pub fn descriptor_writer_write(graphics: &mut Graphics) {
         graphics.descriptor_writer.write(&graphics.device);
     }
 
     impl UniformBufferSeries {
-        pub unsafe fn create_from_graphics<T>(graphics: &Graphics) -> Result<Self> {
-            uniform_buffer::create_series::<T>(
-                &graphics.instance,
-                &graphics.device,
-                graphics.physical_device,
-                graphics.swapchain.get_length(),
-            )
+        pub fn create_from_graphics<T>(graphics: &Graphics) -> Result<Owned<Self>> {
+            let series = unsafe {
+                uniform_buffer::create_series::<T>(
+                    &graphics.instance,
+                    &graphics.device,
+                    graphics.physical_device,
+                    graphics.swapchain.get_length(),
+                )?
+            };
+            Ok(graphics.deletion_queue.own(series))
+        }
+    }
+
+    impl ParticleBufferSeries {
+        /// Buffers for up to `capacity` particles in every frame.
+        pub fn create_from_graphics(graphics: &Graphics, capacity: usize) -> Result<Owned<Self>> {
+            let series = unsafe {
+                ParticleBufferSeries::create(
+                    &graphics.instance,
+                    &graphics.device,
+                    graphics.physical_device,
+                    graphics.swapchain.get_length(),
+                    capacity,
+                )?
+            };
+            Ok(graphics.deletion_queue.own(series))
+        }
+    }
+
+    impl ComputePipeline {
+        /// `bindings` are the descriptor types of set 0 of the shader, binding 0 first.
+        pub fn create_from_graphics(
+            graphics: &Graphics,
+            spirv: &[u8],
+            bindings: &[vk::DescriptorType],
+        ) -> Result<Owned<Self>> {
+            let pipeline = unsafe {
+                ComputePipeline::create(
+                    &graphics.device,
+                    graphics.pipeline_cache.get(),
+                    spirv,
+                    bindings,
+                )?
+            };
+            Ok(graphics.deletion_queue.own(pipeline))
+        }
+    }
+
+    impl StorageBuffer {
+        pub fn create_from_graphics(graphics: &Graphics, size: u64) -> Result<Owned<Self>> {
+            let buffer = unsafe {
+                StorageBuffer::create(
+                    &graphics.instance,
+                    &graphics.device,
+                    graphics.physical_device,
+                    size,
+                )?
+            };
+            Ok(graphics.deletion_queue.own(buffer))
         }
 
-        pub unsafe fn destroy_uniform_buffer_series(&self, graphics: &Graphics) {
-            uniform_buffer::destroy_series(&graphics.device, self);
+        /// Everything compute shaders wrote into the buffer. Call once the dispatches
+        /// finished, after [`Graphics::device_wait_idle`] for example.
+        pub fn read_from_graphics<T: Copy>(&self, graphics: &Graphics) -> Result<Vec<T>> {
+            unsafe { self.read(&graphics.device) }
+        }
+
+        pub fn write_from_graphics<T: Copy>(&self, graphics: &Graphics, data: &[T]) -> Result<()> {
+            unsafe { self.write(&graphics.device, data) }
         }
     }
 
     impl CPUMesh {
-        pub unsafe fn load_from_obj<P>(graphics: &Graphics, path: P) -> Vec<Self>
+        pub fn load_from_obj<P>(path: P) -> Vec<Self>
         where
             P: AsRef<Path> + Debug,
         {
@@ -847,64 +1694,94 @@ pub mod graphics_utility {
     }
 
     impl GPUMesh {
-        pub unsafe fn create(graphics: &Graphics, mesh: &CPUMesh) -> Result<Self> {
-            let vertex_buffer = VertexBuffer::create(
-                &graphics.instance,
-                &graphics.device,
-                graphics.physical_device,
-                graphics.command_pool,
-                graphics.graphics_queue,
-                &mesh.vertices,
-            )?;
+        pub fn create(graphics: &Graphics, mesh: &CPUMesh) -> Result<Owned<Self>> {
+            let (vertex_buffer, _) = unsafe {
+                VertexBuffer::create(
+                    &graphics.instance,
+                    &graphics.device,
+                    graphics.physical_device,
+                    &graphics.upload_context,
+                    &mesh.vertices,
+                )?
+            };
 
-            let index_buffer = IndexBuffer::create(
-                &graphics.instance,
-                &graphics.device,
-                graphics.physical_device,
-                graphics.command_pool,
-                graphics.graphics_queue,
-                &mesh.indices,
-            )?;
+            // Recorded after the vertices, so it lands in the same batch or a later one
+            let (index_buffer, upload) = unsafe {
+                IndexBuffer::create(
+                    &graphics.instance,
+                    &graphics.device,
+                    graphics.physical_device,
+                    &graphics.upload_context,
+                    &mesh.indices,
+                )?
+            };
 
             let triangles_count: usize = mesh.indices.len() / 3;
 
-            Ok(Self {
+            Ok(graphics.deletion_queue.own(Self {
                 triangles_count,
                 vertex_buffer,
                 index_buffer,
-            })
-        }
-
-        pub unsafe fn destroy(&self, graphics: &Graphics) {
-            VertexBuffer::destroy(self.vertex_buffer, &graphics.device);
-            IndexBuffer::destroy(self.index_buffer, &graphics.device);
+                upload,
+            }))
         }
     }
 
     impl LoadedImage {
-        pub unsafe fn create(graphics: &Graphics, image: &Image) -> Result<Self> {
-            LoadedImage::load_into_memory(
-                &image,
-                &graphics.instance,
-                &graphics.device,
-                graphics.physical_device,
-                graphics.graphics_queue,
-                graphics.command_pool,
-            )
+        pub fn create(graphics: &Graphics, image: &Image) -> Result<Owned<Self>> {
+            let loaded_image = unsafe {
+                LoadedImage::load_into_memory(
+                    image,
+                    &graphics.instance,
+                    &graphics.device,
+                    graphics.physical_device,
+                    &graphics.upload_context,
+                )?
+            };
+            Ok(graphics.deletion_queue.own(loaded_image))
         }
 
-        pub unsafe fn destroy_with_graphics(&self, graphics: &Graphics) {
-            self.destroy(&graphics.device)
+        pub fn create_cubemap(graphics: &Graphics, cubemap: &CubemapImage) -> Result<Owned<Self>> {
+            let loaded_image = unsafe {
+                LoadedImage::load_cubemap_into_memory(
+                    cubemap,
+                    &graphics.instance,
+                    &graphics.device,
+                    graphics.physical_device,
+                    &graphics.upload_context,
+                )?
+            };
+            Ok(graphics.deletion_queue.own(loaded_image))
         }
-    }
 
-    impl ImageSampler {
-        pub unsafe fn create_from_graphics(graphics: &Graphics) -> Result<Self> {
-            Self::create(&graphics.device)
+        /// An image compute shaders write into, which can be sampled as well.
+        pub fn create_storage(
+            graphics: &Graphics,
+            format: vk::Format,
+            extent: vk::Extent2D,
+        ) -> Result<Owned<Self>> {
+            let loaded_image = unsafe {
+                LoadedImage::create_storage_image(
+                    &graphics.instance,
+                    &graphics.device,
+                    graphics.physical_device,
+                    graphics.graphics_queue,
+                    graphics.command_pool,
+                    format,
+                    extent,
+                )?
+            };
+            Ok(graphics.deletion_queue.own(loaded_image))
         }
+    }
 
-        pub unsafe fn destroy_with_graphics(&self, graphics: &Graphics) {
-            self.destroy(&graphics.device)
+    impl ImageSampler {
+        /// Get the sampler shared by every texture using `description`.
+        pub fn get_from_graphics(
+            graphics: &mut Graphics,
+            description: &SamplerDescription,
+        ) -> Result<Self> {
+            graphics.get_image_sampler(description)
         }
     }
 }
@@ -2,23 +2,30 @@ use anyhow::Result;
 use std::collections::HashSet;
 use vulkanalia::prelude::v1_0::*;
 
+use super::physical_device::OptionalFeatures;
 use super::queue_families::QueueFamilyIndices;
 use super::validation_layers::*;
 
-use crate::core::config::{DEVICE_EXTENSIONS, PORTABILITY_MACOS_VERSION};
+use crate::core::config::{DEVICE_EXTENSIONS, PORTABILITY_MACOS_VERSION, USE_DEDICATED_TRANSFER_QUEUE};
 
+/// A queue and the family it belongs to.
+pub type QueueWithFamily = (vk::Queue, u32);
+
+/// The transfer queue is only created when the device has a dedicated one.
 pub unsafe fn create_logical_device(
     entry: &Entry,
     instance: &Instance,
     window_surface: vk::SurfaceKHR,
     physical_device: vk::PhysicalDevice,
-)-> Result<(Device, vk::Queue, vk::Queue)> {
+)-> Result<(Device, vk::Queue, vk::Queue, Option<QueueWithFamily>)> {
 
     let indices = QueueFamilyIndices::get(instance, window_surface, physical_device)?;
+    let transfer_family = indices.transfer.filter(|_| USE_DEDICATED_TRANSFER_QUEUE);
 
     let mut unique_indices = HashSet::new();
     unique_indices.insert(indices.graphics);
     unique_indices.insert(indices.present);
+    unique_indices.extend(transfer_family);
 
     let queue_priorities = &[1.0];
     let queue_infos = unique_indices
@@ -42,8 +49,11 @@ pub unsafe fn create_logical_device(
         extensions.push(vk::KHR_PORTABILITY_SUBSET_EXTENSION.name.as_ptr());
     }
 
+    let optional_features = OptionalFeatures::get(instance, physical_device);
+
     let features = vk::PhysicalDeviceFeatures::builder()
-        .sampler_anisotropy(true); // allows for anisotropy to be used on image samplers
+        .sampler_anisotropy(optional_features.sampler_anisotropy) // samplers fall back to plain filtering without it
+        .texture_compression_bc(optional_features.texture_compression_bc); // BC textures are decoded on the CPU without it
 
     let info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_infos)
@@ -55,8 +65,9 @@ pub unsafe fn create_logical_device(
 
     let graphics_queue = device.get_device_queue(indices.graphics, 0);
     let present_queue = device.get_device_queue(indices.present, 0);
+    let transfer = transfer_family.map(|family| (device.get_device_queue(family, 0), family));
 
-    Ok((device, graphics_queue, present_queue))
+    Ok((device, graphics_queue, present_queue, transfer))
 }
 
 pub unsafe fn destroy_logical_device(
@@ -0,0 +1,131 @@
+use anyhow::{anyhow, Result};
+use cgmath::{vec2, vec3, Vector3};
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+
+use super::graphics::CPUMesh;
+use super::wrappers::Vertex;
+
+/// Every object of an OBJ file together with the material it uses.
+pub struct ObjModel {
+    pub sub_meshes: Vec<ObjSubMesh>,
+}
+
+pub struct ObjSubMesh {
+    pub name: String,
+    pub cpu_mesh: CPUMesh,
+    pub material: Option<ObjMaterial>,
+}
+
+/// The parts of an MTL material that we can draw.
+#[derive(Clone, Debug)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub diffuse_color: Vector3<f32>,
+    pub dissolve: f32,
+    /// `map_Kd`, resolved relative to the OBJ file
+    pub diffuse_texture: Option<PathBuf>,
+}
+
+impl ObjModel {
+    /// Load an OBJ file and the MTL libraries it references.
+    /// A missing or broken MTL file only loses the materials, not the geometry.
+    pub fn load(path: &Path) -> Result<Self> {
+        info!("Loading model at {:?}", path);
+
+        let (models, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)
+            .map_err(|e| anyhow!("Failed to load OBJ file {}: {}", path.display(), e))?;
+
+        let materials = materials.unwrap_or_else(|e| {
+            warn!("Failed to load materials of {}: {}", path.display(), e);
+            vec![]
+        });
+
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let materials: Vec<ObjMaterial> = materials
+            .iter()
+            .map(|material| convert_material(material, directory))
+            .collect();
+
+        let sub_meshes = models
+            .iter()
+            .map(|model| {
+                Ok(ObjSubMesh {
+                    name: model.name.clone(),
+                    cpu_mesh: convert_mesh(&model.mesh)?,
+                    material: model
+                        .mesh
+                        .material_id
+                        .and_then(|material_id| materials.get(material_id))
+                        .cloned(),
+                })
+            })
+            .collect::<Result<Vec<ObjSubMesh>>>()?;
+
+        info!(
+            "Loaded model with {} sub-meshes and {} materials",
+            sub_meshes.len(),
+            materials.len()
+        );
+
+        Ok(Self { sub_meshes })
+    }
+}
+
+fn convert_material(material: &tobj::Material, directory: &Path) -> ObjMaterial {
+    let diffuse_texture = match material.diffuse_texture.as_str() {
+        "" => None,
+        texture => Some(directory.join(texture)),
+    };
+
+    // Blender writes `d 0` for materials that take their alpha from `map_d`, which
+    // would leave them invisible
+    let dissolve = if material.dissolve <= 0.0 {
+        warn!(
+            "Material {} is fully dissolved, drawing it opaque instead",
+            material.name
+        );
+        1.0
+    } else {
+        material.dissolve
+    };
+
+    ObjMaterial {
+        name: material.name.clone(),
+        diffuse_color: material.diffuse.into(),
+        dissolve,
+        diffuse_texture,
+    }
+}
+
+fn convert_mesh(mesh: &tobj::Mesh) -> Result<CPUMesh> {
+    let vertex_count = mesh.positions.len() / 3;
+    if vertex_count > u16::MAX as usize + 1 {
+        return Err(anyhow!(
+            "Mesh has {} vertices, more than 16 bit indices can address",
+            vertex_count
+        ));
+    }
+
+    let has_uvs = mesh.texcoords.len() >= vertex_count * 2;
+    let vertices = (0..vertex_count)
+        .map(|v| {
+            let pos = vec3(
+                mesh.positions[3 * v],
+                mesh.positions[3 * v + 1],
+                mesh.positions[3 * v + 2],
+            );
+            // OBJ puts the texture origin at the bottom left, Vulkan at the top left
+            let uv = if has_uvs {
+                vec2(mesh.texcoords[2 * v], 1.0 - mesh.texcoords[2 * v + 1])
+            } else {
+                vec2(0.0, 0.0)
+            };
+            Vertex::new(pos, uv)
+        })
+        .collect();
+
+    let indices = mesh.indices.iter().map(|index| *index as u16).collect();
+
+    Ok(CPUMesh { vertices, indices })
+}
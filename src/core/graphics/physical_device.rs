@@ -2,62 +2,247 @@ use anyhow::{anyhow, Result};
 use log::*;
 use std::collections::HashSet;
 use vulkanalia::prelude::v1_0::*;
+use vulkanalia::vk::KhrSurfaceExtension;
 
 use super::errors::SuitabilityError;
 use super::queue_families::QueueFamilyIndices;
-use crate::core::config::DEVICE_EXTENSIONS;
+use crate::core::config::{DEVICE_EXTENSIONS, PHYSICAL_DEVICE_OVERRIDE_VARIABLE};
 
+/// Optional features, used when the picked device has them and worked around when
+/// it doesn't.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct OptionalFeatures {
+    pub sampler_anisotropy: bool,
+    pub texture_compression_bc: bool,
+}
+
+impl OptionalFeatures {
+    pub unsafe fn get(instance: &Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let features = instance.get_physical_device_features(physical_device);
+        Self {
+            sampler_anisotropy: features.sampler_anisotropy == vk::TRUE,
+            texture_compression_bc: features.texture_compression_bc == vk::TRUE,
+        }
+    }
+}
+
+/// Pick the suitable device with the highest score, unless one is forced through
+/// the environment variable named by [`PHYSICAL_DEVICE_OVERRIDE_VARIABLE`].
 pub unsafe fn pick_physical_device(
     instance: &Instance,
     window_surface: vk::SurfaceKHR,
 ) -> Result<vk::PhysicalDevice> {
-    for physical_device in instance.enumerate_physical_devices()? {
+    let physical_devices = instance.enumerate_physical_devices()?;
+
+    if let Some(device_override) = get_physical_device_override() {
+        return pick_overridden_physical_device(
+            instance,
+            window_surface,
+            &physical_devices,
+            &device_override,
+        );
+    }
+
+    let mut best: Option<(u32, vk::PhysicalDevice)> = None;
+    for (index, physical_device) in physical_devices.iter().cloned().enumerate() {
         let properties = instance.get_physical_device_properties(physical_device);
 
         if let Err(error) = check_physical_device(instance, window_surface, physical_device) {
             warn!(
-                "Skipping physical device (`{}`): {}",
-                properties.device_name, error
+                "Skipping physical device {} (`{}`): {}",
+                index, properties.device_name, error
             );
-        } else {
-            info!("Selected physical device (`{}`).", properties.device_name);
-            return Ok(physical_device);
+            continue;
+        }
+
+        let score = score_physical_device(instance, physical_device);
+        info!(
+            "Physical device {} (`{}`, {:?}) scored {}",
+            index, properties.device_name, properties.device_type, score
+        );
+        if best.is_none_or(|(best_score, _)| score > best_score) {
+            best = Some((score, physical_device));
         }
     }
 
-    Err(anyhow!("Failed to find suitable physical device."))
+    let (_, physical_device) =
+        best.ok_or_else(|| anyhow!("Failed to find suitable physical device."))?;
+    let properties = instance.get_physical_device_properties(physical_device);
+    info!("Selected physical device (`{}`).", properties.device_name);
+
+    Ok(physical_device)
 }
 
-/// Check a physical device to see if supports everything we need
-unsafe fn check_physical_device(
+/// The device forced through [`PHYSICAL_DEVICE_OVERRIDE_VARIABLE`], when it is set.
+pub fn get_physical_device_override() -> Option<String> {
+    std::env::var(PHYSICAL_DEVICE_OVERRIDE_VARIABLE)
+        .ok()
+        .map(|device_override| device_override.trim().to_string())
+        .filter(|device_override| !device_override.is_empty())
+}
+
+unsafe fn pick_overridden_physical_device(
+    instance: &Instance,
+    window_surface: vk::SurfaceKHR,
+    physical_devices: &[vk::PhysicalDevice],
+    device_override: &str,
+) -> Result<vk::PhysicalDevice> {
+    let index = find_overridden_physical_device(instance, physical_devices, device_override)?;
+    let physical_device = physical_devices[index];
+    let device_name = instance
+        .get_physical_device_properties(physical_device)
+        .device_name;
+    check_physical_device(instance, window_surface, physical_device).map_err(|error| {
+        anyhow!(
+            "Physical device {} (`{}`) forced by {} is not suitable: {}",
+            index,
+            device_name,
+            PHYSICAL_DEVICE_OVERRIDE_VARIABLE,
+            error
+        )
+    })?;
+
+    info!(
+        "Selected physical device {} (`{}`) forced by {}.",
+        index, device_name, PHYSICAL_DEVICE_OVERRIDE_VARIABLE
+    );
+    Ok(physical_device)
+}
+
+/// Index of the device `device_override` picks, either its index in enumeration
+/// order or part of its name.
+pub unsafe fn find_overridden_physical_device(
+    instance: &Instance,
+    physical_devices: &[vk::PhysicalDevice],
+    device_override: &str,
+) -> Result<usize> {
+    let device_names = physical_devices
+        .iter()
+        .map(|physical_device| {
+            instance
+                .get_physical_device_properties(*physical_device)
+                .device_name
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+
+    let index = match device_override.parse::<usize>() {
+        Ok(index) if index < physical_devices.len() => Some(index),
+        Ok(_) => None,
+        Err(_) => {
+            let device_override = device_override.to_lowercase();
+            device_names
+                .iter()
+                .position(|name| name.to_lowercase().contains(&device_override))
+        }
+    };
+
+    index.ok_or_else(|| {
+        anyhow!(
+            "{}={} matches none of the physical devices {:?}",
+            PHYSICAL_DEVICE_OVERRIDE_VARIABLE,
+            device_override,
+            device_names
+        )
+    })
+}
+
+/// Discrete GPUs beat integrated ones, which beat software rasterizers. Optional
+/// features only break ties between devices of the same kind.
+unsafe fn score_physical_device(instance: &Instance, physical_device: vk::PhysicalDevice) -> u32 {
+    let properties = instance.get_physical_device_properties(physical_device);
+    let features = OptionalFeatures::get(instance, physical_device);
+
+    let type_score = match properties.device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 1000,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 500,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 250,
+        vk::PhysicalDeviceType::CPU => 100,
+        _ => 0,
+    };
+
+    type_score
+        + if features.sampler_anisotropy { 10 } else { 0 }
+        + if features.texture_compression_bc { 10 } else { 0 }
+}
+
+/// Log what the picked device can do, to make bug reports from other machines useful.
+pub unsafe fn log_capability_report(
     instance: &Instance,
     window_surface: vk::SurfaceKHR,
     physical_device: vk::PhysicalDevice,
 ) -> Result<()> {
     let properties = instance.get_physical_device_properties(physical_device);
-    // if properties.device_type != vk::PhysicalDeviceType::DISCRETE_GPU {
-    //     return Err(anyhow!(SuitabilityError(
-    //         "Only discrete GPUs are supported."
-    //     )));
-    // }
-
-    let features = instance.get_physical_device_features(physical_device);
-    if features.sampler_anisotropy != vk::TRUE {
-        return Err(anyhow!(SuitabilityError("No sampler anisotropy.")));
-    }
-    // if features.geometry_shader != vk::TRUE {
-    //     return Err(anyhow!(SuitabilityError(
-    //         "Missing geometry shader support."
-    //     )));
-    // }
+    let limits = properties.limits;
+    let features = OptionalFeatures::get(instance, physical_device);
+
+    info!(
+        "Device `{}` ({:?}), Vulkan {}, driver {:#x}, vendor {:#x}",
+        properties.device_name,
+        properties.device_type,
+        vulkanalia::Version::from(properties.api_version),
+        properties.driver_version,
+        properties.vendor_id,
+    );
+    info!(
+        "Limits: 2D images up to {}, {} bound descriptor sets, {} bytes of push constants, \
+        {} bytes per uniform buffer, anisotropy up to {}",
+        limits.max_image_dimension_2d,
+        limits.max_bound_descriptor_sets,
+        limits.max_push_constants_size,
+        limits.max_uniform_buffer_range,
+        limits.max_sampler_anisotropy,
+    );
+    info!("Optional features: {:?}", features);
+
+    let surface_formats =
+        instance.get_physical_device_surface_formats_khr(physical_device, window_surface)?;
+    info!(
+        "Surface formats: {:?}",
+        surface_formats
+            .iter()
+            .map(|format| (format.format, format.color_space))
+            .collect::<Vec<_>>()
+    );
+    let present_modes =
+        instance.get_physical_device_surface_present_modes_khr(physical_device, window_surface)?;
+    info!("Present modes: {:?}", present_modes);
 
+    const TEXTURE_FORMATS: &[vk::Format] = &[
+        vk::Format::R8G8B8A8_SRGB,
+        vk::Format::BC1_RGBA_SRGB_BLOCK,
+        vk::Format::BC3_SRGB_BLOCK,
+        vk::Format::BC7_SRGB_BLOCK,
+    ];
+    let sampled_formats = TEXTURE_FORMATS
+        .iter()
+        .filter(|format| {
+            instance
+                .get_physical_device_format_properties(physical_device, **format)
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+        })
+        .collect::<Vec<_>>();
+    info!("Sampled texture formats: {:?}", sampled_formats);
+
+    Ok(())
+}
+
+/// Check a physical device to see if supports everything we need
+unsafe fn check_physical_device(
+    instance: &Instance,
+    window_surface: vk::SurfaceKHR,
+    physical_device: vk::PhysicalDevice,
+) -> Result<()> {
     QueueFamilyIndices::get(instance, window_surface, physical_device)?;
     check_physical_device_extensions(instance, physical_device)?;
 
-    // let support = SwapchainSupport::get(instance, data, physical_device)?;
-    // if support.formats.is_empty() || support.present_modes.is_empty() {
-    //     return Err(anyhow!(SuitabilityError("Insufficient swapchain support")));
-    // }
+    let formats = instance.get_physical_device_surface_formats_khr(physical_device, window_surface)?;
+    let present_modes =
+        instance.get_physical_device_surface_present_modes_khr(physical_device, window_surface)?;
+    if formats.is_empty() || present_modes.is_empty() {
+        return Err(anyhow!(SuitabilityError("swapchain support")));
+    }
 
     Ok(())
 }
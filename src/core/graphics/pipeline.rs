@@ -1,7 +1,7 @@
 use anyhow::Result;
 use vulkanalia::prelude::v1_0::*;
 
-use super::{shader, wrappers::Vertex};
+use super::{shader, wrappers::{ParticleInstance, Vertex}};
 
 // pub static VERTICES: [Vertex; 4] = [
 //     // Vertex::new(vec3(-0.5, -0.5, 0.0), vec3(1.0, 0.0, 0.0)),
@@ -16,25 +16,134 @@ use super::{shader, wrappers::Vertex};
 
 pub static INDICES : &[u16] = &[0, 1, 2, 2, 3, 0];
 
+/// How a pipeline combines its output with what is already in the framebuffer.
+/// Opaque (and alpha-cutout) geometry writes depth and overwrites the color,
+/// translucent geometry blends on top and leaves the depth buffer untouched.
+/// Decals blend like translucent geometry, but are pulled towards the camera so
+/// they win the depth test against the surface they lie on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Opaque,
+    Translucent,
+    Decal,
+}
+
+/// Depth bias of decals, in units of the smallest depth difference and scaled by
+/// the slope of the polygon. Negative moves them towards the camera.
+const DECAL_DEPTH_BIAS: (f32, f32) = (-4.0, -1.5);
+
+pub unsafe fn create_pipeline_layout(
+    device: &Device,
+    set_layouts: &[vk::DescriptorSetLayout],
+) -> Result<vk::PipelineLayout> {
+    let layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(set_layouts);
+    log::info!("Create pipeline layout with {} descriptors.", layout_info.set_layout_count);
+
+    let pipeline_layout: vk::PipelineLayout = device.create_pipeline_layout(&layout_info, None)?;
+    Ok(pipeline_layout)
+}
+
+/// The fixed function state that differs between the pipelines we build.
+struct PipelineDescription<'a> {
+    name: &'a str,
+    vert: &'a [u8],
+    frag: &'a [u8],
+    vertex_bindings: &'a [vk::VertexInputBindingDescription],
+    vertex_attributes: &'a [vk::VertexInputAttributeDescription],
+    cull_mode: vk::CullModeFlags,
+    blend_enable: bool,
+    depth_write_enable: bool,
+    depth_compare_op: vk::CompareOp,
+    /// Constant and slope factor
+    depth_bias: Option<(f32, f32)>,
+}
+
 pub unsafe fn create_pipeline(
     device: &Device, 
-    swapchain_extent: vk::Extent2D, 
-    set_layouts: &[vk::DescriptorSetLayout],
+    pipeline_cache: vk::PipelineCache,
+    pipeline_layout: vk::PipelineLayout,
     render_pass: vk::RenderPass,
-) -> Result<(vk::PipelineLayout, vk::Pipeline)> {
+    blend_mode: BlendMode,
+) -> Result<vk::Pipeline> {
+    let is_opaque = blend_mode == BlendMode::Opaque;
+    let is_decal = blend_mode == BlendMode::Decal;
+    let name = format!("{:?}", blend_mode);
+
+    create_graphics_pipeline(device, pipeline_cache, pipeline_layout, render_pass, PipelineDescription {
+        name: &name,
+        vert: include_bytes!("../../../shaders_compiled/vert.spv"),
+        frag: include_bytes!("../../../shaders_compiled/frag.spv"),
+        vertex_bindings: &[Vertex::binding_description()],
+        vertex_attributes: &Vertex::attribute_descriptions(),
+        cull_mode: vk::CullModeFlags::BACK,
+        blend_enable: !is_opaque,
+        depth_write_enable: is_opaque,
+        depth_compare_op: if is_decal { vk::CompareOp::LESS_OR_EQUAL } else { vk::CompareOp::LESS },
+        depth_bias: is_decal.then_some(DECAL_DEPTH_BIAS),
+    })
+}
 
-    let vert = include_bytes!("../../../shaders_compiled/vert.spv");
-    let frag = include_bytes!("../../../shaders_compiled/frag.spv");
+/// The skybox is drawn from the inside of a cube pushed to the far plane,
+/// so it passes the depth test only where nothing else has been drawn.
+pub unsafe fn create_skybox_pipeline(
+    device: &Device, 
+    pipeline_cache: vk::PipelineCache,
+    pipeline_layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+) -> Result<vk::Pipeline> {
+    create_graphics_pipeline(device, pipeline_cache, pipeline_layout, render_pass, PipelineDescription {
+        name: "Skybox",
+        vert: include_bytes!("../../../shaders_compiled/skybox_vert.spv"),
+        frag: include_bytes!("../../../shaders_compiled/skybox_frag.spv"),
+        vertex_bindings: &[Vertex::binding_description()],
+        vertex_attributes: &Vertex::attribute_descriptions(),
+        cull_mode: vk::CullModeFlags::NONE,
+        blend_enable: false,
+        depth_write_enable: false,
+        depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+        depth_bias: None,
+    })
+}
+
+/// Particles are camera facing quads, one per instance, blended over the scene
+/// without writing depth.
+pub unsafe fn create_particle_pipeline(
+    device: &Device, 
+    pipeline_cache: vk::PipelineCache,
+    pipeline_layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+) -> Result<vk::Pipeline> {
+    create_graphics_pipeline(device, pipeline_cache, pipeline_layout, render_pass, PipelineDescription {
+        name: "Particle",
+        vert: include_bytes!("../../../shaders_compiled/particle_vert.spv"),
+        frag: include_bytes!("../../../shaders_compiled/particle_frag.spv"),
+        vertex_bindings: &[ParticleInstance::binding_description()],
+        vertex_attributes: &ParticleInstance::attribute_descriptions(),
+        cull_mode: vk::CullModeFlags::NONE,
+        blend_enable: true,
+        depth_write_enable: false,
+        depth_compare_op: vk::CompareOp::LESS,
+        depth_bias: None,
+    })
+}
+
+unsafe fn create_graphics_pipeline(
+    device: &Device, 
+    pipeline_cache: vk::PipelineCache,
+    pipeline_layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+    description: PipelineDescription,
+) -> Result<vk::Pipeline> {
+    let vert = description.vert;
+    let frag = description.frag;
 
     let vert_shader_module = shader::create_shader_module(device, vert)?;
     let frag_shader_module = shader::create_shader_module(device, frag)?;
 
-    let binding_description: &[vk::VertexInputBindingDescription; 1] 
-        = &[Vertex::binding_description()];
-    let attribute_descriptions = &Vertex::attribute_descriptions();
     let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
-        .vertex_binding_descriptions(binding_description)
-        .vertex_attribute_descriptions(attribute_descriptions);
+        .vertex_binding_descriptions(description.vertex_bindings)
+        .vertex_attribute_descriptions(description.vertex_attributes);
 
     let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
         .stage(vk::ShaderStageFlags::VERTEX)
@@ -50,32 +159,26 @@ pub unsafe fn create_pipeline(
         .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
         .primitive_restart_enable(false);
 
-    let viewport = vk::Viewport::builder()
-        .x(0.0)
-        .y(0.0)
-        .width(swapchain_extent.width as f32)
-        .height(swapchain_extent.height as f32)
-        .min_depth(0.0)
-        .max_depth(1.0);
-
-    let scissor = vk::Rect2D::builder()
-        .offset(vk::Offset2D {x: 0, y: 0})
-        .extent(swapchain_extent);
-
-    let viewports = &[viewport];
-    let scissors = &[scissor];
+    // Set while recording, so one pipeline serves every camera viewport
     let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
-        .viewports(viewports)
-        .scissors(scissors);
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+        .dynamic_states(dynamic_states);
 
+    let (depth_bias_constant_factor, depth_bias_slope_factor) = description.depth_bias.unwrap_or_default();
     let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
         .depth_clamp_enable(false)
         .rasterizer_discard_enable(false)
         .polygon_mode(vk::PolygonMode::FILL)
         .line_width(1.0)
-        .cull_mode(vk::CullModeFlags::BACK)
+        .cull_mode(description.cull_mode)
         .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-        .depth_bias_enable(false);
+        .depth_bias_enable(description.depth_bias.is_some())
+        .depth_bias_constant_factor(depth_bias_constant_factor)
+        .depth_bias_slope_factor(depth_bias_slope_factor);
 
     let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
         .sample_shading_enable(false)
@@ -83,7 +186,7 @@ pub unsafe fn create_pipeline(
 
     let attachment = vk::PipelineColorBlendAttachmentState::builder()
         .color_write_mask(vk::ColorComponentFlags::all())
-        .blend_enable(true)
+        .blend_enable(description.blend_enable)
         .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
         .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
         .color_blend_op(vk::BlendOp::ADD)
@@ -99,18 +202,14 @@ pub unsafe fn create_pipeline(
         .blend_constants([0.0, 0.0, 0.0, 0.0]);
     let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
         .depth_test_enable(true)
-        .depth_write_enable(true)
-        .depth_compare_op(vk::CompareOp::LESS)
+        .depth_write_enable(description.depth_write_enable)
+        .depth_compare_op(description.depth_compare_op)
         .depth_bounds_test_enable(false)
         .min_depth_bounds(0.0)
         .max_depth_bounds(1.0)
         .stencil_test_enable(false);
 
-    let layout_info = vk::PipelineLayoutCreateInfo::builder()
-        .set_layouts(set_layouts);
-    log::info!("Create pipeline with layout of {} descriptors.", layout_info.set_layout_count);
-
-    let pipeline_layout: vk::PipelineLayout = device.create_pipeline_layout(&layout_info, None)?;
+    log::info!("Create {} pipeline.", description.name);
 
     let stages = &[vert_stage, frag_stage];
     let info = vk::GraphicsPipelineCreateInfo::builder()
@@ -122,21 +221,25 @@ pub unsafe fn create_pipeline(
         .multisample_state(&multisample_state)
         .color_blend_state(&color_blend_state)
         .depth_stencil_state(&depth_stencil_state)
+        .dynamic_state(&dynamic_state)
         .layout(pipeline_layout)
         .render_pass(render_pass)
         .subpass(0)
         .base_pipeline_handle(vk::Pipeline::null())
         .base_pipeline_index(-1);
 
-    let pipeline: vk::Pipeline = device.create_graphics_pipelines(vk::PipelineCache::null(), &[info], None)?.0[0];
+    let pipeline: vk::Pipeline = device.create_graphics_pipelines(pipeline_cache, &[info], None)?.0[0];
 
     shader::destroy_shader_module(device, vert_shader_module);
     shader::destroy_shader_module(device, frag_shader_module);
 
-    Ok((pipeline_layout, pipeline))
+    Ok(pipeline)
 }
 
-pub unsafe fn destroy_pipeline(device: &Device, pipeline: vk::Pipeline, pipeline_layout: vk::PipelineLayout) {
+pub unsafe fn destroy_pipeline(device: &Device, pipeline: vk::Pipeline) {
     device.destroy_pipeline(pipeline, None);
+}
+
+pub unsafe fn destroy_pipeline_layout(device: &Device, pipeline_layout: vk::PipelineLayout) {
     device.destroy_pipeline_layout(pipeline_layout, None);
 }
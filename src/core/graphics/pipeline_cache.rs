@@ -0,0 +1,159 @@
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use vulkanalia::prelude::v1_0::*;
+
+use crate::core::config::{DATA_DIRECTORY_NAME, PIPELINE_CACHE_FILE_NAME};
+
+const MAGIC: &[u8; 8] = b"SAGAPSO1";
+
+/// What the cached data was built with. Drivers reject or, worse, misbehave on data
+/// from another device or driver version, so anything else starts an empty cache.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct CacheHeader {
+    vendor_id: u32,
+    device_id: u32,
+    driver_version: u32,
+    pipeline_cache_uuid: [u8; vk::UUID_SIZE],
+}
+
+impl CacheHeader {
+    const SIZE: usize = MAGIC.len() + 3 * 4 + vk::UUID_SIZE;
+
+    unsafe fn get(instance: &Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let properties = instance.get_physical_device_properties(physical_device);
+        Self {
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            driver_version: properties.driver_version,
+            pipeline_cache_uuid: properties.pipeline_cache_uuid.into(),
+        }
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SIZE);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.vendor_id.to_le_bytes());
+        bytes.extend_from_slice(&self.device_id.to_le_bytes());
+        bytes.extend_from_slice(&self.driver_version.to_le_bytes());
+        bytes.extend_from_slice(&self.pipeline_cache_uuid);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..Self::SIZE)?.strip_prefix(MAGIC)?;
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+        };
+        Some(Self {
+            vendor_id: read_u32(0),
+            device_id: read_u32(4),
+            driver_version: read_u32(8),
+            pipeline_cache_uuid: bytes[12..].try_into().unwrap(),
+        })
+    }
+}
+
+/// A `vk::PipelineCache` kept in a file in the user data directory between runs,
+/// so pipelines aren't compiled from SPIR-V again at every launch.
+pub struct PipelineCache {
+    cache: vk::PipelineCache,
+    header: CacheHeader,
+    path: Option<PathBuf>,
+}
+
+impl PipelineCache {
+    pub unsafe fn load(
+        instance: &Instance,
+        device: &Device,
+        physical_device: vk::PhysicalDevice,
+    ) -> Result<Self> {
+        let header = CacheHeader::get(instance, physical_device);
+        let path = dirs::data_dir()
+            .map(|directory| directory.join(DATA_DIRECTORY_NAME).join(PIPELINE_CACHE_FILE_NAME));
+
+        let initial_data = match &path {
+            Some(path) => read_cache_file(path, &header),
+            None => {
+                warn!("No user data directory, pipelines will not be cached between runs");
+                Vec::new()
+            }
+        };
+
+        let info = vk::PipelineCacheCreateInfo::builder().initial_data(&initial_data);
+        let cache = match device.create_pipeline_cache(&info, None) {
+            Ok(cache) => cache,
+            // Should the driver still choke on the data, compile everything again
+            Err(e) if !initial_data.is_empty() => {
+                warn!("Pipeline cache data rejected ({}), starting empty", e);
+                let info = vk::PipelineCacheCreateInfo::builder();
+                device.create_pipeline_cache(&info, None)?
+            }
+            Err(e) => return Err(anyhow!(e)),
+        };
+
+        Ok(Self {
+            cache,
+            header,
+            path,
+        })
+    }
+
+    pub fn get(&self) -> vk::PipelineCache {
+        self.cache
+    }
+
+    /// Write everything compiled so far to the cache file.
+    pub unsafe fn save(&self, device: &Device) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let data = device.get_pipeline_cache_data(self.cache)?;
+        let mut bytes = self.header.to_bytes();
+        bytes.extend_from_slice(&data);
+
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        // Write next to the cache first, so a crash never leaves a truncated file
+        let temporary_path = path.with_extension("tmp");
+        fs::write(&temporary_path, &bytes)?;
+        fs::rename(&temporary_path, path)?;
+
+        info!("Saved {} bytes of pipeline cache to {:?}", data.len(), path);
+        Ok(())
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_pipeline_cache(self.cache, None);
+    }
+}
+
+/// The cached data in `path`, or nothing if it is missing or was made for another
+/// device or driver.
+fn read_cache_file(path: &Path, header: &CacheHeader) -> Vec<u8> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            info!("No pipeline cache loaded from {:?}: {}", path, e);
+            return Vec::new();
+        }
+    };
+
+    match CacheHeader::from_bytes(&bytes) {
+        Some(file_header) if file_header == *header => {
+            info!("Loaded {} bytes of pipeline cache from {:?}", bytes.len() - CacheHeader::SIZE, path);
+            bytes[CacheHeader::SIZE..].to_vec()
+        }
+        Some(_) => {
+            info!("Pipeline cache was made for another device or driver, starting empty");
+            Vec::new()
+        }
+        None => {
+            warn!("Pipeline cache file {:?} is not valid, starting empty", path);
+            Vec::new()
+        }
+    }
+}
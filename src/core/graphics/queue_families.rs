@@ -8,6 +8,11 @@ use super::errors::SuitabilityError;
 pub struct QueueFamilyIndices {
     pub graphics: u32,
     pub present: u32,
+    /// A family that can only copy, which uploads use when there is one
+    pub transfer: Option<u32>,
+    /// The graphics family when it can dispatch as well, so frames record their compute
+    /// work alongside the drawing. Otherwise the first family that can.
+    pub compute: Option<u32>,
 }
 
 impl QueueFamilyIndices {
@@ -24,6 +29,20 @@ impl QueueFamilyIndices {
             .position(|p| p.queue_flags.contains(vk::QueueFlags::GRAPHICS))
             .map(|i| i as u32);
 
+        let compute = graphics
+            .filter(|&graphics| {
+                properties[graphics as usize].queue_flags.contains(vk::QueueFlags::COMPUTE)
+            })
+            .or_else(|| get_compute_family(&properties));
+
+        let transfer = properties
+            .iter()
+            .position(|p| {
+                p.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                    && !p.queue_flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            })
+            .map(|i| i as u32);
+
         let mut present = None;
 
         for (index, properties) in properties.iter().enumerate() {
@@ -35,9 +54,17 @@ impl QueueFamilyIndices {
         }
 
         if let (Some(graphics), Some(present)) = (graphics, present) {
-            Ok(Self { graphics, present })
+            Ok(Self { graphics, present, transfer, compute })
         } else {
             Err(anyhow!(SuitabilityError("Missing required queue families.")))
         }
     }
 }
+
+/// The first family that can dispatch compute shaders, for work that needs no surface.
+pub fn get_compute_family(properties: &[vk::QueueFamilyProperties]) -> Option<u32> {
+    properties
+        .iter()
+        .position(|p| p.queue_flags.contains(vk::QueueFlags::COMPUTE))
+        .map(|i| i as u32)
+}
@@ -0,0 +1,136 @@
+use anyhow::Result;
+use std::sync::Arc;
+use vulkanalia::prelude::v1_0::*;
+
+use super::deletion_queue::{DeletionQueue, Owned};
+use super::wrappers::{DepthBuffer, LoadedImage};
+
+/// Part of a render target, as fractions of its size with the origin at the top left.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ViewportRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl ViewportRect {
+    pub const FULL: Self = Self {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// The pixels covered within a target of the given extent. Neighbouring rects
+    /// round to the same edge, so split screens have neither gaps nor overlap.
+    pub fn get_pixel_rect(&self, extent: vk::Extent2D) -> vk::Rect2D {
+        let to_pixels = |fraction: f32, size: u32| {
+            (fraction.clamp(0.0, 1.0) * size as f32).round() as u32
+        };
+        let left = to_pixels(self.x, extent.width);
+        let top = to_pixels(self.y, extent.height);
+        let right = to_pixels(self.x + self.width, extent.width).max(left);
+        let bottom = to_pixels(self.y + self.height, extent.height).max(top);
+
+        vk::Rect2D {
+            offset: vk::Offset2D {
+                x: left as i32,
+                y: top as i32,
+            },
+            extent: vk::Extent2D {
+                width: right - left,
+                height: bottom - top,
+            },
+        }
+    }
+}
+
+impl Default for ViewportRect {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+/// An offscreen color and depth image pair that cameras can render into, through a
+/// pass of the frame graph. The color image can be bound to meshes like any loaded
+/// texture, and outlives the target for as long as a mesh still holds it. Clones
+/// share the images, which the frame graph holds on to while it draws into them.
+#[derive(Clone)]
+pub struct RenderTarget {
+    color: Arc<Owned<LoadedImage>>,
+    depth: Arc<Owned<DepthBuffer>>,
+    format: vk::Format,
+    extent: vk::Extent2D,
+}
+
+impl RenderTarget {
+    pub unsafe fn new(
+        instance: &Instance,
+        device: &Device,
+        physical_device: vk::PhysicalDevice,
+        graphics_queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        deletion_queue: &DeletionQueue,
+        color_format: vk::Format,
+        extent: vk::Extent2D,
+    ) -> Result<Self> {
+        let color = LoadedImage::create_render_target(
+            instance,
+            device,
+            physical_device,
+            graphics_queue,
+            command_pool,
+            color_format,
+            extent,
+        )?;
+        let color = Arc::new(deletion_queue.own(color));
+        let depth = Arc::new(deletion_queue.own(DepthBuffer::new(
+            instance,
+            device,
+            physical_device,
+            extent,
+            graphics_queue,
+            command_pool,
+        )?));
+
+        Ok(Self {
+            color,
+            depth,
+            format: color_format,
+            extent,
+        })
+    }
+
+    pub fn get_texture(&self) -> &Arc<Owned<LoadedImage>> {
+        &self.color
+    }
+
+    pub fn get_extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub fn get_format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn get_depth_image_view(&self) -> vk::ImageView {
+        self.depth.get_image_view()
+    }
+}
+
+/// Targets are the same when they share their images.
+impl PartialEq for RenderTarget {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.color, &other.color)
+    }
+}
@@ -4,25 +4,29 @@ use vulkanalia::vk::KhrSurfaceExtension;
 use vulkanalia::vk::KhrSwapchainExtension;
 use winit::window::Window;
 
+use super::deletion_queue::DeviceResource;
 use super::queue_families::QueueFamilyIndices;
 use super::wrappers::create_image_view;
 
-#[derive(Clone)]
 pub struct Swapchain {
     chain: vk::SwapchainKHR,
     format: vk::Format,
     extent: vk::Extent2D,
     images: Vec<vk::Image>,
     image_views: Vec<vk::ImageView>,
-    destroyed: bool, // marked true when swapchain is destroyed.
+    supports_capture: bool,
 }
 
 impl Swapchain {
+    /// `old_swapchain` is retired by the new one, and can be destroyed once it is
+    /// done presenting.
     pub unsafe fn new(window: &Window, instance: &Instance, device: &Device, 
-              surface: vk::SurfaceKHR, physical_device: vk::PhysicalDevice
+              surface: vk::SurfaceKHR, physical_device: vk::PhysicalDevice,
+              old_swapchain: vk::SwapchainKHR,
     ) -> Result<Self> {
-        let (swapchain, swapchain_images, swapchain_format, swapchain_extent)
-            = create_swapchain(window, &instance, &device, surface, physical_device)?;
+        let (swapchain, swapchain_images, swapchain_format, swapchain_extent, supports_capture)
+            = create_swapchain(window, instance, device, surface, physical_device,
+                old_swapchain)?;
         let swapchain_image_views = create_swapchain_image_views(
             device, &swapchain_images, swapchain_format)?;
         Ok(Self { 
@@ -31,7 +35,7 @@ impl Swapchain {
             extent: swapchain_extent, 
             images: swapchain_images, 
             image_views: swapchain_image_views,
-            destroyed: false,
+            supports_capture,
         })
     }
 
@@ -41,13 +45,16 @@ impl Swapchain {
     pub fn get_image_views(&self) -> &[vk::ImageView] { &self.image_views }
     pub fn get_images(&self) -> &[vk::Image] { &self.images }
     pub fn get_length(&self) -> usize { self.images.len() }
+    /// Whether images can be copied out of the swapchain, see [`super::capture`]
+    pub fn supports_capture(&self) -> bool { self.supports_capture }
 
-    pub unsafe fn destroy(&mut self, device: &Device) {
-        self.destroyed = true;
+}
+
+impl DeviceResource for Swapchain {
+    unsafe fn destroy(&self, device: &Device) {
         destroy_swapchain_and_image_views(
             device, self.chain, &self.image_views);
     }
-
 }
 
 #[derive(Clone, Debug)]
@@ -125,7 +132,8 @@ pub unsafe fn create_swapchain(
     device: &Device,
     window_surface: vk::SurfaceKHR,
     physical_device: vk::PhysicalDevice,
-) -> Result<(vk::SwapchainKHR, Vec<vk::Image>, vk::Format, vk::Extent2D)> {
+    old_swapchain: vk::SwapchainKHR,
+) -> Result<(vk::SwapchainKHR, Vec<vk::Image>, vk::Format, vk::Extent2D, bool)> {
 
     let indices = QueueFamilyIndices::get(instance, window_surface, physical_device)?;
     let support = SwapchainSupport::get(instance, window_surface, physical_device)?;
@@ -137,6 +145,15 @@ pub unsafe fn create_swapchain(
     let image_count = (support.capabilities.min_image_count + 1).max(
         support.capabilities.max_image_count.max(support.capabilities.min_image_count));
 
+    // Transfer source lets us copy presented frames out for screenshots
+    let supports_capture = support.capabilities.supported_usage_flags
+        .contains(vk::ImageUsageFlags::TRANSFER_SRC);
+    let image_usage = if supports_capture {
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC
+    } else {
+        vk::ImageUsageFlags::COLOR_ATTACHMENT
+    };
+
     let mut queue_family_indices = vec![];
     let image_sharing_mode = if indices.graphics != indices.present {
         queue_family_indices.push(indices.graphics);
@@ -153,21 +170,21 @@ pub unsafe fn create_swapchain(
         .image_color_space(surface_format.color_space)
         .image_extent(extent)
         .image_array_layers(1)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        .image_usage(image_usage)
         .image_sharing_mode(image_sharing_mode)
         .queue_family_indices(&queue_family_indices)
         .pre_transform(support.capabilities.current_transform)
         .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
         .present_mode(present_mode)
         .clipped(true)
-        .old_swapchain(vk::SwapchainKHR::null());
+        .old_swapchain(old_swapchain);
 
     let swapchain = device.create_swapchain_khr(&info, None)?;
     let swapchain_images = device.get_swapchain_images_khr(swapchain)?;
     let swapchain_format : vk::Format = surface_format.format;
     let swapchain_extent = extent;
 
-    Ok((swapchain, swapchain_images, swapchain_format, swapchain_extent))
+    Ok((swapchain, swapchain_images, swapchain_format, swapchain_extent, supports_capture))
 }
 
 pub unsafe fn create_swapchain_image_views(
@@ -0,0 +1,601 @@
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::ptr::copy_nonoverlapping as memcpy;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use vulkanalia::prelude::v1_0::*;
+
+use super::buffers::create_buffer;
+
+/// Offsets into the ring are kept aligned to the largest texel block, so image
+/// copies of any format can start at them.
+const STAGING_ALIGNMENT: u64 = 16;
+
+/// Identifies the batch an upload was recorded into. Resources are ready to be
+/// drawn from once their batch finished, see [`UploadContext::is_finished`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UploadHandle(u64);
+
+impl UploadHandle {
+    /// For resources that were never uploaded, render targets for example.
+    pub const READY: Self = Self(0);
+}
+
+/// Where the destination of an image upload is read from afterwards.
+pub struct ImageUpload<'a> {
+    pub image: vk::Image,
+    pub subresource_range: vk::ImageSubresourceRange,
+    /// Offsets are relative to the start of the uploaded data
+    pub regions: &'a [vk::BufferImageCopy],
+}
+
+/// Uploads recorded since the last flush, or submitted and not yet finished.
+struct UploadBatch {
+    handle: UploadHandle,
+    transfer_command_buffer: vk::CommandBuffer,
+    // Takes ownership of the uploaded resources on the graphics queue, only with a
+    // dedicated transfer queue
+    acquire_command_buffer: Option<vk::CommandBuffer>,
+    semaphore: Option<vk::Semaphore>,
+    fence: vk::Fence,
+    // The ring is in use up to here until the batch finished
+    ring_end: u64,
+    // Staging buffers of uploads too large for the ring
+    dedicated_staging: Vec<(vk::Buffer, vk::DeviceMemory)>,
+}
+
+struct UploadState {
+    pending: Option<UploadBatch>,
+    submitted: VecDeque<UploadBatch>,
+    // Next free byte of the ring, and the first byte still in use
+    head: u64,
+    tail: u64,
+    next_handle: u64,
+    finished: UploadHandle,
+}
+
+/// Copies data to device local resources through a persistent staging ring. Uploads
+/// are recorded into one batch until it is flushed, which submits them all at once
+/// without waiting for the queue. Uses a dedicated transfer queue when the device has
+/// one, handing the resources over to the graphics queue once copied.
+pub struct UploadContext {
+    // For staging buffers of uploads too large for the ring
+    instance: Instance,
+    physical_device: vk::PhysicalDevice,
+
+    staging_buffer: vk::Buffer,
+    staging_memory: vk::DeviceMemory,
+    capacity: u64,
+
+    transfer_queue: vk::Queue,
+    transfer_command_pool: vk::CommandPool,
+    graphics_queue: vk::Queue,
+    graphics_command_pool: vk::CommandPool,
+    // Both set with a dedicated transfer queue
+    queue_families: Option<(u32, u32)>,
+
+    state: Mutex<UploadState>,
+}
+
+impl UploadContext {
+    /// `transfer` is a dedicated transfer queue and its family, without one every
+    /// upload goes through the graphics queue.
+    pub unsafe fn create(
+        instance: &Instance,
+        device: &Device,
+        physical_device: vk::PhysicalDevice,
+        graphics_queue: vk::Queue,
+        graphics_family: u32,
+        transfer: Option<(vk::Queue, u32)>,
+        capacity: u64,
+    ) -> Result<Self> {
+        let (staging_buffer, staging_memory) = create_buffer(
+            instance,
+            device,
+            physical_device,
+            capacity,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+
+        let create_command_pool = |family: u32| {
+            let info = vk::CommandPoolCreateInfo::builder()
+                .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+                .queue_family_index(family);
+            device.create_command_pool(&info, None)
+        };
+        let graphics_command_pool = create_command_pool(graphics_family)?;
+        let (transfer_queue, transfer_command_pool) = match transfer {
+            Some((queue, family)) => (queue, create_command_pool(family)?),
+            None => (graphics_queue, graphics_command_pool),
+        };
+
+        Ok(Self {
+            instance: instance.clone(),
+            physical_device,
+            staging_buffer,
+            staging_memory,
+            capacity,
+            transfer_queue,
+            transfer_command_pool,
+            graphics_queue,
+            graphics_command_pool,
+            queue_families: transfer.map(|(_, family)| (family, graphics_family)),
+            state: Mutex::new(UploadState {
+                pending: None,
+                submitted: VecDeque::new(),
+                head: 0,
+                tail: 0,
+                next_handle: 1,
+                finished: UploadHandle::READY,
+            }),
+        })
+    }
+
+    pub fn has_dedicated_transfer_queue(&self) -> bool {
+        self.queue_families.is_some()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, UploadState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Copy `data` to the start of `buffer`, which must have been created with
+    /// `TRANSFER_DST` usage. Vertex, index and shader reads of it wait for the copy.
+    pub unsafe fn upload_buffer(
+        &self,
+        device: &Device,
+        data: &[u8],
+        buffer: vk::Buffer,
+    ) -> Result<UploadHandle> {
+        let mut state = self.lock();
+        let (source, offset) = self.stage(device, &mut state, data)?;
+        let batch = self.get_pending(device, &mut state)?;
+
+        let region = vk::BufferCopy::builder()
+            .src_offset(offset)
+            .dst_offset(0)
+            .size(data.len() as u64);
+        device.cmd_copy_buffer(batch.transfer_command_buffer, source, buffer, &[region]);
+
+        if let (Some((transfer_family, graphics_family)), Some(acquire_command_buffer)) =
+            (self.queue_families, batch.acquire_command_buffer)
+        {
+            let barrier = vk::BufferMemoryBarrier::builder()
+                .src_queue_family_index(transfer_family)
+                .dst_queue_family_index(graphics_family)
+                .buffer(buffer)
+                .offset(0)
+                .size(vk::WHOLE_SIZE as u64);
+
+            let release = barrier.src_access_mask(vk::AccessFlags::TRANSFER_WRITE);
+            device.cmd_pipeline_barrier(
+                batch.transfer_command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[] as &[vk::MemoryBarrier],
+                &[release],
+                &[] as &[vk::ImageMemoryBarrier],
+            );
+
+            let acquire = barrier.dst_access_mask(get_buffer_read_access());
+            device.cmd_pipeline_barrier(
+                acquire_command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                get_read_stages(),
+                vk::DependencyFlags::empty(),
+                &[] as &[vk::MemoryBarrier],
+                &[acquire],
+                &[] as &[vk::ImageMemoryBarrier],
+            );
+        }
+
+        Ok(batch.handle)
+    }
+
+    /// Copy `data` into the regions of a new image, and leave it ready to be sampled
+    /// in `SHADER_READ_ONLY_OPTIMAL`. Whatever the image held before is discarded.
+    pub unsafe fn upload_image(
+        &self,
+        device: &Device,
+        data: &[u8],
+        upload: ImageUpload,
+    ) -> Result<UploadHandle> {
+        let mut state = self.lock();
+        let (source, offset) = self.stage(device, &mut state, data)?;
+        let batch = self.get_pending(device, &mut state)?;
+        let command_buffer = batch.transfer_command_buffer;
+
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(upload.image)
+            .subresource_range(upload.subresource_range);
+
+        let to_transfer = barrier
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE);
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[] as &[vk::BufferMemoryBarrier],
+            &[to_transfer],
+        );
+
+        let regions = upload
+            .regions
+            .iter()
+            .map(|region| {
+                let mut region = *region;
+                region.buffer_offset += offset;
+                region
+            })
+            .collect::<Vec<_>>();
+        device.cmd_copy_buffer_to_image(
+            command_buffer,
+            source,
+            upload.image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &regions,
+        );
+
+        let to_shader = barrier
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        match (self.queue_families, batch.acquire_command_buffer) {
+            (Some((transfer_family, graphics_family)), Some(acquire_command_buffer)) => {
+                // The layout changes once, as part of handing the image over
+                let handover = to_shader
+                    .src_queue_family_index(transfer_family)
+                    .dst_queue_family_index(graphics_family);
+
+                let release = handover.src_access_mask(vk::AccessFlags::TRANSFER_WRITE);
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::DependencyFlags::empty(),
+                    &[] as &[vk::MemoryBarrier],
+                    &[] as &[vk::BufferMemoryBarrier],
+                    &[release],
+                );
+
+                let acquire = handover.dst_access_mask(vk::AccessFlags::SHADER_READ);
+                device.cmd_pipeline_barrier(
+                    acquire_command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    get_read_stages(),
+                    vk::DependencyFlags::empty(),
+                    &[] as &[vk::MemoryBarrier],
+                    &[] as &[vk::BufferMemoryBarrier],
+                    &[acquire],
+                );
+            }
+            _ => {
+                let to_shader = to_shader
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ);
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    get_read_stages(),
+                    vk::DependencyFlags::empty(),
+                    &[] as &[vk::MemoryBarrier],
+                    &[] as &[vk::BufferMemoryBarrier],
+                    &[to_shader],
+                );
+            }
+        }
+
+        Ok(batch.handle)
+    }
+
+    /// The batch uploads recorded now go into. Finished already when nothing is
+    /// waiting to be flushed.
+    pub fn get_pending_handle(&self) -> UploadHandle {
+        let state = self.lock();
+        match &state.pending {
+            Some(batch) => batch.handle,
+            None => UploadHandle(state.next_handle - 1),
+        }
+    }
+
+    /// Submit everything recorded since the last flush, without waiting for it. Work
+    /// submitted to the graphics queue afterwards sees the uploaded data.
+    pub unsafe fn flush(&self, device: &Device) -> Result<UploadHandle> {
+        let mut state = self.lock();
+        self.flush_locked(device, &mut state)
+    }
+
+    /// Whether the uploads of `handle` finished. Frees the staging space of every
+    /// batch that did.
+    pub unsafe fn is_finished(&self, device: &Device, handle: UploadHandle) -> Result<bool> {
+        let mut state = self.lock();
+        self.collect_locked(device, &mut state)?;
+        Ok(handle <= state.finished)
+    }
+
+    /// Flush if needed, and block until the uploads of `handle` finished.
+    pub unsafe fn wait(&self, device: &Device, handle: UploadHandle) -> Result<()> {
+        let mut state = self.lock();
+        if state.pending.as_ref().is_some_and(|batch| batch.handle <= handle) {
+            self.flush_locked(device, &mut state)?;
+        }
+        while handle > state.finished && !state.submitted.is_empty() {
+            self.retire_oldest(device, &mut state, true)?;
+        }
+        Ok(())
+    }
+
+    /// Free the staging space of every finished batch. Call once per frame.
+    pub unsafe fn collect(&self, device: &Device) -> Result<()> {
+        let mut state = self.lock();
+        self.collect_locked(device, &mut state)
+    }
+
+    unsafe fn collect_locked(&self, device: &Device, state: &mut UploadState) -> Result<()> {
+        while let Some(batch) = state.submitted.front() {
+            if device.get_fence_status(batch.fence)? != vk::SuccessCode::SUCCESS {
+                break;
+            }
+            self.retire_oldest(device, state, false)?;
+        }
+        Ok(())
+    }
+
+    unsafe fn retire_oldest(&self, device: &Device, state: &mut UploadState, wait: bool) -> Result<()> {
+        let Some(batch) = state.submitted.pop_front() else {
+            return Ok(());
+        };
+        if wait {
+            device.wait_for_fences(&[batch.fence], true, u64::MAX)?;
+        }
+
+        state.finished = batch.handle;
+        state.tail = batch.ring_end;
+        if state.submitted.is_empty() && state.pending.is_none() {
+            state.head = 0;
+            state.tail = 0;
+        }
+        self.destroy_batch(device, batch);
+        Ok(())
+    }
+
+    unsafe fn flush_locked(&self, device: &Device, state: &mut UploadState) -> Result<UploadHandle> {
+        let Some(mut batch) = state.pending.take() else {
+            return Ok(UploadHandle(state.next_handle - 1));
+        };
+        batch.ring_end = state.head;
+
+        let transfer_command_buffers = &[batch.transfer_command_buffer];
+        match (batch.acquire_command_buffer, batch.semaphore) {
+            (Some(acquire_command_buffer), Some(semaphore)) => {
+                device.end_command_buffer(batch.transfer_command_buffer)?;
+                device.end_command_buffer(acquire_command_buffer)?;
+
+                let signal_semaphores = &[semaphore];
+                let transfer_info = vk::SubmitInfo::builder()
+                    .command_buffers(transfer_command_buffers)
+                    .signal_semaphores(signal_semaphores);
+                device.queue_submit(self.transfer_queue, &[transfer_info], vk::Fence::null())?;
+
+                let acquire_command_buffers = &[acquire_command_buffer];
+                let wait_stages = &[vk::PipelineStageFlags::ALL_COMMANDS];
+                let acquire_info = vk::SubmitInfo::builder()
+                    .wait_semaphores(signal_semaphores)
+                    .wait_dst_stage_mask(wait_stages)
+                    .command_buffers(acquire_command_buffers);
+                device.queue_submit(self.graphics_queue, &[acquire_info], batch.fence)?;
+            }
+            _ => {
+                // Buffers are not handed over, one barrier covers all of them
+                let barrier = vk::MemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(get_buffer_read_access());
+                device.cmd_pipeline_barrier(
+                    batch.transfer_command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    get_read_stages(),
+                    vk::DependencyFlags::empty(),
+                    &[barrier],
+                    &[] as &[vk::BufferMemoryBarrier],
+                    &[] as &[vk::ImageMemoryBarrier],
+                );
+                device.end_command_buffer(batch.transfer_command_buffer)?;
+
+                let info = vk::SubmitInfo::builder().command_buffers(transfer_command_buffers);
+                device.queue_submit(self.transfer_queue, &[info], batch.fence)?;
+            }
+        }
+
+        let handle = batch.handle;
+        state.submitted.push_back(batch);
+        Ok(handle)
+    }
+
+    unsafe fn get_pending<'a>(
+        &self,
+        device: &Device,
+        state: &'a mut UploadState,
+    ) -> Result<&'a mut UploadBatch> {
+        if state.pending.is_none() {
+            let handle = UploadHandle(state.next_handle);
+            state.next_handle += 1;
+            let batch = self.begin_batch(device, handle)?;
+            state.pending = Some(batch);
+        }
+        Ok(state.pending.as_mut().unwrap())
+    }
+
+    unsafe fn begin_batch(&self, device: &Device, handle: UploadHandle) -> Result<UploadBatch> {
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        let allocate = |command_pool: vk::CommandPool| -> Result<vk::CommandBuffer> {
+            let info = vk::CommandBufferAllocateInfo::builder()
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_pool(command_pool)
+                .command_buffer_count(1);
+            let command_buffer = device.allocate_command_buffers(&info)?[0];
+            device.begin_command_buffer(command_buffer, &begin_info)?;
+            Ok(command_buffer)
+        };
+
+        let transfer_command_buffer = allocate(self.transfer_command_pool)?;
+        let (acquire_command_buffer, semaphore) = if self.has_dedicated_transfer_queue() {
+            let semaphore =
+                device.create_semaphore(&vk::SemaphoreCreateInfo::builder(), None)?;
+            (Some(allocate(self.graphics_command_pool)?), Some(semaphore))
+        } else {
+            (None, None)
+        };
+        let fence = device.create_fence(&vk::FenceCreateInfo::builder(), None)?;
+
+        Ok(UploadBatch {
+            handle,
+            transfer_command_buffer,
+            acquire_command_buffer,
+            semaphore,
+            fence,
+            ring_end: 0,
+            dedicated_staging: vec![],
+        })
+    }
+
+    unsafe fn destroy_batch(&self, device: &Device, batch: UploadBatch) {
+        device.free_command_buffers(self.transfer_command_pool, &[batch.transfer_command_buffer]);
+        if let Some(acquire_command_buffer) = batch.acquire_command_buffer {
+            device.free_command_buffers(self.graphics_command_pool, &[acquire_command_buffer]);
+        }
+        if let Some(semaphore) = batch.semaphore {
+            device.destroy_semaphore(semaphore, None);
+        }
+        device.destroy_fence(batch.fence, None);
+        for (buffer, memory) in batch.dedicated_staging {
+            device.destroy_buffer(buffer, None);
+            device.free_memory(memory, None);
+        }
+    }
+
+    /// Copy `data` into staging memory, returning the buffer and offset it landed
+    /// at. Waits for older batches when the ring is full.
+    unsafe fn stage(
+        &self,
+        device: &Device,
+        state: &mut UploadState,
+        data: &[u8],
+    ) -> Result<(vk::Buffer, u64)> {
+        let size = (data.len() as u64).max(1);
+
+        // Would hold back every other upload, it gets a buffer of its own instead
+        if size > self.capacity / 2 {
+            let (buffer, memory) = create_buffer(
+                &self.instance,
+                device,
+                self.physical_device,
+                size,
+                vk::BufferUsageFlags::TRANSFER_SRC,
+                vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+            )?;
+            write_memory(device, memory, 0, data)?;
+            self.get_pending(device, state)?
+                .dedicated_staging
+                .push((buffer, memory));
+            return Ok((buffer, 0));
+        }
+
+        let offset = loop {
+            if let Some(offset) = self.allocate(state, size) {
+                break offset;
+            }
+            // Older batches free up the ring as they finish, the pending one has to
+            // be submitted before it can
+            if state.submitted.is_empty() {
+                self.flush_locked(device, state)?;
+            }
+            self.retire_oldest(device, state, true)?;
+        };
+
+        write_memory(device, self.staging_memory, offset, data)?;
+        Ok((self.staging_buffer, offset))
+    }
+
+    /// Room for `size` bytes between the head and the tail, wrapping around to the
+    /// start of the ring when the end is too close.
+    fn allocate(&self, state: &mut UploadState, size: u64) -> Option<u64> {
+        let start = align(state.head, STAGING_ALIGNMENT);
+        let offset = if state.head >= state.tail {
+            if start + size <= self.capacity {
+                start
+            } else if size < state.tail {
+                0
+            } else {
+                return None;
+            }
+        } else if start + size < state.tail {
+            start
+        } else {
+            return None;
+        };
+
+        state.head = offset + size;
+        Some(offset)
+    }
+
+    /// Waits for every upload, the device must not be destroyed before.
+    pub unsafe fn destroy(&self, device: &Device) {
+        let mut state = self.lock();
+        if let Err(e) = self.flush_locked(device, &mut state) {
+            log::error!("Failed to submit the last uploads: {}", e);
+        }
+        while !state.submitted.is_empty() {
+            if let Err(e) = self.retire_oldest(device, &mut state, true) {
+                log::error!("Failed to wait for uploads: {}", e);
+                break;
+            }
+        }
+
+        if self.transfer_command_pool != self.graphics_command_pool {
+            device.destroy_command_pool(self.transfer_command_pool, None);
+        }
+        device.destroy_command_pool(self.graphics_command_pool, None);
+        device.destroy_buffer(self.staging_buffer, None);
+        device.free_memory(self.staging_memory, None);
+    }
+}
+
+fn align(offset: u64, alignment: u64) -> u64 {
+    offset.div_ceil(alignment) * alignment
+}
+
+unsafe fn write_memory(
+    device: &Device,
+    memory: vk::DeviceMemory,
+    offset: u64,
+    data: &[u8],
+) -> Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    let mapped = device.map_memory(memory, offset, data.len() as u64, vk::MemoryMapFlags::empty())?;
+    memcpy(data.as_ptr(), mapped.cast(), data.len());
+    device.unmap_memory(memory);
+    Ok(())
+}
+
+/// Everything uploaded is read as vertex input or by shaders.
+fn get_read_stages() -> vk::PipelineStageFlags {
+    vk::PipelineStageFlags::VERTEX_INPUT
+        | vk::PipelineStageFlags::VERTEX_SHADER
+        | vk::PipelineStageFlags::FRAGMENT_SHADER
+        | vk::PipelineStageFlags::COMPUTE_SHADER
+}
+
+fn get_buffer_read_access() -> vk::AccessFlags {
+    vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+        | vk::AccessFlags::INDEX_READ
+        | vk::AccessFlags::SHADER_READ
+}
@@ -2,11 +2,145 @@ use vulkanalia::{prelude::v1_0::*, vk::DebugUtilsMessengerEXT};
 use vulkanalia::vk::ExtDebugUtilsExtension;
 use std::collections::HashSet;
 use anyhow::{anyhow, Result};
+use std::backtrace::Backtrace;
 use std::ffi::CStr;
-use std::os::raw::c_void;
+use std::fmt;
+use std::os::raw::{c_char, c_void};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use log::*;
 
-use crate::core::config::{VALIDATION_ENABLED, VALIDATION_LAYER};
+use crate::core::config::{STRICT_VALIDATION_VARIABLE, VALIDATION_ENABLED, VALIDATION_LAYER};
+
+/// Messages kept until they are drained, anything beyond is only counted.
+const MAX_QUEUED_VALIDATION_MESSAGES: usize = 256;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ValidationSeverity {
+    Verbose,
+    Info,
+    Warning,
+    Error,
+}
+
+impl ValidationSeverity {
+    fn from_flags(severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> Self {
+        if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
+            ValidationSeverity::Error
+        } else if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING {
+            ValidationSeverity::Warning
+        } else if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::INFO {
+            ValidationSeverity::Info
+        } else {
+            ValidationSeverity::Verbose
+        }
+    }
+}
+
+/// A Vulkan object a validation message is about.
+#[derive(Clone, Debug)]
+pub struct ValidationObject {
+    pub object_type: vk::ObjectType,
+    pub handle: u64,
+    pub name: Option<String>,
+}
+
+/// One message of the validation layers, parsed out of the debug messenger callback.
+#[derive(Clone, Debug)]
+pub struct ValidationMessage {
+    pub severity: ValidationSeverity,
+    pub message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    /// Such as `VUID-vkCmdDraw-None-02699`
+    pub message_id_name: Option<String>,
+    pub message_id_number: i32,
+    pub message: String,
+    pub objects: Vec<ValidationObject>,
+}
+
+impl fmt::Display for ValidationMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{:?}] ({:?}) {} ({:#x})",
+            self.severity,
+            self.message_type,
+            self.message_id_name.as_deref().unwrap_or("no message id"),
+            self.message_id_number
+        )?;
+        for object in self.objects.iter() {
+            write!(f, " {:?} {:#x}", object.object_type, object.handle)?;
+            if let Some(name) = &object.name {
+                write!(f, " `{}`", name)?;
+            }
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// How many messages of each severity arrived since the last drain.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationCounts {
+    pub errors: usize,
+    pub warnings: usize,
+    pub infos: usize,
+    pub verbose: usize,
+}
+
+impl ValidationCounts {
+    fn add(&mut self, severity: ValidationSeverity) {
+        match severity {
+            ValidationSeverity::Error => self.errors += 1,
+            ValidationSeverity::Warning => self.warnings += 1,
+            ValidationSeverity::Info => self.infos += 1,
+            ValidationSeverity::Verbose => self.verbose += 1,
+        }
+    }
+
+    pub fn accumulate(&mut self, other: &ValidationCounts) {
+        self.errors += other.errors;
+        self.warnings += other.warnings;
+        self.infos += other.infos;
+        self.verbose += other.verbose;
+    }
+}
+
+struct ValidationSink {
+    messages: Vec<ValidationMessage>,
+    counts: ValidationCounts,
+    // The first error reported in strict mode, with the backtrace of the Vulkan call
+    strict_failure: Option<String>,
+}
+
+// The debug callback has no way to reach the renderer, so messages wait here
+static VALIDATION_SINK: Mutex<ValidationSink> = Mutex::new(ValidationSink {
+    messages: Vec::new(),
+    counts: ValidationCounts {
+        errors: 0,
+        warnings: 0,
+        infos: 0,
+        verbose: 0,
+    },
+    strict_failure: None,
+});
+static STRICT_VALIDATION: AtomicBool = AtomicBool::new(false);
+
+/// In strict mode any validation error fails the next drain, with a backtrace pointing
+/// at the Vulkan call that caused it.
+pub fn set_strict_validation(strict: bool) {
+    STRICT_VALIDATION.store(strict, Ordering::Relaxed);
+}
+
+/// Take every queued message along with the counts since the last call. Fails once a
+/// validation error was reported in strict mode.
+pub fn drain_validation_messages() -> Result<(Vec<ValidationMessage>, ValidationCounts)> {
+    let mut sink = VALIDATION_SINK.lock().unwrap_or_else(|error| error.into_inner());
+    if let Some(failure) = sink.strict_failure.take() {
+        return Err(anyhow!("Validation error in strict mode: {}", failure));
+    }
+    let messages = std::mem::take(&mut sink.messages);
+    let counts = std::mem::take(&mut sink.counts);
+    Ok((messages, counts))
+}
 
 pub unsafe fn get_validation_layers(entry: &Entry) -> Result<Vec<*const i8>> {
     let available_layers = entry
@@ -42,6 +176,11 @@ pub unsafe fn create_instance_with_debug(
         extensions.push(vk::EXT_DEBUG_UTILS_EXTENSION.name.as_ptr());
     }
 
+    if std::env::var(STRICT_VALIDATION_VARIABLE).is_ok_and(|strict| strict != "0") {
+        info!("Strict validation enabled, validation errors will fail the run.");
+        set_strict_validation(true);
+    }
+
     let layers = get_validation_layers(entry)?;
 
     let mut info = vk::InstanceCreateInfo::builder()
@@ -80,17 +219,65 @@ extern "system" fn debug_callback(
     _: *mut c_void
     ) -> vk::Bool32 {
     let data = unsafe { *data };
-    let message = unsafe { CStr::from_ptr(data.message) }.to_string_lossy();
-
-    if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
-        error!("({:?}) {}", type_, message);
-    } else if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING {
-        warn!("({:?}) {}", type_, message);
-    } else if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::INFO {
-        debug!("({:?}) {}", type_, message);
-    } else {
-        trace!("({:?}) {}", type_, message);
+    let message = unsafe { parse_message(severity, type_, &data) };
+
+    match message.severity {
+        ValidationSeverity::Error => error!("{}", message),
+        ValidationSeverity::Warning => warn!("{}", message),
+        ValidationSeverity::Info => debug!("{}", message),
+        ValidationSeverity::Verbose => trace!("{}", message),
+    }
+
+    let mut sink = VALIDATION_SINK.lock().unwrap_or_else(|error| error.into_inner());
+    let is_strict_failure = message.severity == ValidationSeverity::Error
+        && STRICT_VALIDATION.load(Ordering::Relaxed)
+        && sink.strict_failure.is_none();
+    if is_strict_failure {
+        // Unwinding out of the callback aborts, so only the report is kept here. The
+        // callback runs inside the offending Vulkan call, so the backtrace leads to it.
+        sink.strict_failure = Some(format!("{}\n{}", message, Backtrace::force_capture()));
+    }
+
+    sink.counts.add(message.severity);
+    if sink.messages.len() < MAX_QUEUED_VALIDATION_MESSAGES {
+        sink.messages.push(message);
     }
 
     vk::FALSE
 }
+
+unsafe fn parse_message(
+    severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    type_: vk::DebugUtilsMessageTypeFlagsEXT,
+    data: &vk::DebugUtilsMessengerCallbackDataEXT,
+) -> ValidationMessage {
+    let objects = if data.objects.is_null() {
+        &[]
+    } else {
+        std::slice::from_raw_parts(data.objects, data.object_count as usize)
+    };
+
+    ValidationMessage {
+        severity: ValidationSeverity::from_flags(severity),
+        message_type: type_,
+        message_id_name: optional_string(data.message_id_name),
+        message_id_number: data.message_id_number,
+        message: optional_string(data.message).unwrap_or_default(),
+        objects: objects
+            .iter()
+            .map(|object| ValidationObject {
+                object_type: object.object_type,
+                handle: object.object_handle,
+                name: optional_string(object.object_name),
+            })
+            .collect(),
+    }
+}
+
+unsafe fn optional_string(pointer: *const c_char) -> Option<String> {
+    if pointer.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(pointer).to_string_lossy().into_owned())
+    }
+}
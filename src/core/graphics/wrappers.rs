@@ -1,13 +1,20 @@
+mod atlas;
+mod block_compression;
 mod depth_buffer;
 mod image;
 mod image_sampler;
 mod index_buffer;
+mod palette;
+mod particle_buffer;
 mod uniform_buffer_object;
 mod vertex_buffer;
 
-pub use image::{create_image_view, LoadedImage, Image};
-pub use image_sampler::{ImageSampler, bind_sampler_to_descriptor_sets};
+pub use atlas::{TextureAtlas, TextureAtlasBuilder};
+pub use image::{create_image_view, AlphaMode, CubemapImage, LoadedImage, Image};
+pub use image_sampler::{ImageSampler, SamplerCache, SamplerDescription};
 pub use index_buffer::IndexBuffer;
+pub use palette::{create_palette_image, Palette};
+pub use particle_buffer::{ParticleBufferSeries, ParticleInstance};
 pub use uniform_buffer_object::uniform_buffer;
 pub use vertex_buffer::{Vertex, VertexBuffer};
 pub use depth_buffer::{get_depth_format, DepthBuffer};
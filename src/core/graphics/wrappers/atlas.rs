@@ -0,0 +1,215 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+use super::image::{AlphaMode, Image};
+
+type Vec4 = cgmath::Vector4<f32>;
+
+/// Where one image, or one frame of a sprite sheet, ended up in a [`TextureAtlas`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AtlasRegion {
+    /// Index into [`TextureAtlas::get_pages`]
+    pub page: usize,
+    /// Offset and size of the image on its page, in texture coordinates
+    pub uv_rect: Vec4,
+    /// Of this image alone, its page is as transparent as its most transparent image
+    pub alpha_mode: AlphaMode,
+}
+
+/// One image waiting to be packed.
+struct AtlasEntry {
+    key: String,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+    alpha_mode: AlphaMode,
+}
+
+/// Packs many small images into a few large pages, so meshes showing any of them
+/// can share a texture.
+pub struct TextureAtlasBuilder {
+    page_size: u32,
+    padding: u32,
+    entries: Vec<AtlasEntry>,
+}
+
+impl TextureAtlasBuilder {
+    /// Pages are at most `page_size` square. Every image is surrounded by `padding`
+    /// copies of its edge pixels, so filtering and mipmapping near its border never
+    /// picks up its neighbours.
+    pub fn new(page_size: u32, padding: u32) -> Self {
+        Self {
+            page_size,
+            padding,
+            entries: vec![],
+        }
+    }
+
+    pub fn add(&mut self, key: impl Into<String>, image: &Image) -> Result<&mut Self> {
+        self.add_sprite_sheet(key, image, 1, 1)
+    }
+
+    /// Add every frame of a sheet of `columns` by `rows` equally sized frames,
+    /// numbered row by row. Frames are packed on their own, so they get padding
+    /// between them even when the sheet has none.
+    pub fn add_sprite_sheet(
+        &mut self,
+        key: impl Into<String>,
+        image: &Image,
+        columns: u32,
+        rows: u32,
+    ) -> Result<&mut Self> {
+        let key = key.into();
+        if self.entries.iter().any(|entry| entry.key == key) {
+            return Err(anyhow!("{:?} was already added to the atlas", key));
+        }
+        let pixels = image
+            .get_rgba_pixels()
+            .ok_or_else(|| anyhow!("Only uncompressed RGBA images can be packed, {:?} is not", key))?;
+
+        let columns = columns.max(1);
+        let rows = rows.max(1);
+        let frame_width = image.get_width() / columns;
+        let frame_height = image.get_height() / rows;
+        if frame_width == 0 || frame_height == 0 {
+            return Err(anyhow!("{:?} is too small for {} by {} frames", key, columns, rows));
+        }
+
+        let stride = image.get_width() as usize * 4;
+        for frame in 0..(columns * rows) {
+            let left = (frame % columns * frame_width) as usize;
+            let top = (frame / columns * frame_height) as usize;
+
+            let mut frame_pixels = Vec::with_capacity((frame_width * frame_height * 4) as usize);
+            for y in top..(top + frame_height as usize) {
+                let start = y * stride + left * 4;
+                frame_pixels.extend_from_slice(&pixels[start..start + frame_width as usize * 4]);
+            }
+
+            self.entries.push(AtlasEntry {
+                key: key.clone(),
+                width: frame_width,
+                height: frame_height,
+                pixels: frame_pixels,
+                alpha_mode: image.get_alpha_mode(),
+            });
+        }
+
+        Ok(self)
+    }
+
+    /// Pack everything added into rows of images, tallest first, starting a new page
+    /// whenever one is full. Pages are cut off below their last row.
+    pub fn build(self) -> Result<TextureAtlas> {
+        let padding = self.padding;
+        let page_size = self.page_size;
+
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        order.sort_by(|&a, &b| self.entries[b].height.cmp(&self.entries[a].height));
+
+        // Top left corner of every padded entry, and the height used on each page
+        let mut placements = vec![(0, 0, 0); self.entries.len()];
+        let mut page_heights = vec![0];
+        let (mut x, mut y, mut row_height) = (0, 0, 0);
+        for &index in &order {
+            let entry = &self.entries[index];
+            let width = entry.width + 2 * padding;
+            let height = entry.height + 2 * padding;
+            if width > page_size || height > page_size {
+                return Err(anyhow!(
+                    "{:?} does not fit on a page of {} pixels",
+                    entry.key,
+                    page_size
+                ));
+            }
+
+            if x + width > page_size {
+                x = 0;
+                y += row_height;
+                row_height = 0;
+            }
+            if y + height > page_size {
+                page_heights.push(0);
+                x = 0;
+                y = 0;
+                row_height = 0;
+            }
+
+            let page = page_heights.len() - 1;
+            placements[index] = (page, x, y);
+            page_heights[page] = page_heights[page].max(y + height);
+            x += width;
+            row_height = row_height.max(height);
+        }
+
+        let mut page_pixels: Vec<Vec<u8>> = page_heights
+            .iter()
+            .map(|&height| vec![0; (page_size * height.max(1) * 4) as usize])
+            .collect();
+        let mut regions: HashMap<String, Vec<AtlasRegion>> = HashMap::new();
+
+        for (entry, &(page, left, top)) in self.entries.iter().zip(&placements) {
+            let pixels = &mut page_pixels[page];
+            let padded_width = entry.width + 2 * padding;
+            let padded_height = entry.height + 2 * padding;
+            for y in 0..padded_height {
+                // Outside of the image proper, repeat its closest edge
+                let source_y = y.saturating_sub(padding).min(entry.height - 1);
+                for x in 0..padded_width {
+                    let source_x = x.saturating_sub(padding).min(entry.width - 1);
+                    let source = ((source_y * entry.width + source_x) * 4) as usize;
+                    let destination = (((top + y) * page_size + left + x) * 4) as usize;
+                    pixels[destination..destination + 4]
+                        .copy_from_slice(&entry.pixels[source..source + 4]);
+                }
+            }
+
+            let page_width = page_size as f32;
+            let page_height = page_heights[page].max(1) as f32;
+            // Entries of a key were added frame by frame
+            regions.entry(entry.key.clone()).or_default().push(AtlasRegion {
+                page,
+                uv_rect: Vec4::new(
+                    (left + padding) as f32 / page_width,
+                    (top + padding) as f32 / page_height,
+                    entry.width as f32 / page_width,
+                    entry.height as f32 / page_height,
+                ),
+                alpha_mode: entry.alpha_mode,
+            });
+        }
+
+        let pages = page_pixels
+            .into_iter()
+            .zip(&page_heights)
+            .map(|(pixels, &height)| Image::from_rgba(page_size, height.max(1), pixels))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(TextureAtlas { pages, regions })
+    }
+}
+
+/// Images packed by a [`TextureAtlasBuilder`], looked up by the key they were added with.
+pub struct TextureAtlas {
+    pages: Vec<Image>,
+    regions: HashMap<String, Vec<AtlasRegion>>,
+}
+
+impl TextureAtlas {
+    pub fn get_pages(&self) -> &[Image] {
+        &self.pages
+    }
+
+    /// The whole image added as `key`, or the first frame of a sprite sheet.
+    pub fn get_region(&self, key: &str) -> Option<AtlasRegion> {
+        self.get_frame(key, 0)
+    }
+
+    pub fn get_frame(&self, key: &str, frame: usize) -> Option<AtlasRegion> {
+        self.regions.get(key)?.get(frame).copied()
+    }
+
+    pub fn get_frame_count(&self, key: &str) -> usize {
+        self.regions.get(key).map_or(0, |frames| frames.len())
+    }
+}
@@ -0,0 +1,554 @@
+use anyhow::{anyhow, Result};
+use vulkanalia::vk;
+
+/// Block compressed encodings that texture containers may hold. Every one of them
+/// stores 4x4 pixel blocks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BlockCompression {
+    /// RGB with optional 1 bit alpha, 8 bytes per block
+    Bc1,
+    /// RGB with interpolated alpha, 16 bytes per block
+    Bc3,
+    /// High quality RGBA, 16 bytes per block
+    Bc7,
+}
+
+impl BlockCompression {
+    pub const BLOCK_SIZE: u32 = 4;
+
+    pub fn get_bytes_per_block(&self) -> usize {
+        match self {
+            BlockCompression::Bc1 => 8,
+            BlockCompression::Bc3 | BlockCompression::Bc7 => 16,
+        }
+    }
+
+    pub fn get_vk_format(&self, srgb: bool) -> vk::Format {
+        match (self, srgb) {
+            (BlockCompression::Bc1, true) => vk::Format::BC1_RGBA_SRGB_BLOCK,
+            (BlockCompression::Bc1, false) => vk::Format::BC1_RGBA_UNORM_BLOCK,
+            (BlockCompression::Bc3, true) => vk::Format::BC3_SRGB_BLOCK,
+            (BlockCompression::Bc3, false) => vk::Format::BC3_UNORM_BLOCK,
+            (BlockCompression::Bc7, true) => vk::Format::BC7_SRGB_BLOCK,
+            (BlockCompression::Bc7, false) => vk::Format::BC7_UNORM_BLOCK,
+        }
+    }
+
+    /// Number of bytes a single mip level of the given size takes up.
+    pub fn get_level_size(&self, width: u32, height: u32) -> usize {
+        let blocks_x = width.div_ceil(Self::BLOCK_SIZE) as usize;
+        let blocks_y = height.div_ceil(Self::BLOCK_SIZE) as usize;
+        blocks_x * blocks_y * self.get_bytes_per_block()
+    }
+
+    /// Decode one mip level into [r, g, b, a] tuples row by row.
+    pub fn decode(&self, data: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+        let expected_size = self.get_level_size(width, height);
+        if data.len() < expected_size {
+            return Err(anyhow!(
+                "{:?} level of {}x{} needs {} bytes, got {}",
+                self, width, height, expected_size, data.len()
+            ));
+        }
+
+        let blocks_x = width.div_ceil(Self::BLOCK_SIZE) as usize;
+        let bytes_per_block = self.get_bytes_per_block();
+        let mut pixels = vec![0; width as usize * height as usize * 4];
+
+        for (index, block) in data[..expected_size].chunks_exact(bytes_per_block).enumerate() {
+            let decoded = match self {
+                BlockCompression::Bc1 => decode_bc1_block(block),
+                BlockCompression::Bc3 => decode_bc3_block(block),
+                BlockCompression::Bc7 => decode_bc7_block(block),
+            };
+
+            // blocks at the right and bottom edge may hang over the image
+            let block_x = (index % blocks_x) * 4;
+            let block_y = (index / blocks_x) * 4;
+            for y in 0..4 {
+                for x in 0..4 {
+                    let (pixel_x, pixel_y) = (block_x + x, block_y + y);
+                    if pixel_x >= width as usize || pixel_y >= height as usize {
+                        continue;
+                    }
+                    let offset = (pixel_y * width as usize + pixel_x) * 4;
+                    pixels[offset..offset + 4].copy_from_slice(&decoded[y * 4 + x]);
+                }
+            }
+        }
+
+        Ok(pixels)
+    }
+}
+
+type Block = [[u8; 4]; 16];
+
+fn expand_565(color: u16) -> [u8; 4] {
+    let r = ((color >> 11) & 0x1f) as u8;
+    let g = ((color >> 5) & 0x3f) as u8;
+    let b = (color & 0x1f) as u8;
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2), u8::MAX]
+}
+
+fn mix(a: u8, b: u8, weight_a: u32, weight_b: u32) -> u8 {
+    ((a as u32 * weight_a + b as u32 * weight_b) / (weight_a + weight_b)) as u8
+}
+
+fn mix_color(a: [u8; 4], b: [u8; 4], weight_a: u32, weight_b: u32) -> [u8; 4] {
+    [
+        mix(a[0], b[0], weight_a, weight_b),
+        mix(a[1], b[1], weight_a, weight_b),
+        mix(a[2], b[2], weight_a, weight_b),
+        u8::MAX,
+    ]
+}
+
+/// The color part shared by BC1 and BC3. BC3 always uses four colors, BC1 switches
+/// to three colors and transparent black when the endpoints are ordered low to high.
+fn decode_color_block(block: &[u8], allow_transparency: bool) -> Block {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    let endpoint0 = expand_565(color0);
+    let endpoint1 = expand_565(color1);
+    let palette = if color0 > color1 || !allow_transparency {
+        [
+            endpoint0,
+            endpoint1,
+            mix_color(endpoint0, endpoint1, 2, 1),
+            mix_color(endpoint0, endpoint1, 1, 2),
+        ]
+    } else {
+        [
+            endpoint0,
+            endpoint1,
+            mix_color(endpoint0, endpoint1, 1, 1),
+            [0, 0, 0, 0],
+        ]
+    };
+
+    let mut pixels = [[0; 4]; 16];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = palette[((indices >> (i * 2)) & 0b11) as usize];
+    }
+    pixels
+}
+
+fn decode_bc1_block(block: &[u8]) -> Block {
+    decode_color_block(block, true)
+}
+
+fn decode_bc3_block(block: &[u8]) -> Block {
+    let alpha0 = block[0];
+    let alpha1 = block[1];
+    let mut indices = [0; 8];
+    indices[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(indices);
+
+    let mut alphas = [alpha0, alpha1, 0, 0, 0, 0, 0, u8::MAX];
+    if alpha0 > alpha1 {
+        for i in 1..7 {
+            alphas[i + 1] = mix(alpha0, alpha1, 7 - i as u32, i as u32);
+        }
+    } else {
+        for i in 1..5 {
+            alphas[i + 1] = mix(alpha0, alpha1, 5 - i as u32, i as u32);
+        }
+    }
+
+    let mut pixels = decode_color_block(&block[8..16], false);
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        pixel[3] = alphas[((indices >> (i * 3)) & 0b111) as usize];
+    }
+    pixels
+}
+
+/// Reads the little endian bit stream of a BC7 block, lowest bit first.
+struct BitReader {
+    bits: u128,
+    position: u32,
+}
+
+impl BitReader {
+    fn read(&mut self, count: u32) -> u8 {
+        let value = (self.bits >> self.position) & ((1 << count) - 1);
+        self.position += count;
+        value as u8
+    }
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_p_bits: bool,
+    shared_p_bits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32,
+}
+
+/// Field layout of the eight BC7 modes, selected by the lowest set bit of a block.
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode {
+        subsets: 3,
+        partition_bits: 4,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 4,
+        alpha_bits: 0,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 6,
+        alpha_bits: 0,
+        endpoint_p_bits: false,
+        shared_p_bits: true,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 3,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 0,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 0,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 1,
+        color_bits: 5,
+        alpha_bits: 6,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 3,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 8,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 2,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 7,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 4,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 5,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+];
+
+/// Subset of every pixel for the two subset partitions, pixel i in bit i.
+const BC7_PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80,
+    0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000,
+    0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c,
+    0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a,
+    0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c,
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+/// Subset of every pixel for the three subset partitions, row by row.
+const BC7_PARTITIONS_3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2],
+    [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0],
+    [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0],
+    [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2],
+    [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0],
+    [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0],
+    [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1],
+    [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1],
+    [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2],
+    [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2],
+    [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+    [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+/// Pixel whose index drops its top bit in the second subset of two subset partitions.
+const BC7_ANCHORS_2: [usize; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Anchor pixel of the second subset of three subset partitions.
+const BC7_ANCHORS_3_SECOND: [usize; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3,
+    3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15,
+    8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15,
+    3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+];
+
+/// Anchor pixel of the third subset of three subset partitions.
+const BC7_ANCHORS_3_THIRD: [usize; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8,
+    15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+    15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8,
+    15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+const BC7_WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn bc7_weights(index_bits: u32) -> &'static [u32] {
+    match index_bits {
+        2 => &BC7_WEIGHTS_2,
+        3 => &BC7_WEIGHTS_3,
+        _ => &BC7_WEIGHTS_4,
+    }
+}
+
+fn bc7_interpolate(a: u8, b: u8, weight: u32) -> u8 {
+    (((64 - weight) * a as u32 + weight * b as u32 + 32) >> 6) as u8
+}
+
+/// Scale an endpoint of the given precision up to 8 bits by repeating its top bits.
+fn bc7_expand(value: u8, bits: u32) -> u8 {
+    if bits >= 8 {
+        return value;
+    }
+    let value = (value as u32) << (8 - bits);
+    (value | (value >> bits)) as u8
+}
+
+fn decode_bc7_block(block: &[u8]) -> Block {
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(block);
+    let mut reader = BitReader {
+        bits: u128::from_le_bytes(bytes),
+        position: 0,
+    };
+
+    let mode_index = block[0].trailing_zeros() as usize;
+    if mode_index >= BC7_MODES.len() {
+        // reserved mode, decoders must output transparent black
+        return [[0; 4]; 16];
+    }
+    let mode = &BC7_MODES[mode_index];
+    reader.read(mode_index as u32 + 1);
+
+    let partition = reader.read(mode.partition_bits) as usize;
+    let rotation = reader.read(mode.rotation_bits);
+    let index_selection = reader.read(mode.index_selection_bits);
+
+    // endpoints[subset * 2 + end][channel]
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u8; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[channel] = reader.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        endpoint[3] = reader.read(mode.alpha_bits);
+    }
+
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+    if mode.endpoint_p_bits || mode.shared_p_bits {
+        // shared p-bits are stored once per subset, endpoint p-bits once per endpoint
+        let p_bit_count = if mode.shared_p_bits { mode.subsets } else { endpoint_count };
+        let p_bits = (0..p_bit_count).map(|_| reader.read(1)).collect::<Vec<_>>();
+        for (i, endpoint) in endpoints.iter_mut().take(endpoint_count).enumerate() {
+            let p_bit = if mode.shared_p_bits { p_bits[i / 2] } else { p_bits[i] };
+            for value in endpoint.iter_mut() {
+                *value = (*value << 1) | p_bit;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        for value in endpoint.iter_mut().take(3) {
+            *value = bc7_expand(*value, color_bits);
+        }
+        endpoint[3] = if alpha_bits > 0 {
+            bc7_expand(endpoint[3], alpha_bits)
+        } else {
+            u8::MAX
+        };
+    }
+
+    let subset_of = |pixel: usize| -> usize {
+        match mode.subsets {
+            2 => ((BC7_PARTITIONS_2[partition] >> pixel) & 1) as usize,
+            3 => BC7_PARTITIONS_3[partition][pixel] as usize,
+            _ => 0,
+        }
+    };
+    let is_anchor = |pixel: usize| -> bool {
+        match mode.subsets {
+            2 => pixel == 0 || pixel == BC7_ANCHORS_2[partition],
+            3 => {
+                pixel == 0
+                    || pixel == BC7_ANCHORS_3_SECOND[partition]
+                    || pixel == BC7_ANCHORS_3_THIRD[partition]
+            }
+            _ => pixel == 0,
+        }
+    };
+
+    let mut indices = [0u8; 16];
+    for (pixel, index) in indices.iter_mut().enumerate() {
+        let bits = if is_anchor(pixel) { mode.index_bits - 1 } else { mode.index_bits };
+        *index = reader.read(bits);
+    }
+    let mut secondary_indices = [0u8; 16];
+    if mode.secondary_index_bits > 0 {
+        for (pixel, index) in secondary_indices.iter_mut().enumerate() {
+            let bits = if pixel == 0 {
+                mode.secondary_index_bits - 1
+            } else {
+                mode.secondary_index_bits
+            };
+            *index = reader.read(bits);
+        }
+    }
+
+    let mut pixels = [[0; 4]; 16];
+    for (pixel, output) in pixels.iter_mut().enumerate() {
+        let subset = subset_of(pixel);
+        let start = endpoints[subset * 2];
+        let end = endpoints[subset * 2 + 1];
+
+        let (color_weight, alpha_weight) = if mode.secondary_index_bits == 0 {
+            let weight = bc7_weights(mode.index_bits)[indices[pixel] as usize];
+            (weight, weight)
+        } else {
+            let primary = bc7_weights(mode.index_bits)[indices[pixel] as usize];
+            let secondary =
+                bc7_weights(mode.secondary_index_bits)[secondary_indices[pixel] as usize];
+            if index_selection == 0 {
+                (primary, secondary)
+            } else {
+                (secondary, primary)
+            }
+        };
+
+        for channel in 0..3 {
+            output[channel] = bc7_interpolate(start[channel], end[channel], color_weight);
+        }
+        output[3] = bc7_interpolate(start[3], end[3], alpha_weight);
+
+        match rotation {
+            1 => output.swap(0, 3),
+            2 => output.swap(1, 3),
+            3 => output.swap(2, 3),
+            _ => {}
+        }
+    }
+
+    pixels
+}
@@ -2,12 +2,11 @@ use anyhow::{anyhow, Result};
 use vulkanalia::prelude::v1_0::*;
 use vulkanalia::{vk, Device, Instance};
 
-use crate::core::graphics::swapchain::Swapchain;
+use crate::core::graphics::deletion_queue::DeviceResource;
 
 use super::create_image_view;
 use super::image::{create_vk_image, transition_image_layout};
 
-#[derive(Clone)]
 pub struct DepthBuffer {
     image: vk::Image,
     image_memory: vk::DeviceMemory,
@@ -25,13 +24,12 @@ impl DepthBuffer {
         instance: &Instance,
         device: &Device,
         physical_device: vk::PhysicalDevice,
-        swapchain: &Swapchain,
+        extent: vk::Extent2D,
         graphics_queue: vk::Queue,
         command_pool: vk::CommandPool,
     ) -> Result<Self> {
 
         let format = get_depth_format(instance, physical_device)?;
-        let extent = swapchain.get_extent();
         let (depth_image, depth_image_memory) = create_vk_image(
             instance,
             device,
@@ -73,6 +71,12 @@ impl DepthBuffer {
     }
 }
 
+impl DeviceResource for DepthBuffer {
+    unsafe fn destroy(&self, device: &Device) {
+        DepthBuffer::destroy(self, device);
+    }
+}
+
 pub unsafe fn get_depth_format(
     instance: &Instance,
     physical_device: vk::PhysicalDevice,
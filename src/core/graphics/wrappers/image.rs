@@ -3,26 +3,70 @@ use log::info;
 use png::ColorType;
 use std::{fs::File, path::Path};
 use vulkanalia::{
-    vk::{self, DeviceV1_0, HasBuilder},
+    vk::{self, DeviceV1_0, HasBuilder, InstanceV1_0},
     Device, Instance,
 };
 
 use crate::core::graphics::{
-    buffers::{create_buffer, get_memory_type_index},
+    buffers::get_memory_type_index,
     command_buffers::{begin_single_time_commands, end_single_time_commands},
+    deletion_queue::DeviceResource,
+    upload::{ImageUpload, UploadContext, UploadHandle},
 };
 
+use super::block_compression::BlockCompression;
 use super::depth_buffer::get_supported_format;
 
+/// How a texture uses its alpha channel, which decides the pass it is drawn in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AlphaMode {
+    /// Every pixel is fully opaque.
+    Opaque,
+    /// Pixels are either fully opaque or fully transparent, transparent ones are discarded.
+    Cutout,
+    /// Some pixels are partially transparent and need to be blended back to front.
+    Translucent,
+}
+
+impl AlphaMode {
+    /// Fragments with an alpha below this value are discarded by the mesh shader.
+    pub fn get_alpha_cutoff(&self) -> f32 {
+        match self {
+            AlphaMode::Opaque => 0.0,
+            AlphaMode::Cutout => 0.5,
+            AlphaMode::Translucent => 1.0 / 255.0,
+        }
+    }
+}
+
 pub struct Image {
     width: u32,
     height: u32,
-    pixels: Vec<u8>, // this is in [r, g, b, a] tuples
+    pixels: Vec<u8>, // this is in [r, g, b, a] tuples, or every mip level of blocks when compressed
     color_type: ColorType,
+    alpha_mode: AlphaMode,
+    compression: Option<BlockCompression>,
+    srgb: bool,
+    mip_levels: u32,
 }
 
 impl Image {
+    /// Load a PNG, or a KTX2 or DDS container holding BC1, BC3 or BC7 blocks,
+    /// depending on the file extension.
     pub fn load(filepath: &Path) -> Result<Self> {
+        let extension = filepath
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("ktx2") => Self::load_ktx2(filepath),
+            Some("dds") => Self::load_dds(filepath),
+            _ => Self::load_png(filepath),
+        }
+    }
+
+    fn load_png(filepath: &Path) -> Result<Self> {
         let image = File::open(filepath)?;
 
         let mut decoder = png::Decoder::new(image);
@@ -45,12 +89,370 @@ impl Image {
             filepath, color_type, size, width, height,
         );
 
+        let alpha_mode = detect_alpha_mode(&pixels, color_type);
+
         Ok(Image {
             width,
             height,
             pixels,
             color_type,
+            alpha_mode,
+            compression: None,
+            srgb: true,
+            mip_levels: 1,
+        })
+    }
+
+    fn load_ktx2(filepath: &Path) -> Result<Self> {
+        let data = std::fs::read(filepath)?;
+        let reader = ktx2::Reader::new(&data[..])?;
+        let header = reader.header();
+
+        if let Some(scheme) = header.supercompression_scheme {
+            return Err(anyhow!(
+                "KTX2 texture {:?} uses unsupported supercompression {:?}",
+                filepath, scheme
+            ));
+        }
+        if header.face_count > 1 || header.layer_count > 1 || header.pixel_depth > 1 {
+            return Err(anyhow!(
+                "KTX2 texture {:?} is not a single 2D image",
+                filepath
+            ));
+        }
+
+        let (compression, srgb) = match header.format {
+            Some(ktx2::Format::BC1_RGB_UNORM_BLOCK | ktx2::Format::BC1_RGBA_UNORM_BLOCK) => {
+                (BlockCompression::Bc1, false)
+            }
+            Some(ktx2::Format::BC1_RGB_SRGB_BLOCK | ktx2::Format::BC1_RGBA_SRGB_BLOCK) => {
+                (BlockCompression::Bc1, true)
+            }
+            Some(ktx2::Format::BC3_UNORM_BLOCK) => (BlockCompression::Bc3, false),
+            Some(ktx2::Format::BC3_SRGB_BLOCK) => (BlockCompression::Bc3, true),
+            Some(ktx2::Format::BC7_UNORM_BLOCK) => (BlockCompression::Bc7, false),
+            Some(ktx2::Format::BC7_SRGB_BLOCK) => (BlockCompression::Bc7, true),
+            format => {
+                return Err(anyhow!(
+                    "KTX2 texture {:?} has unsupported format {:?}",
+                    filepath, format
+                ))
+            }
+        };
+
+        let levels = reader.levels().map(|level| level.data).collect::<Vec<_>>();
+
+        Self::from_blocks(
+            filepath,
+            compression,
+            srgb,
+            header.pixel_width,
+            header.pixel_height,
+            &levels,
+        )
+    }
+
+    fn load_dds(filepath: &Path) -> Result<Self> {
+        let dds = ddsfile::Dds::read(File::open(filepath)?)?;
+
+        if dds.get_num_array_layers() > 1 || dds.get_depth() > 1 {
+            return Err(anyhow!(
+                "DDS texture {:?} is not a single 2D image",
+                filepath
+            ));
+        }
+
+        use ddsfile::{D3DFormat, DxgiFormat};
+        let (compression, srgb) = match (dds.get_dxgi_format(), dds.get_d3d_format()) {
+            (Some(DxgiFormat::BC1_UNorm), _) => (BlockCompression::Bc1, false),
+            (Some(DxgiFormat::BC1_UNorm_sRGB), _) => (BlockCompression::Bc1, true),
+            (Some(DxgiFormat::BC3_UNorm), _) => (BlockCompression::Bc3, false),
+            (Some(DxgiFormat::BC3_UNorm_sRGB), _) => (BlockCompression::Bc3, true),
+            (Some(DxgiFormat::BC7_UNorm), _) => (BlockCompression::Bc7, false),
+            (Some(DxgiFormat::BC7_UNorm_sRGB), _) => (BlockCompression::Bc7, true),
+            // legacy headers don't record a color space, treat them like our PNGs
+            (None, Some(D3DFormat::DXT1)) => (BlockCompression::Bc1, true),
+            (None, Some(D3DFormat::DXT5)) => (BlockCompression::Bc3, true),
+            (dxgi_format, d3d_format) => {
+                return Err(anyhow!(
+                    "DDS texture {:?} has unsupported format {:?} / {:?}",
+                    filepath, dxgi_format, d3d_format
+                ))
+            }
+        };
+
+        let (width, height) = (dds.get_width(), dds.get_height());
+        let mut data = dds.get_data(0)?;
+        let mut levels = Vec::new();
+        for level in 0..dds.get_num_mipmap_levels().max(1) {
+            let (level_width, level_height) = get_mip_level_extent(width, height, level);
+            let size = compression.get_level_size(level_width, level_height).min(data.len());
+            let (level_data, rest) = data.split_at(size);
+            levels.push(level_data);
+            data = rest;
+        }
+
+        Self::from_blocks(filepath, compression, srgb, width, height, &levels)
+    }
+
+    /// Keep the blocks of every mip level as they are, so they can be uploaded
+    /// without decoding when the device supports the format.
+    fn from_blocks(
+        filepath: &Path,
+        compression: BlockCompression,
+        srgb: bool,
+        width: u32,
+        height: u32,
+        levels: &[&[u8]],
+    ) -> Result<Self> {
+        if width == 0 || height == 0 || levels.is_empty() {
+            return Err(anyhow!("Compressed texture {:?} is empty", filepath));
+        }
+
+        let mut pixels = Vec::new();
+        for (level, data) in levels.iter().enumerate() {
+            let (level_width, level_height) = get_mip_level_extent(width, height, level as u32);
+            let expected_size = compression.get_level_size(level_width, level_height);
+            if data.len() != expected_size {
+                return Err(anyhow!(
+                    "Mip level {} of {:?} has {} bytes, expected {} for {}x{} {:?}",
+                    level, filepath, data.len(), expected_size, level_width, level_height, compression
+                ));
+            }
+            pixels.extend_from_slice(data);
+        }
+
+        // the alpha mode is only known after decoding, the first level decides it
+        let alpha_mode = detect_alpha_mode(
+            &compression.decode(levels[0], width, height)?,
+            ColorType::Rgba,
+        );
+
+        info!(
+            "Reading compressed image from path {:?} with {:?} srgb {} width {} height {} mip levels {}",
+            filepath, compression, srgb, width, height, levels.len(),
+        );
+
+        Ok(Image {
+            width,
+            height,
+            pixels,
+            color_type: ColorType::Rgba,
+            alpha_mode,
+            compression: Some(compression),
+            srgb,
+            mip_levels: levels.len() as u32,
+        })
+    }
+
+    /// Wrap already decoded pixels, given as [r, g, b, a] tuples row by row.
+    pub fn from_rgba(width: u32, height: u32, pixels: Vec<u8>) -> Result<Self> {
+        let expected_size = width as usize * height as usize * 4;
+        if pixels.len() != expected_size {
+            return Err(anyhow!(
+                "Image of {}x{} needs {} bytes of RGBA pixels, got {}",
+                width, height, expected_size, pixels.len()
+            ));
+        }
+
+        let alpha_mode = detect_alpha_mode(&pixels, ColorType::Rgba);
+
+        Ok(Image {
+            width,
+            height,
+            pixels,
+            color_type: ColorType::Rgba,
+            alpha_mode,
+            compression: None,
+            srgb: true,
+            mip_levels: 1,
+        })
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    /// Override the detected alpha mode, for example to force a texture with soft
+    /// edges to be drawn as a cutout.
+    pub fn with_alpha_mode(mut self, alpha_mode: AlphaMode) -> Self {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+
+    /// Whether the color channels are sRGB encoded and converted to linear when
+    /// sampled. Images holding data rather than colors, like palette indices, are not.
+    pub fn with_srgb(mut self, srgb: bool) -> Self {
+        self.srgb = srgb;
+        self
+    }
+
+    /// The [r, g, b, a] tuples row by row, unless the image is block compressed.
+    pub fn get_rgba_pixels(&self) -> Option<&[u8]> {
+        match (self.compression, self.color_type) {
+            (None, ColorType::Rgba) => Some(&self.pixels),
+            _ => None,
+        }
+    }
+
+    /// Decode every mip level of a compressed image into [r, g, b, a] tuples, one
+    /// level after another.
+    fn decode_mip_levels(&self, compression: BlockCompression) -> Result<Vec<u8>> {
+        let offsets =
+            get_mip_level_offsets(self.width, self.height, self.mip_levels, Some(compression));
+        let mut pixels = Vec::new();
+        for (level, offset) in offsets.iter().enumerate() {
+            let (width, height) = get_mip_level_extent(self.width, self.height, level as u32);
+            let offset = *offset as usize;
+            let size = compression.get_level_size(width, height);
+            pixels.extend(compression.decode(&self.pixels[offset..offset + size], width, height)?);
+        }
+        Ok(pixels)
+    }
+}
+
+fn get_mip_level_extent(width: u32, height: u32, level: u32) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+/// Byte offset of every mip level when they are stored one after another, either as
+/// blocks or as [r, g, b, a] tuples.
+fn get_mip_level_offsets(
+    width: u32,
+    height: u32,
+    mip_levels: u32,
+    compression: Option<BlockCompression>,
+) -> Vec<u64> {
+    let mut offset = 0;
+    (0..mip_levels)
+        .map(|level| {
+            let level_offset = offset;
+            let (level_width, level_height) = get_mip_level_extent(width, height, level);
+            offset += match compression {
+                Some(compression) => compression.get_level_size(level_width, level_height),
+                None => level_width as usize * level_height as usize * 4,
+            } as u64;
+            level_offset
         })
+        .collect()
+}
+
+fn detect_alpha_mode(pixels: &[u8], color_type: ColorType) -> AlphaMode {
+    let channels = match color_type {
+        ColorType::Rgba => 4,
+        ColorType::GrayscaleAlpha => 2,
+        _ => return AlphaMode::Opaque,
+    };
+
+    let mut has_transparent_pixels = false;
+    for pixel in pixels.chunks_exact(channels) {
+        match pixel[channels - 1] {
+            u8::MAX => {}
+            0 => has_transparent_pixels = true,
+            _ => return AlphaMode::Translucent,
+        }
+    }
+
+    if has_transparent_pixels {
+        AlphaMode::Cutout
+    } else {
+        AlphaMode::Opaque
+    }
+}
+
+/// Six square faces in Vulkan layer order: +X, -X, +Y, -Y, +Z, -Z.
+pub struct CubemapImage {
+    face_size: u32,
+    pixels: Vec<u8>, // faces one after another, each in [r, g, b, a] tuples
+}
+
+impl CubemapImage {
+    pub const FACE_COUNT: usize = 6;
+
+    /// Load six separate face images, given in +X, -X, +Y, -Y, +Z, -Z order.
+    pub fn load_faces(filepaths: [&Path; 6]) -> Result<Self> {
+        let faces = filepaths
+            .iter()
+            .map(|filepath| Image::load(filepath))
+            .collect::<Result<Vec<Image>>>()?;
+
+        let face_size = faces[0].width;
+        let mut pixels = Vec::with_capacity(faces[0].pixels.len() * Self::FACE_COUNT);
+        for (face, filepath) in faces.iter().zip(filepaths.iter()) {
+            if face.width != face_size || face.height != face_size {
+                return Err(anyhow!(
+                    "Cubemap face {:?} is {}x{}, expected {}x{}",
+                    filepath, face.width, face.height, face_size, face_size
+                ));
+            }
+            if face.color_type != ColorType::Rgba || face.compression.is_some() {
+                return Err(anyhow!(
+                    "Cubemap face {:?} has unsupported color type {:?}",
+                    filepath, face.color_type
+                ));
+            }
+            pixels.extend_from_slice(&face.pixels);
+        }
+
+        Ok(Self { face_size, pixels })
+    }
+
+    /// Load a single image holding all faces in a horizontal cross:
+    ///
+    /// ```text
+    ///     +Y
+    /// -X  +Z  +X  -Z
+    ///     -Y
+    /// ```
+    pub fn load_cross(filepath: &Path) -> Result<Self> {
+        let image = Image::load(filepath)?;
+
+        let face_size = image.width / 4;
+        if face_size == 0 || image.width != face_size * 4 || image.height != face_size * 3 {
+            return Err(anyhow!(
+                "Cubemap cross {:?} is {}x{}, expected a 4:3 horizontal cross",
+                filepath, image.width, image.height
+            ));
+        }
+        if image.color_type != ColorType::Rgba || image.compression.is_some() {
+            return Err(anyhow!(
+                "Cubemap cross {:?} has unsupported color type {:?}",
+                filepath, image.color_type
+            ));
+        }
+
+        // (column, row) of each face within the cross, in layer order
+        const FACE_CELLS: [(u32, u32); 6] = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)];
+        const BYTES_PER_PIXEL: usize = 4;
+
+        let row_bytes = face_size as usize * BYTES_PER_PIXEL;
+        let image_row_bytes = image.width as usize * BYTES_PER_PIXEL;
+        let mut pixels = Vec::with_capacity(row_bytes * face_size as usize * Self::FACE_COUNT);
+        for (column, row) in FACE_CELLS {
+            for y in 0..face_size {
+                let start = (row * face_size + y) as usize * image_row_bytes
+                    + (column * face_size) as usize * BYTES_PER_PIXEL;
+                pixels.extend_from_slice(&image.pixels[start..start + row_bytes]);
+            }
+        }
+
+        Ok(Self { face_size, pixels })
+    }
+
+    /// A 1x1 cubemap of a single color, used when nothing better is bound.
+    pub fn from_color(color: [u8; 4]) -> Self {
+        Self {
+            face_size: 1,
+            pixels: color.repeat(Self::FACE_COUNT),
+        }
     }
 }
 
@@ -58,12 +460,29 @@ pub struct LoadedImage {
     image: vk::Image,
     memory: vk::DeviceMemory,
     image_view: vk::ImageView,
+    // the batch its pixels are copied in, it can't be sampled before that finished
+    upload: UploadHandle,
+    // storage images stay in the general layout, everything else is sampled read only
+    sampled_layout: vk::ImageLayout,
 }
 
 impl LoadedImage {
+    pub fn get_image(&self) -> vk::Image {
+        self.image
+    }
+
     pub fn get_image_view(&self) -> vk::ImageView {
         self.image_view
     }
+
+    pub fn get_upload(&self) -> UploadHandle {
+        self.upload
+    }
+
+    /// The layout shaders find the image in when they sample it.
+    pub fn get_sampled_layout(&self) -> vk::ImageLayout {
+        self.sampled_layout
+    }
 }
 
 impl LoadedImage {
@@ -72,51 +491,115 @@ impl LoadedImage {
         instance: &Instance,
         device: &Device,
         physical_device: vk::PhysicalDevice,
-        graphics_queue: vk::Queue,
-        command_pool: vk::CommandPool,
+        upload_context: &UploadContext,
     ) -> Result<Self> {
-        use std::ptr::copy_nonoverlapping as memcpy;
-        let size = image.pixels.len();
+        let tiling = vk::ImageTiling::OPTIMAL;
+        let features = vk::FormatFeatureFlags::TRANSFER_DST | vk::FormatFeatureFlags::SAMPLED_IMAGE;
+
+        let (pixels, color_format) = match image.compression {
+            None if !image.srgb && image.color_type == ColorType::Rgba => (
+                None,
+                get_supported_format(instance, physical_device, &[vk::Format::R8G8B8A8_UNORM], tiling, features)?,
+            ),
+            None => (
+                None,
+                get_supported_color_format(instance, physical_device, image.color_type, tiling, features)?,
+            ),
+            Some(compression) => {
+                let block_format = compression.get_vk_format(image.srgb);
+                if supports_block_compression(instance, physical_device, block_format, tiling, features) {
+                    (None, block_format)
+                } else {
+                    info!(
+                        "Device can't sample {:?}, decoding texture on the CPU",
+                        block_format
+                    );
+                    let candidates: &[vk::Format] = if image.srgb {
+                        &[vk::Format::R8G8B8A8_SRGB]
+                    } else {
+                        &[vk::Format::R8G8B8A8_UNORM]
+                    };
+                    (
+                        Some(image.decode_mip_levels(compression)?),
+                        get_supported_format(instance, physical_device, candidates, tiling, features)?,
+                    )
+                }
+            }
+        };
 
-        let (staging_buffer, staging_buffer_memory) = create_buffer(
+        // decoded levels are tightly packed RGBA, just like an uncompressed image
+        let mip_level_offsets = get_mip_level_offsets(
+            image.width,
+            image.height,
+            image.mip_levels,
+            if pixels.is_some() { None } else { image.compression },
+        );
+
+        Self::upload(
+            pixels.as_deref().unwrap_or(&image.pixels),
+            color_format,
+            image.width,
+            image.height,
+            &mip_level_offsets,
+            ImageLayers::Single,
             instance,
             device,
             physical_device,
-            size as u64,
-            vk::BufferUsageFlags::TRANSFER_SRC,
-            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
-        )?;
-
-        let memory = device.map_memory(
-            staging_buffer_memory,
-            0,
-            size as u64,
-            vk::MemoryMapFlags::empty(),
-        )?;
-
-        memcpy(image.pixels.as_ptr(), memory.cast(), size);
-
-        device.unmap_memory(staging_buffer_memory);
-
-        let tiling = vk::ImageTiling::OPTIMAL;
+            upload_context,
+        )
+    }
 
+    /// Upload all six faces of a cubemap into a single cube compatible image,
+    /// viewed as a `samplerCube`.
+    pub unsafe fn load_cubemap_into_memory(
+        cubemap: &CubemapImage,
+        instance: &Instance,
+        device: &Device,
+        physical_device: vk::PhysicalDevice,
+        upload_context: &UploadContext,
+    ) -> Result<Self> {
         let color_format = get_supported_color_format(
             instance,
             physical_device,
-            image.color_type,
-            tiling,
+            ColorType::Rgba,
+            vk::ImageTiling::OPTIMAL,
             vk::FormatFeatureFlags::TRANSFER_DST | vk::FormatFeatureFlags::SAMPLED_IMAGE,
         )?;
 
-        let (texture_image, texture_image_memory) = create_vk_image(
+        Self::upload(
+            &cubemap.pixels,
+            color_format,
+            cubemap.face_size,
+            cubemap.face_size,
+            &[0],
+            ImageLayers::Cube,
             instance,
             device,
             physical_device,
-            image.width,
-            image.height,
+            upload_context,
+        )
+    }
+
+    /// An image that render passes draw into and meshes then sample. It starts out
+    /// in the layout meshes sample from, so it can be bound before anything is drawn.
+    pub unsafe fn create_render_target(
+        instance: &Instance,
+        device: &Device,
+        physical_device: vk::PhysicalDevice,
+        graphics_queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        color_format: vk::Format,
+        extent: vk::Extent2D,
+    ) -> Result<Self> {
+        let (image, memory) = create_vk_image(
+            instance,
+            device,
+            physical_device,
+            extent.width,
+            extent.height,
             color_format,
-            tiling,
-            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
             vk::MemoryPropertyFlags::DEVICE_LOCAL,
         )?;
 
@@ -124,56 +607,176 @@ impl LoadedImage {
             device,
             graphics_queue,
             command_pool,
-            texture_image,
+            image,
             color_format,
             vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
         )?;
 
-        copy_buffer_to_image(
+        let image_view = create_image_view(device, image, color_format, vk::ImageAspectFlags::COLOR)?;
+
+        Ok(Self {
+            image,
+            memory,
+            image_view,
+            upload: UploadHandle::READY,
+            sampled_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        })
+    }
+
+    /// An image compute shaders write into, kept in the general layout so it can be
+    /// bound as a storage image and sampled alike.
+    pub unsafe fn create_storage_image(
+        instance: &Instance,
+        device: &Device,
+        physical_device: vk::PhysicalDevice,
+        graphics_queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        format: vk::Format,
+        extent: vk::Extent2D,
+    ) -> Result<Self> {
+        let (image, memory) = create_vk_image(
+            instance,
             device,
-            graphics_queue,
-            command_pool,
-            staging_buffer,
-            texture_image,
-            image.width,
-            image.height,
+            physical_device,
+            extent.width,
+            extent.height,
+            format,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
         )?;
 
         transition_image_layout(
             device,
             graphics_queue,
             command_pool,
-            texture_image,
+            image,
+            format,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+        )?;
+
+        let image_view = create_image_view(device, image, format, vk::ImageAspectFlags::COLOR)?;
+
+        Ok(Self {
+            image,
+            memory,
+            image_view,
+            upload: UploadHandle::READY,
+            sampled_layout: vk::ImageLayout::GENERAL,
+        })
+    }
+
+    /// Upload every mip level, starting at the given offsets within `pixels`, into a
+    /// new image of the given format.
+    unsafe fn upload(
+        pixels: &[u8],
+        color_format: vk::Format,
+        width: u32,
+        height: u32,
+        mip_level_offsets: &[u64],
+        layers: ImageLayers,
+        instance: &Instance,
+        device: &Device,
+        physical_device: vk::PhysicalDevice,
+        upload_context: &UploadContext,
+    ) -> Result<Self> {
+        let tiling = vk::ImageTiling::OPTIMAL;
+        let mip_levels = mip_level_offsets.len() as u32;
+
+        let (texture_image, texture_image_memory) = create_layered_vk_image(
+            instance,
+            device,
+            physical_device,
+            width,
+            height,
             color_format,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            tiling,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            layers,
+            mip_levels,
         )?;
 
-        device.destroy_buffer(staging_buffer, None);
-        device.free_memory(staging_buffer_memory, None);
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(mip_levels)
+            .base_array_layer(0)
+            .layer_count(layers.count())
+            .build();
+        let regions = get_layered_copy_regions(width, height, layers, mip_level_offsets);
+        let upload = upload_context.upload_image(
+            device,
+            pixels,
+            ImageUpload {
+                image: texture_image,
+                subresource_range,
+                regions: &regions,
+            },
+        )?;
 
-        let texture_image_view = create_image_view(
+        let texture_image_view = create_layered_image_view(
             device,
             texture_image,
             color_format,
             vk::ImageAspectFlags::COLOR,
+            layers,
+            mip_levels,
         )?;
 
         Ok(Self {
             image: texture_image,
             memory: texture_image_memory,
             image_view: texture_image_view,
+            upload,
+            sampled_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
         })
     }
 
-    pub unsafe fn destroy(&self, device: &Device) {
+}
+
+impl DeviceResource for LoadedImage {
+    unsafe fn destroy(&self, device: &Device) {
         device.destroy_image(self.image, None);
         device.free_memory(self.memory, None);
         device.destroy_image_view(self.image_view, None);
     }
 }
 
+/// How many array layers an image has and how they are viewed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageLayers {
+    /// A plain 2D image
+    Single,
+    /// Six layers viewed as the faces of a cube
+    Cube,
+}
+
+impl ImageLayers {
+    fn count(&self) -> u32 {
+        match self {
+            ImageLayers::Single => 1,
+            ImageLayers::Cube => CubemapImage::FACE_COUNT as u32,
+        }
+    }
+
+    fn create_flags(&self) -> vk::ImageCreateFlags {
+        match self {
+            ImageLayers::Single => vk::ImageCreateFlags::empty(),
+            ImageLayers::Cube => vk::ImageCreateFlags::CUBE_COMPATIBLE,
+        }
+    }
+
+    fn view_type(&self) -> vk::ImageViewType {
+        match self {
+            ImageLayers::Single => vk::ImageViewType::_2D,
+            ImageLayers::Cube => vk::ImageViewType::CUBE,
+        }
+    }
+}
+
 pub unsafe fn create_vk_image(
     instance: &Instance,
     device: &Device,
@@ -184,6 +787,34 @@ pub unsafe fn create_vk_image(
     tiling: vk::ImageTiling,
     usage: vk::ImageUsageFlags,
     memory_property_mode: vk::MemoryPropertyFlags,
+) -> Result<(vk::Image, vk::DeviceMemory)> {
+    create_layered_vk_image(
+        instance,
+        device,
+        physical_device,
+        width,
+        height,
+        format,
+        tiling,
+        usage,
+        memory_property_mode,
+        ImageLayers::Single,
+        1,
+    )
+}
+
+pub unsafe fn create_layered_vk_image(
+    instance: &Instance,
+    device: &Device,
+    physical_device: vk::PhysicalDevice,
+    width: u32,
+    height: u32,
+    format: vk::Format,
+    tiling: vk::ImageTiling,
+    usage: vk::ImageUsageFlags,
+    memory_property_mode: vk::MemoryPropertyFlags,
+    layers: ImageLayers,
+    mip_levels: u32,
 ) -> Result<(vk::Image, vk::DeviceMemory)> {
     let info = vk::ImageCreateInfo::builder()
         .image_type(vk::ImageType::_2D)
@@ -193,15 +824,15 @@ pub unsafe fn create_vk_image(
             depth: 1,
         })
         .image_type(vk::ImageType::_2D)
-        .mip_levels(1)
-        .array_layers(1)
+        .mip_levels(mip_levels)
+        .array_layers(layers.count())
         .format(format)
         .tiling(tiling)
         .initial_layout(vk::ImageLayout::UNDEFINED)
         .usage(usage)
         .sharing_mode(vk::SharingMode::EXCLUSIVE)
         .samples(vk::SampleCountFlags::_1)
-        .flags(vk::ImageCreateFlags::empty());
+        .flags(layers.create_flags());
 
     let texture_image = device.create_image(&info, None)?;
 
@@ -231,6 +862,30 @@ pub unsafe fn transition_image_layout(
     format: vk::Format,
     old_layout: vk::ImageLayout,
     new_layout: vk::ImageLayout,
+) -> Result<()> {
+    transition_layered_image_layout(
+        device,
+        graphics_queue,
+        command_pool,
+        image,
+        format,
+        old_layout,
+        new_layout,
+        ImageLayers::Single,
+        1,
+    )
+}
+
+pub unsafe fn transition_layered_image_layout(
+    device: &Device,
+    graphics_queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    image: vk::Image,
+    format: vk::Format,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    layers: ImageLayers,
+    mip_levels: u32,
 ) -> Result<()> {
     let command_buffer = begin_single_time_commands(device, command_pool)?;
 
@@ -248,6 +903,18 @@ pub unsafe fn transition_image_layout(
                 vk::PipelineStageFlags::TRANSFER,
                 vk::PipelineStageFlags::FRAGMENT_SHADER,
             ),
+            (vk::ImageLayout::UNDEFINED, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => (
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::SHADER_READ,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+            ),
+            (vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL) => (
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+            ),
             (vk::ImageLayout::UNDEFINED, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL) => (
                 vk::AccessFlags::empty(),
                 vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
@@ -272,9 +939,9 @@ pub unsafe fn transition_image_layout(
     let subresource = vk::ImageSubresourceRange::builder()
         .aspect_mask(aspect_mask)
         .base_mip_level(0)
-        .level_count(1)
+        .level_count(mip_levels)
         .base_array_layer(0)
-        .layer_count(1);
+        .layer_count(layers.count());
 
     let barrier = vk::ImageMemoryBarrier::builder()
         .old_layout(old_layout)
@@ -301,46 +968,40 @@ pub unsafe fn transition_image_layout(
     Ok(())
 }
 
-pub unsafe fn copy_buffer_to_image(
-    device: &Device,
-    graphics_queue: vk::Queue,
-    command_pool: vk::CommandPool,
-    buffer: vk::Buffer,
-    image: vk::Image,
+/// Copies of tightly packed layers, one after another in the buffer, into an image.
+/// Every mip level starts at its own offset and holds all layers of that level.
+fn get_layered_copy_regions(
     width: u32,
     height: u32,
-) -> Result<()> {
-    let command_buffer = begin_single_time_commands(device, command_pool)?;
-
-    let subresource = vk::ImageSubresourceLayers::builder()
-        .aspect_mask(vk::ImageAspectFlags::COLOR)
-        .mip_level(0)
-        .base_array_layer(0)
-        .layer_count(1);
-
-    let region = vk::BufferImageCopy::builder()
-        .buffer_offset(0)
-        .buffer_row_length(0) // means they are tightly packed in memory
-        .buffer_image_height(0) // means they are tightly packed in memory
-        .image_subresource(subresource)
-        .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
-        .image_extent(vk::Extent3D {
-            width,
-            height,
-            depth: 1,
-        });
-
-    device.cmd_copy_buffer_to_image(
-        command_buffer,
-        buffer,
-        image,
-        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-        &[region],
-    );
+    layers: ImageLayers,
+    mip_level_offsets: &[u64],
+) -> Vec<vk::BufferImageCopy> {
+    mip_level_offsets
+        .iter()
+        .enumerate()
+        .map(|(level, offset)| {
+            let (level_width, level_height) = get_mip_level_extent(width, height, level as u32);
 
-    end_single_time_commands(device, graphics_queue, command_pool, command_buffer)?;
+            let subresource = vk::ImageSubresourceLayers::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(level as u32)
+                .base_array_layer(0)
+                .layer_count(layers.count());
 
-    Ok(())
+            vk::BufferImageCopy::builder()
+                .buffer_offset(*offset)
+                .buffer_row_length(0) // means they are tightly packed in memory
+                .buffer_image_height(0) // means they are tightly packed in memory
+                .image_subresource(subresource)
+                .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+                .image_extent(vk::Extent3D {
+                    width: level_width,
+                    height: level_height,
+                    depth: 1,
+                })
+                .build()
+        })
+        .collect()
 }
 
 pub unsafe fn create_image_view(
@@ -348,17 +1009,28 @@ pub unsafe fn create_image_view(
     image: vk::Image,
     format: vk::Format,
     aspects: vk::ImageAspectFlags,
+) -> Result<vk::ImageView> {
+    create_layered_image_view(device, image, format, aspects, ImageLayers::Single, 1)
+}
+
+pub unsafe fn create_layered_image_view(
+    device: &Device,
+    image: vk::Image,
+    format: vk::Format,
+    aspects: vk::ImageAspectFlags,
+    layers: ImageLayers,
+    mip_levels: u32,
 ) -> Result<vk::ImageView> {
     let subresource_range = vk::ImageSubresourceRange::builder()
         .aspect_mask(aspects)
         .base_mip_level(0)
-        .level_count(1)
+        .level_count(mip_levels)
         .base_array_layer(0)
-        .layer_count(1);
+        .layer_count(layers.count());
 
     let info = vk::ImageViewCreateInfo::builder()
         .image(image)
-        .view_type(vk::ImageViewType::_2D)
+        .view_type(layers.view_type())
         .format(format)
         .subresource_range(subresource_range);
 
@@ -392,3 +1064,18 @@ unsafe fn get_supported_color_format(
 
     get_supported_format(instance, physical_device, candidates, tiling, features)
 }
+
+/// Block compressed formats need the device feature on top of format support.
+unsafe fn supports_block_compression(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    format: vk::Format,
+    tiling: vk::ImageTiling,
+    features: vk::FormatFeatureFlags,
+) -> bool {
+    instance
+        .get_physical_device_features(physical_device)
+        .texture_compression_bc
+        == vk::TRUE
+        && get_supported_format(instance, physical_device, &[format], tiling, features).is_ok()
+}
@@ -1,4 +1,8 @@
 use anyhow::Result;
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
 use vulkanalia::{
     vk::{self, DeviceV1_0, HasBuilder},
     Device,
@@ -6,13 +10,122 @@ use vulkanalia::{
 
 use super::LoadedImage;
 
+/// How a texture is filtered and addressed. Equal descriptions share one sampler
+/// through the [`SamplerCache`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SamplerDescription {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    pub address_mode_w: vk::SamplerAddressMode,
+    /// `None` disables anisotropic filtering
+    pub max_anisotropy: Option<f32>,
+    pub min_lod: f32,
+    pub max_lod: f32,
+    /// Only used with `CLAMP_TO_BORDER` addressing
+    pub border_color: vk::BorderColor,
+}
+
+impl Default for SamplerDescription {
+    /// Crisp pixel art: nearest filtering, repeating, every mip level.
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            max_anisotropy: Some(16.0),
+            min_lod: 0.0,
+            max_lod: vk::LOD_CLAMP_NONE,
+            border_color: vk::BorderColor::INT_OPAQUE_BLACK,
+        }
+    }
+}
+
+impl SamplerDescription {
+    pub fn nearest() -> Self {
+        Self::default()
+    }
+
+    /// Smooth filtering between texels and between mip levels.
+    pub fn linear() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            ..Self::default()
+        }
+    }
+
+    /// Linear filtering without wrapping, so cube faces blend into each other
+    /// instead of showing seams.
+    pub fn cubemap() -> Self {
+        Self {
+            max_anisotropy: None,
+            max_lod: 0.0,
+            ..Self::linear()
+        }
+        .with_address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+    }
+
+    /// Use the same addressing in every direction.
+    pub fn with_address_mode(mut self, address_mode: vk::SamplerAddressMode) -> Self {
+        self.address_mode_u = address_mode;
+        self.address_mode_v = address_mode;
+        self.address_mode_w = address_mode;
+        self
+    }
+
+    pub fn with_max_anisotropy(mut self, max_anisotropy: Option<f32>) -> Self {
+        self.max_anisotropy = max_anisotropy;
+        self
+    }
+
+    pub fn with_lod_range(mut self, min_lod: f32, max_lod: f32) -> Self {
+        self.min_lod = min_lod;
+        self.max_lod = max_lod;
+        self
+    }
+
+    pub fn with_border_color(mut self, border_color: vk::BorderColor) -> Self {
+        self.border_color = border_color;
+        self
+    }
+}
+
+// floats are compared bit for bit, two descriptions that differ only in the sign
+// of a zero simply get separate samplers
+impl Eq for SamplerDescription {}
+
+impl Hash for SamplerDescription {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.mag_filter.hash(state);
+        self.min_filter.hash(state);
+        self.mipmap_mode.hash(state);
+        self.address_mode_u.hash(state);
+        self.address_mode_v.hash(state);
+        self.address_mode_w.hash(state);
+        self.max_anisotropy.map(f32::to_bits).hash(state);
+        self.min_lod.to_bits().hash(state);
+        self.max_lod.to_bits().hash(state);
+        self.border_color.hash(state);
+    }
+}
+
+/// A sampler handle. Samplers handed out by the [`SamplerCache`] are shared and
+/// destroyed together with the cache, never individually.
+#[derive(Copy, Clone)]
 pub struct ImageSampler {
     sampler: vk::Sampler,
 }
 
 impl ImageSampler {
-    pub unsafe fn create(device: &Device) -> Result<Self> {
-        let sampler = unsafe { create_image_sampler(device)? };
+    pub unsafe fn create(device: &Device, description: &SamplerDescription) -> Result<Self> {
+        let sampler = unsafe { create_image_sampler(device, description)? };
 
         Ok(Self { sampler })
     }
@@ -26,23 +139,76 @@ impl ImageSampler {
     }
 }
 
-pub unsafe fn create_image_sampler(device: &Device) -> Result<vk::Sampler> {
+/// Creates one sampler per distinct [`SamplerDescription`].
+pub struct SamplerCache {
+    samplers: HashMap<SamplerDescription, ImageSampler>,
+    // `None` when the device lacks sampler anisotropy
+    max_anisotropy: Option<f32>,
+}
+
+impl SamplerCache {
+    /// Anisotropy asked for by descriptions is clamped to `max_anisotropy`, or
+    /// turned off when it is `None`.
+    pub fn new(max_anisotropy: Option<f32>) -> Self {
+        Self {
+            samplers: HashMap::new(),
+            max_anisotropy,
+        }
+    }
+
+    pub unsafe fn get_or_create(
+        &mut self,
+        device: &Device,
+        description: &SamplerDescription,
+    ) -> Result<ImageSampler> {
+        if let Some(sampler) = self.samplers.get(description) {
+            return Ok(*sampler);
+        }
+
+        let supported_description = description.with_max_anisotropy(
+            description
+                .max_anisotropy
+                .zip(self.max_anisotropy)
+                .map(|(requested, supported)| requested.min(supported)),
+        );
+        let sampler = ImageSampler::create(device, &supported_description)?;
+        self.samplers.insert(*description, sampler);
+        log::info!(
+            "Created sampler {:?}, {} samplers cached",
+            description,
+            self.samplers.len()
+        );
+
+        Ok(sampler)
+    }
+
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        for (_, sampler) in self.samplers.drain() {
+            sampler.destroy(device);
+        }
+    }
+}
+
+pub unsafe fn create_image_sampler(
+    device: &Device,
+    description: &SamplerDescription,
+) -> Result<vk::Sampler> {
     let info = vk::SamplerCreateInfo::builder()
-        .mag_filter(vk::Filter::NEAREST)
-        .min_filter(vk::Filter::NEAREST)
-        .address_mode_u(vk::SamplerAddressMode::REPEAT)
-        .address_mode_w(vk::SamplerAddressMode::REPEAT)
-        .address_mode_v(vk::SamplerAddressMode::REPEAT)
-        .anisotropy_enable(true)
-        .max_anisotropy(16.0)
-        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .mag_filter(description.mag_filter)
+        .min_filter(description.min_filter)
+        .address_mode_u(description.address_mode_u)
+        .address_mode_w(description.address_mode_w)
+        .address_mode_v(description.address_mode_v)
+        .anisotropy_enable(description.max_anisotropy.is_some())
+        .max_anisotropy(description.max_anisotropy.unwrap_or(1.0))
+        .border_color(description.border_color)
         .unnormalized_coordinates(false)
         .compare_enable(false)
         .compare_op(vk::CompareOp::ALWAYS)
-        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .mipmap_mode(description.mipmap_mode)
         .mip_lod_bias(0.0)
-        .min_lod(0.0)
-        .max_lod(0.0);
+        .min_lod(description.min_lod)
+        .max_lod(description.max_lod);
 
     let texture_sampler = device.create_sampler(&info, None)?;
 
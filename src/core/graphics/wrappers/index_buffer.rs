@@ -3,7 +3,8 @@ use std::mem::size_of;
 use vulkanalia::vk::{self};
 use vulkanalia::prelude::v1_0::*;
 
-use super::super::buffers::{create_buffer, copy_buffer};
+use super::super::buffers::create_buffer;
+use super::super::upload::{UploadContext, UploadHandle};
 
 pub struct Index(u16);
 
@@ -18,49 +19,23 @@ impl IndexBuffer {
         instance: &Instance, 
         device: &Device, 
         physical_device: vk::PhysicalDevice, 
-        command_pool: vk::CommandPool,
-        graphics_queue: vk::Queue,
+        upload_context: &UploadContext,
         indices: &[u16],
-    ) -> Result<IndexBuffer> {
-        use std::ptr::copy_nonoverlapping as memcpy;
-
+    ) -> Result<(IndexBuffer, UploadHandle)> {
         let size: u64 = (size_of::<Index>() * indices.len()) as u64;
 
-        let memory_property_flags = vk::MemoryPropertyFlags::HOST_COHERENT | 
-                                    vk::MemoryPropertyFlags::HOST_VISIBLE;
-        let (staging_buffer, staging_buffer_memory) = create_buffer(
-            instance, device, physical_device, size, 
-            vk::BufferUsageFlags::TRANSFER_SRC, 
-            memory_property_flags)?;
-
-        let memory_offset: u64 = 0;
-        let gpu_memory = device.map_memory(
-            staging_buffer_memory,
-            memory_offset, 
-            size, 
-            vk::MemoryMapFlags::empty()
-        )?;
-
-        memcpy(indices.as_ptr(), gpu_memory.cast(), indices.len());
-
-        device.unmap_memory(staging_buffer_memory);
-
         let (index_buffer, index_buffer_memory) = create_buffer(
             instance, device, physical_device,
             size, vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::INDEX_BUFFER, 
             vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
 
+        let data = std::slice::from_raw_parts(indices.as_ptr().cast::<u8>(), size as usize);
+        let upload = upload_context.upload_buffer(device, data, index_buffer)?;
 
-        copy_buffer(device, staging_buffer, index_buffer, size, 
-                    command_pool, graphics_queue)?;
-
-        device.destroy_buffer(staging_buffer, None);
-        device.free_memory(staging_buffer_memory, None);
-
-        Ok(IndexBuffer {
+        Ok((IndexBuffer {
             buffer: index_buffer, 
             memory: index_buffer_memory
-        })
+        }, upload))
     }
 
     pub unsafe fn destroy(buffer: IndexBuffer, device: &Device) {
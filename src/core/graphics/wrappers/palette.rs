@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+use super::image::Image;
+
+/// Colors an indexed texture can show, at most [`Palette::MAX_COLORS`] of them.
+/// A texture is authored against a base palette, and drawn with any palette of the
+/// same length that recolors it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    /// Indices are stored in a single 8 bit channel
+    pub const MAX_COLORS: usize = 256;
+
+    pub fn new(colors: Vec<[u8; 3]>) -> Result<Self> {
+        if colors.len() > Self::MAX_COLORS {
+            return Err(anyhow!(
+                "Palette of {} colors is larger than {}",
+                colors.len(),
+                Self::MAX_COLORS
+            ));
+        }
+        Ok(Self { colors })
+    }
+
+    /// Every color of the visible pixels of `image`, in the order they first appear.
+    pub fn extract(image: &Image) -> Result<Self> {
+        let pixels = image
+            .get_rgba_pixels()
+            .ok_or_else(|| anyhow!("Only uncompressed RGBA images have a palette"))?;
+
+        let mut colors: Vec<[u8; 3]> = vec![];
+        for pixel in pixels.chunks_exact(4) {
+            let color = [pixel[0], pixel[1], pixel[2]];
+            if pixel[3] > 0 && !colors.contains(&color) {
+                colors.push(color);
+            }
+        }
+        Self::new(colors)
+    }
+
+    pub fn get_colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    /// The same palette with every color passed through `recolor`.
+    pub fn recolor(&self, recolor: impl Fn([u8; 3]) -> [u8; 3]) -> Self {
+        Self {
+            colors: self.colors.iter().map(|&color| recolor(color)).collect(),
+        }
+    }
+
+    /// Replace the colors of `image` by their index in this palette, in the red
+    /// channel. Alpha is kept. Must be sampled without filtering, or neighbouring
+    /// indices blend into unrelated colors.
+    pub fn index(&self, image: &Image) -> Result<Image> {
+        let pixels = image
+            .get_rgba_pixels()
+            .ok_or_else(|| anyhow!("Only uncompressed RGBA images can be indexed"))?;
+
+        let indices: HashMap<[u8; 3], u8> = self
+            .colors
+            .iter()
+            .enumerate()
+            .map(|(index, &color)| (color, index as u8))
+            .collect();
+
+        let mut indexed = Vec::with_capacity(pixels.len());
+        for pixel in pixels.chunks_exact(4) {
+            let index = match indices.get(&[pixel[0], pixel[1], pixel[2]]) {
+                Some(&index) => index,
+                // Invisible anyway
+                None if pixel[3] == 0 => 0,
+                None => {
+                    return Err(anyhow!(
+                        "Color {:?} is not part of the palette",
+                        &pixel[..3]
+                    ))
+                }
+            };
+            indexed.extend_from_slice(&[index, 0, 0, pixel[3]]);
+        }
+
+        Ok(Image::from_rgba(image.get_width(), image.get_height(), indexed)?
+            .with_alpha_mode(image.get_alpha_mode())
+            .with_srgb(false))
+    }
+}
+
+/// A texture with one palette per row, indexed by the mesh fragment shader.
+pub fn create_palette_image(palettes: &[Palette]) -> Result<Image> {
+    let width = palettes
+        .iter()
+        .map(|palette| palette.colors.len())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    let mut pixels = Vec::with_capacity(width * palettes.len() * 4);
+    for palette in palettes {
+        for x in 0..width {
+            let [r, g, b] = palette.colors.get(x).copied().unwrap_or([0, 0, 0]);
+            pixels.extend_from_slice(&[r, g, b, u8::MAX]);
+        }
+    }
+
+    Image::from_rgba(width as u32, palettes.len() as u32, pixels)
+}
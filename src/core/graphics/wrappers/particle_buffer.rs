@@ -0,0 +1,169 @@
+use anyhow::Result;
+use std::mem::size_of;
+use std::ptr::copy_nonoverlapping as memcpy;
+use vulkanalia::prelude::v1_0::*;
+
+use super::super::buffers;
+use super::super::deletion_queue::DeviceResource;
+
+type Vec3 = cgmath::Vector3<f32>;
+type Vec4 = cgmath::Vector4<f32>;
+
+/// Vertices the particle vertex shader expands every instance into, two triangles.
+const VERTICES_PER_PARTICLE: u32 = 6;
+
+/// One particle as the particle vertex shader reads it, once per instance.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ParticleInstance {
+    pub position: Vec3,
+    pub size: f32,
+    pub color: Vec4,
+    /// Offset and size of the sprite sheet frame, in texture coordinates
+    pub uv_rect: Vec4,
+}
+
+impl ParticleInstance {
+    pub fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<ParticleInstance>() as u32)
+            .input_rate(vk::VertexInputRate::INSTANCE)
+            .build()
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 4] {
+        let position = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(0)
+            .build();
+        let size = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R32_SFLOAT)
+            .offset(size_of::<Vec3>() as u32)
+            .build();
+        let color = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(2)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset((size_of::<Vec3>() + size_of::<f32>()) as u32)
+            .build();
+        let uv_rect = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(3)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset((size_of::<Vec3>() + size_of::<f32>() + size_of::<Vec4>()) as u32)
+            .build();
+        [position, size, color, uv_rect]
+    }
+}
+
+/// Host visible instance and indirect draw buffers, one pair per swapchain image.
+/// The particle count lives in the indirect buffer, so it can change every frame
+/// without recording the command buffers again.
+pub struct ParticleBufferSeries {
+    capacity: usize,
+    instance_buffers: Vec<(vk::Buffer, vk::DeviceMemory)>,
+    indirect_buffers: Vec<(vk::Buffer, vk::DeviceMemory)>,
+}
+
+impl ParticleBufferSeries {
+    pub unsafe fn create(
+        instance: &Instance,
+        device: &Device,
+        physical_device: vk::PhysicalDevice,
+        number_of_buffers: usize,
+        capacity: usize,
+    ) -> Result<Self> {
+        let properties = vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE;
+
+        let mut series = Self {
+            capacity,
+            instance_buffers: vec![],
+            indirect_buffers: vec![],
+        };
+        for _ in 0..number_of_buffers {
+            series.instance_buffers.push(buffers::create_buffer(
+                instance,
+                device,
+                physical_device,
+                (capacity.max(1) * size_of::<ParticleInstance>()) as u64,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                properties,
+            )?);
+            series.indirect_buffers.push(buffers::create_buffer(
+                instance,
+                device,
+                physical_device,
+                size_of::<vk::DrawIndirectCommand>() as u64,
+                vk::BufferUsageFlags::INDIRECT_BUFFER,
+                properties,
+            )?);
+            // Nothing to draw until the first update
+            series.write_draw_command(device, series.indirect_buffers.len() - 1, 0)?;
+        }
+
+        Ok(series)
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get_instance_buffer(&self, index: usize) -> vk::Buffer {
+        self.instance_buffers[index].0
+    }
+
+    pub fn get_indirect_buffer(&self, index: usize) -> vk::Buffer {
+        self.indirect_buffers[index].0
+    }
+
+    /// Replace the particles drawn with the buffers at `index`. Anything past the
+    /// capacity is left out.
+    pub unsafe fn update(
+        &self,
+        device: &Device,
+        index: usize,
+        particles: &[ParticleInstance],
+    ) -> Result<()> {
+        let count = particles.len().min(self.capacity);
+        if count > 0 {
+            let memory = self.instance_buffers[index].1;
+            let size = (count * size_of::<ParticleInstance>()) as u64;
+            let data = device.map_memory(memory, 0, size, vk::MemoryMapFlags::empty())?;
+            memcpy(particles.as_ptr(), data.cast(), count);
+            device.unmap_memory(memory);
+        }
+
+        self.write_draw_command(device, index, count as u32)
+    }
+
+    unsafe fn write_draw_command(&self, device: &Device, index: usize, instance_count: u32) -> Result<()> {
+        let command = vk::DrawIndirectCommand {
+            vertex_count: VERTICES_PER_PARTICLE,
+            instance_count,
+            first_vertex: 0,
+            first_instance: 0,
+        };
+
+        let memory = self.indirect_buffers[index].1;
+        let size = size_of::<vk::DrawIndirectCommand>() as u64;
+        let data = device.map_memory(memory, 0, size, vk::MemoryMapFlags::empty())?;
+        memcpy(&command, data.cast(), 1);
+        device.unmap_memory(memory);
+
+        Ok(())
+    }
+}
+
+impl DeviceResource for ParticleBufferSeries {
+    unsafe fn destroy(&self, device: &Device) {
+        for (buffer, memory) in self.instance_buffers.iter().chain(&self.indirect_buffers) {
+            device.destroy_buffer(*buffer, None);
+            device.free_memory(*memory, None);
+        }
+    }
+}
@@ -5,6 +5,7 @@ pub mod uniform_buffer {
     use vulkanalia::prelude::v1_0::*;
 
     use super::super::super::buffers;
+    use super::super::super::deletion_queue::DeviceResource;
     use std::ptr::copy_nonoverlapping as memcpy;
 
     #[derive(Clone, Debug, Default)]
@@ -119,6 +120,12 @@ pub mod uniform_buffer {
             .for_each(|b| device.free_memory(*b, None));
     }
 
+    impl DeviceResource for UniformBufferSeries {
+        unsafe fn destroy(&self, device: &Device) {
+            destroy_series(device, self);
+        }
+    }
+
     pub unsafe fn update_uniform_buffer_series<T>(
         device: &Device,
         uniform_buffer_object: &T,
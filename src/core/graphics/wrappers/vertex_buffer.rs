@@ -3,7 +3,8 @@ use std::mem::size_of;
 use vulkanalia::vk::{self};
 use vulkanalia::prelude::v1_0::*;
 
-use super::super::buffers::{create_buffer, copy_buffer};
+use super::super::buffers::create_buffer;
+use super::super::upload::{UploadContext, UploadHandle};
 
 type Vec3 = cgmath::Vector3<f32>;
 type Vec2 = cgmath::Vector2<f32>;
@@ -72,48 +73,23 @@ impl VertexBuffer {
         instance: &Instance, 
         device: &Device,
         physical_device: vk::PhysicalDevice,
-        command_pool: vk::CommandPool,
-        graphics_queue: vk::Queue,
+        upload_context: &UploadContext,
         vertices: &[Vertex],
-    ) -> Result<Self> {
-        use std::ptr::copy_nonoverlapping as memcpy;
-
+    ) -> Result<(Self, UploadHandle)> {
         let size: u64 = (size_of::<Vertex>() * vertices.len()) as u64;
 
-        let memory_property_flags = vk::MemoryPropertyFlags::HOST_COHERENT | 
-            vk::MemoryPropertyFlags::HOST_VISIBLE;
-
-        let (staging_buffer, staging_buffer_memory) = create_buffer(
-            instance, device, physical_device,
-            size, vk::BufferUsageFlags::TRANSFER_SRC, 
-            memory_property_flags)?;
-
-        let memory_offset: u64 = 0;
-        let gpu_memory = device.map_memory(
-            staging_buffer_memory,
-            memory_offset, 
-            size, 
-            vk::MemoryMapFlags::empty()
-        )?;
-
-        memcpy(vertices.as_ptr(), gpu_memory.cast(), vertices.len());
-
-        device.unmap_memory(staging_buffer_memory);
-
         let (vertex_buffer, vertex_buffer_memory) = create_buffer(
             instance, device, physical_device,
             size, vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER, 
             vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
 
-        copy_buffer(device, staging_buffer, vertex_buffer, size, command_pool, graphics_queue)?;
-
-        device.destroy_buffer(staging_buffer, None);
-        device.free_memory(staging_buffer_memory, None);
+        let data = std::slice::from_raw_parts(vertices.as_ptr().cast::<u8>(), size as usize);
+        let upload = upload_context.upload_buffer(device, data, vertex_buffer)?;
 
-        Ok(Self {
+        Ok((Self {
             buffer : vertex_buffer,
             memory : vertex_buffer_memory
-        })
+        }, upload))
     }
     pub unsafe fn destroy(buffer: VertexBuffer, device: &Device) {
         device.destroy_buffer(buffer.buffer, None);
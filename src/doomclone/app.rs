@@ -1,14 +1,20 @@
 use crate::{
     core::graphics::{
-        CPUMesh, GPUMesh, Graphics, Image, ImageSampler, LoadedImage, UniformBufferSeries,
+        create_palette_image, AlphaMode, CPUMesh, DescriptorSets, GPUMesh, GltfPrimitive,
+        GltfScene, Graphics, Image, ImageSampler, LoadedImage, ObjModel, Owned, Palette,
+        RenderTarget, SamplerDescription, UniformBufferSeries, ViewportRect,
     },
     doomclone::app::saga_renderer::MeshVertexUniformObject,
 };
 use anyhow::Result;
 use bevy_app::App;
-use bevy_ecs::{component::Component, system::ResMut};
-use cgmath::{vec3, Angle, Vector2};
-use std::path::Path;
+use bevy_ecs::{
+    component::Component,
+    entity::Entity,
+    system::{Commands, ResMut},
+};
+use cgmath::{vec3, Angle, ElementWise, One, Vector2};
+use std::{path::Path, sync::Arc};
 use vulkanalia::prelude::v1_0::*;
 
 use self::saga_renderer::{MeshFragmentData, MeshFragmentUniformObject, MeshRenderingBundle};
@@ -23,6 +29,14 @@ struct Position(Vec3);
 #[derive(Component)]
 struct RelativePosition(Vec3);
 
+/// Places an entity relative to another one. Every frame the child's
+/// `Position` and `Rotation` are derived from the parent's and the child's
+/// `RelativePosition` and `RelativeRotation`. A child with a `Scale` also has it
+/// derived, from the parent's `Scale` and its own `RelativeScale`. Parents cannot
+/// have parents.
+#[derive(Component, Clone, Copy)]
+struct Parent(Entity);
+
 impl Position {
     fn x(&self) -> f32 {
         self.0.x
@@ -70,6 +84,9 @@ impl Rotation {
 #[derive(Component)]
 struct Scale(Vec3);
 
+#[derive(Component)]
+struct RelativeScale(Vec3);
+
 impl RelativeRotation {
     fn forward(&self) -> Vec3 {
         self.0 * vec3(0.0, 0.0, 1.0)
@@ -90,17 +107,101 @@ struct MovementSpeed(f32);
 #[derive(Component)]
 struct TurnSpeed(Vector2<cgmath::Rad<f64>>);
 
+/// How a camera maps view space onto its viewport.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Projection {
+    Perspective {
+        /// Vertical field of view
+        field_of_view: cgmath::Rad<f32>,
+        near_plane_distance: f32,
+        far_plane_distance: f32,
+    },
+    Orthographic {
+        /// Height of the view volume in world units, its width follows the aspect ratio
+        size: f32,
+        near_plane_distance: f32,
+        far_plane_distance: f32,
+    },
+}
+
+/// Groups meshes so that cameras can pick which ones they draw, and in which order.
+/// Meshes without a layer are in [`RenderLayer::WORLD`].
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct RenderLayer(u8);
+
+impl RenderLayer {
+    /// The level, its inhabitants and the sky
+    const WORLD: Self = Self(0);
+    /// What the player holds, drawn over the world
+    const VIEWMODEL: Self = Self(1);
+}
+
+impl Default for RenderLayer {
+    fn default() -> Self {
+        Self::WORLD
+    }
+}
+
+/// Turns a mesh towards whichever camera draws it, in the vertex shader. Its
+/// rotation is ignored, only position and scale are kept.
+/// The discriminants are the modes the vertex shader expects.
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+enum Billboard {
+    /// Faces the camera fully, like a particle
+    Spherical = 1,
+    /// Only turns around the world up axis, so it stays upright like a sprite
+    Cylindrical = 2,
+}
+
+/// One layer drawn by a camera.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct CameraLayer {
+    layer: RenderLayer,
+    /// Start the layer on an empty depth buffer, so it ends up on top of the layers
+    /// before it and never clips into them
+    clear_depth: bool,
+    /// Overrides the camera projection for this layer only
+    projection: Option<Projection>,
+}
+
+impl CameraLayer {
+    fn new(layer: RenderLayer) -> Self {
+        Self {
+            layer,
+            clear_depth: false,
+            projection: None,
+        }
+    }
+}
+
 #[derive(Component)]
 struct Camera {
-    field_of_view: cgmath::Rad<f32>,
-    far_plane_distance: f32,
-    near_plane_distance: f32,
+    projection: Projection,
     width: u32,
     height: u32,
+    /// Part of the render target this camera draws into
+    viewport: ViewportRect,
+    /// Cameras sharing a render target draw in ascending order, later ones on top
+    order: i32,
+    /// Drawn in order. Fixed once the camera rendering info is created, since every
+    /// layer gets its own projection uniform.
+    layers: Vec<CameraLayer>,
 }
 
 impl Camera {
-    fn calculate_projection_matrix(&self) -> Mat4 {
+    /// Size the camera to the part of a `width` by `height` render target it covers.
+    fn fit_to_target(&mut self, width: u32, height: u32) {
+        let rect = self.viewport.get_pixel_rect(vk::Extent2D { width, height });
+        self.width = rect.extent.width;
+        self.height = rect.extent.height;
+    }
+
+    fn get_layer_projection(&self, layer: &CameraLayer) -> Projection {
+        layer.projection.unwrap_or(self.projection)
+    }
+
+    fn calculate_projection_matrix(&self, projection: Projection) -> Mat4 {
         // this matrix transforms the typical view space into an intermediate space
         // where the forward direction is the direction that the camera
         // fulstrum captures, and the up direction is the downwards camera direction
@@ -108,30 +209,62 @@ impl Camera {
             1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
         );
 
-        let inverse_tan_half_fov: f32 = 1.0 / Angle::tan(self.field_of_view / 2.0);
         let inverse_aspect_ratio: f32 = match self.height {
             0 => 0.0 as f32,
             _ => (self.height as f32) / (self.width as f32),
         };
 
+        let projection_matrix = match projection {
+            Projection::Perspective {
+                field_of_view,
+                near_plane_distance,
+                far_plane_distance,
+            } => Self::calculate_perspective_matrix(
+                field_of_view,
+                near_plane_distance,
+                far_plane_distance,
+                inverse_aspect_ratio,
+            ),
+            Projection::Orthographic {
+                size,
+                near_plane_distance,
+                far_plane_distance,
+            } => Self::calculate_orthographic_matrix(
+                size,
+                near_plane_distance,
+                far_plane_distance,
+                inverse_aspect_ratio,
+            ),
+        };
+
+        projection_matrix * intermediate_matrix
+    }
+
+    fn calculate_perspective_matrix(
+        field_of_view: cgmath::Rad<f32>,
+        near_plane_distance: f32,
+        far_plane_distance: f32,
+        inverse_aspect_ratio: f32,
+    ) -> Mat4 {
+        let inverse_tan_half_fov: f32 = 1.0 / Angle::tan(field_of_view / 2.0);
+
         let projection_matrix_c0r0: f32 = inverse_tan_half_fov * inverse_aspect_ratio;
         let projection_matrix_c1r1: f32 = inverse_tan_half_fov;
         let projection_matrix_c2r2: f32 =
-            self.far_plane_distance / (self.far_plane_distance - self.near_plane_distance);
+            far_plane_distance / (far_plane_distance - near_plane_distance);
         let projection_matrix_c2r3: f32 = 1.0;
-        let projection_matrix_c3r2: f32 = match self.far_plane_distance == self.near_plane_distance
-        {
+        let projection_matrix_c3r2: f32 = match far_plane_distance == near_plane_distance {
             true => 0.0 as f32,
             false => {
-                -self.far_plane_distance * self.near_plane_distance
-                    / (self.far_plane_distance - self.near_plane_distance)
+                -far_plane_distance * near_plane_distance
+                    / (far_plane_distance - near_plane_distance)
             }
         };
 
         // according to the following:
         // https://johannesugb.github.io/gpu-programming/setting-up-a-proper-vulkan-projection-matrix/
         // this should transform the intermediate space into clip space in vulkan
-        let projection_matrix: Mat4 = Mat4::new(
+        Mat4::new(
             projection_matrix_c0r0,
             0.0,
             0.0,
@@ -148,9 +281,49 @@ impl Camera {
             0.0,
             projection_matrix_c3r2,
             0.0,
-        );
+        )
+    }
 
-        projection_matrix * intermediate_matrix
+    /// Maps a box `size` high, centered on the view direction, straight onto the
+    /// viewport, with depth going from 0 at the near plane to 1 at the far plane.
+    fn calculate_orthographic_matrix(
+        size: f32,
+        near_plane_distance: f32,
+        far_plane_distance: f32,
+        inverse_aspect_ratio: f32,
+    ) -> Mat4 {
+        let inverse_half_size: f32 = match size == 0.0 {
+            true => 0.0,
+            false => 2.0 / size,
+        };
+        let inverse_depth: f32 = match far_plane_distance == near_plane_distance {
+            true => 0.0,
+            false => 1.0 / (far_plane_distance - near_plane_distance),
+        };
+
+        let projection_matrix_c0r0: f32 = inverse_half_size * inverse_aspect_ratio;
+        let projection_matrix_c1r1: f32 = inverse_half_size;
+        let projection_matrix_c2r2: f32 = inverse_depth;
+        let projection_matrix_c3r2: f32 = -near_plane_distance * inverse_depth;
+
+        Mat4::new(
+            projection_matrix_c0r0,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            projection_matrix_c1r1,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            projection_matrix_c2r2,
+            0.0,
+            0.0,
+            0.0,
+            projection_matrix_c3r2,
+            1.0,
+        )
     }
 
     fn calculate_view_matrix(position: &Position, rotation: &Rotation) -> Mat4 {
@@ -193,67 +366,220 @@ impl Camera {
 #[derive(Component)]
 pub struct CameraRenderingInfo {
     view: Mat4,
+    /// One for each of the camera layers, in the same order
+    layers: Vec<CameraLayerRenderingInfo>,
+    /// Where the camera renders to, the swapchain when `None`
+    target: Option<RenderTarget>,
+}
+
+pub struct CameraLayerRenderingInfo {
     projection: Mat4,
-    uniform_buffers: UniformBufferSeries,
+    uniform_buffers: Owned<UniformBufferSeries>,
+    descriptor_sets: Owned<DescriptorSets>,
 }
 
 #[derive(Component)]
 struct Mesh {
-    gpu_mesh: GPUMesh,
+    gpu_mesh: Owned<GPUMesh>,
 }
 
-#[derive(Component)]
+#[derive(Component, Clone)]
 struct MainTexture {
-    texture: LoadedImage,
+    /// Shared with the camera when this is its render target
+    texture: Arc<Owned<LoadedImage>>,
     sampler: ImageSampler,
+    alpha_mode: AlphaMode,
+    /// The camera whose render target this texture is, which must not draw it.
+    render_target: Option<Entity>,
+    /// One palette per row, when the texture holds indices into them
+    palette: Option<Arc<Owned<LoadedImage>>>,
 }
 
 #[derive(Component)]
 struct MeshRenderingInfo {
-    descriptor_sets: Vec<vk::DescriptorSet>,
-    vertex_uniform_buffers: UniformBufferSeries,
-    fragment_uniform_buffers: UniformBufferSeries,
+    descriptor_sets: Owned<DescriptorSets>,
+    vertex_uniform_buffers: Owned<UniformBufferSeries>,
+    fragment_uniform_buffers: Owned<UniformBufferSeries>,
 }
 
 fn construct_mesh_with_cpu_mesh(
     graphics: &mut ResMut<Graphics>,
     path_to_texture: &Path,
+    sampler: &SamplerDescription,
     cpu_mesh: CPUMesh,
 ) -> Result<(MeshRenderingBundle, CPUMesh)> {
-    let gpu_mesh = unsafe { GPUMesh::create(&graphics, &cpu_mesh).unwrap() };
+    let texture = Image::load(path_to_texture)?;
+    let mesh_rendering_bundle = construct_mesh_with_image(graphics, &texture, sampler, &cpu_mesh)?;
 
-    let texture = Image::load(&path_to_texture).unwrap();
-
-    let loaded_texture = unsafe { LoadedImage::create(&graphics, &texture).unwrap() };
-    let texture_sampler = unsafe { ImageSampler::create_from_graphics(&graphics).unwrap() };
+    Ok((mesh_rendering_bundle, cpu_mesh))
+}
 
-    let descriptor_sets = unsafe {
-        let device = graphics.get_device().clone();
-        let set_layout = graphics.mesh_descriptor_set_layout;
-        let swapchain_len = graphics.swapchain.get_length();
-        graphics
-            .mesh_descriptor_allocator
-            .allocate(&device, set_layout, swapchain_len)
-            .unwrap()
+/// Build a mesh from one glTF primitive, applying its material factors.
+/// Primitives without a base color texture are drawn in plain white times the tint.
+fn construct_mesh_with_gltf_primitive(
+    graphics: &mut ResMut<Graphics>,
+    scene: &GltfScene,
+    primitive: &GltfPrimitive,
+) -> Result<MeshRenderingBundle> {
+    let material = scene.get_material(primitive);
+    let white_texture;
+    let texture = match material.and_then(|material| scene.get_base_color_image(material)) {
+        Some(texture) => texture,
+        None => {
+            white_texture = Image::from_rgba(1, 1, vec![u8::MAX; 4])?;
+            &white_texture
+        }
     };
 
-    let vertex_uniform_buffers = unsafe {
-        UniformBufferSeries::create_from_graphics::<MeshVertexUniformObject>(&graphics).unwrap()
-    };
+    let sampler = material
+        .map(|material| material.base_color_sampler)
+        .unwrap_or_default();
+
+    let mut mesh_rendering_bundle =
+        construct_mesh_with_image(graphics, texture, &sampler, &primitive.cpu_mesh)?;
+
+    if let Some(material) = material {
+        mesh_rendering_bundle.main_texture.alpha_mode = material.alpha_mode;
+        mesh_rendering_bundle.fragment_data.tint = material.base_color_factor;
+        mesh_rendering_bundle.fragment_data.alpha_cutoff = material.alpha_cutoff;
+    }
+
+    Ok(mesh_rendering_bundle)
+}
+
+impl MainTexture {
+    /// Upload `texture`, to be shared by every mesh given a clone of the result.
+    fn create(
+        graphics: &mut Graphics,
+        texture: &Image,
+        sampler: &SamplerDescription,
+    ) -> Result<Self> {
+        Ok(MainTexture {
+            texture: Arc::new(LoadedImage::create(graphics, texture)?),
+            sampler: ImageSampler::get_from_graphics(graphics, sampler)?,
+            alpha_mode: texture.get_alpha_mode(),
+            render_target: None,
+            palette: None,
+        })
+    }
+
+    /// Upload `texture` as indices into the first of `palettes`, which it must be
+    /// drawn with. Meshes then pick any of them by their row.
+    fn create_indexed(
+        graphics: &mut Graphics,
+        texture: &Image,
+        palettes: &[Palette],
+    ) -> Result<Self> {
+        let base_palette = palettes
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("An indexed texture needs a palette"))?;
+        let indices = base_palette.index(texture)?;
+        let palette_image = create_palette_image(palettes)?;
+
+        Ok(MainTexture {
+            palette: Some(Arc::new(LoadedImage::create(graphics, &palette_image)?)),
+            // Filtering would blend neighbouring indices into unrelated colors
+            ..MainTexture::create(graphics, &indices, &SamplerDescription::nearest())?
+        })
+    }
+}
+
+fn construct_mesh_with_image(
+    graphics: &mut ResMut<Graphics>,
+    texture: &Image,
+    sampler: &SamplerDescription,
+    cpu_mesh: &CPUMesh,
+) -> Result<MeshRenderingBundle> {
+    let main_texture = MainTexture::create(graphics, texture, sampler)?;
+
+    construct_mesh_with_main_texture(graphics, main_texture, cpu_mesh)
+}
 
-    let fragment_uniform_buffers = unsafe {
-        UniformBufferSeries::create_from_graphics::<MeshFragmentUniformObject>(&graphics).unwrap()
+/// Build a mesh showing what `camera` renders into `target`.
+fn construct_mesh_with_render_target(
+    graphics: &mut ResMut<Graphics>,
+    camera: Entity,
+    target: &RenderTarget,
+    sampler: &SamplerDescription,
+    cpu_mesh: &CPUMesh,
+) -> Result<MeshRenderingBundle> {
+    let main_texture = MainTexture {
+        texture: target.get_texture().clone(),
+        sampler: ImageSampler::get_from_graphics(graphics, sampler)?,
+        alpha_mode: AlphaMode::Opaque,
+        render_target: Some(camera),
+        palette: None,
     };
 
+    construct_mesh_with_main_texture(graphics, main_texture, cpu_mesh)
+}
+
+/// Point the texture and palette bindings of a mesh at `main_texture`.
+fn queue_write_main_texture(
+    graphics: &mut Graphics,
+    main_texture: &MainTexture,
+    descriptor_sets: &[vk::DescriptorSet],
+) {
     let device = graphics.get_device().clone();
     graphics.descriptor_writer.queue_write_image(
         &device,
-        &texture_sampler,
-        &loaded_texture,
-        &descriptor_sets,
+        &main_texture.sampler,
+        &main_texture.texture,
+        descriptor_sets,
         1,
     );
+    let palette = main_texture.palette.as_deref().map(|palette| &**palette);
+    graphics.queue_write_palette(palette, descriptor_sets, 4);
+}
+
+fn construct_mesh_with_main_texture(
+    graphics: &mut ResMut<Graphics>,
+    main_texture: MainTexture,
+    cpu_mesh: &CPUMesh,
+) -> Result<MeshRenderingBundle> {
+    let gpu_mesh = GPUMesh::create(graphics, cpu_mesh)?;
+    let alpha_mode = main_texture.alpha_mode;
+    let palette_row = main_texture.palette.is_some().then_some(0);
+
+    let descriptor_sets = graphics.allocate_mesh_descriptor_sets()?;
+
+    let vertex_uniform_buffers =
+        UniformBufferSeries::create_from_graphics::<MeshVertexUniformObject>(graphics)?;
+    let fragment_uniform_buffers =
+        UniformBufferSeries::create_from_graphics::<MeshFragmentUniformObject>(graphics)?;
+
+    queue_write_main_texture(graphics, &main_texture, &descriptor_sets);
+    graphics.queue_write_cubemap(None, &descriptor_sets, 3);
+    queue_write_mesh_uniform_buffers(
+        graphics,
+        &vertex_uniform_buffers,
+        &fragment_uniform_buffers,
+        &descriptor_sets,
+    );
+
+    Ok(MeshRenderingBundle {
+        mesh: Mesh { gpu_mesh },
+        main_texture,
+        fragment_data: MeshFragmentData {
+            tint: cgmath::vec4(1.0, 1.0, 1.0, 1.0),
+            alpha_cutoff: alpha_mode.get_alpha_cutoff(),
+            reflectivity: 0.0,
+            palette_row,
+        },
+        rendering_info: MeshRenderingInfo {
+            vertex_uniform_buffers,
+            fragment_uniform_buffers,
+            descriptor_sets,
+        },
+    })
+}
 
+fn queue_write_mesh_uniform_buffers(
+    graphics: &mut Graphics,
+    vertex_uniform_buffers: &UniformBufferSeries,
+    fragment_uniform_buffers: &UniformBufferSeries,
+    descriptor_sets: &[vk::DescriptorSet],
+) {
     vertex_uniform_buffers
         .get_buffers()
         .iter()
@@ -287,42 +613,161 @@ fn construct_mesh_with_cpu_mesh(
                     2,
                 );
         });
-
-    Ok((
-        MeshRenderingBundle {
-            mesh: Mesh { gpu_mesh },
-            main_texture: MainTexture {
-                texture: loaded_texture,
-                sampler: texture_sampler,
-            },
-            fragment_data: MeshFragmentData {
-                tint: cgmath::vec4(1.0, 1.0, 1.0, 1.0),
-            },
-            rendering_info: MeshRenderingInfo {
-                vertex_uniform_buffers,
-                fragment_uniform_buffers,
-                descriptor_sets,
-            },
-        },
-        cpu_mesh,
-    ))
 }
 
-fn construct_mesh(
+/// Load every object of an OBJ file with the diffuse texture of its material.
+/// Objects without a `map_Kd` texture are drawn in their diffuse color.
+fn construct_model(
     graphics: &mut ResMut<Graphics>,
     path_to_obj: &Path,
-    path_to_texture: &Path,
-) -> Result<(MeshRenderingBundle, CPUMesh)> {
-    let mut cpu_mesh = unsafe { CPUMesh::load_from_obj(&graphics, &path_to_obj) };
+    sampler: &SamplerDescription,
+) -> Result<Vec<(MeshRenderingBundle, CPUMesh)>> {
+    let model = ObjModel::load(path_to_obj)?;
 
-    if cpu_mesh.len() == 0 {
+    if model.sub_meshes.is_empty() {
         return Err(anyhow::anyhow!(
             "Provided obj file at path {:?} does not have a mesh",
             path_to_obj
         ));
     }
 
-    construct_mesh_with_cpu_mesh(graphics, path_to_texture, cpu_mesh.remove(0))
+    model
+        .sub_meshes
+        .into_iter()
+        .map(|sub_mesh| {
+            let material = sub_mesh.material.as_ref();
+            let texture = match material.and_then(|material| material.diffuse_texture.as_ref()) {
+                Some(path_to_texture) => Image::load(path_to_texture)?,
+                None => Image::from_rgba(1, 1, vec![u8::MAX; 4])?,
+            };
+
+            let mut mesh_rendering_bundle =
+                construct_mesh_with_image(graphics, &texture, sampler, &sub_mesh.cpu_mesh)?;
+
+            if let Some(material) = material {
+                let color = match material.diffuse_texture {
+                    Some(_) => cgmath::vec3(1.0, 1.0, 1.0),
+                    None => material.diffuse_color,
+                };
+                mesh_rendering_bundle.fragment_data.tint = color.extend(material.dissolve);
+                if material.dissolve < 1.0 {
+                    mesh_rendering_bundle.main_texture.alpha_mode = AlphaMode::Translucent;
+                    mesh_rendering_bundle.fragment_data.alpha_cutoff =
+                        AlphaMode::Translucent.get_alpha_cutoff();
+                }
+            }
+
+            Ok((mesh_rendering_bundle, sub_mesh.cpu_mesh))
+        })
+        .collect()
+}
+
+/// Spawn every mesh of an OBJ, glTF or GLB model as a part of `parent`, along with the
+/// mesh of each part for colliders. `sampler` only applies to OBJ textures, glTF
+/// materials name their own.
+fn spawn_model(
+    graphics: &mut ResMut<Graphics>,
+    commands: &mut Commands,
+    parent: Entity,
+    path: &Path,
+    sampler: &SamplerDescription,
+) -> Result<Vec<(Entity, CPUMesh)>> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("gltf" | "glb") => spawn_gltf_model(graphics, commands, parent, path),
+        _ => Ok(construct_model(graphics, path, sampler)?
+            .into_iter()
+            .map(|(mesh_rendering_bundle, cpu_mesh)| {
+                (
+                    spawn_model_part(commands, parent, mesh_rendering_bundle),
+                    cpu_mesh,
+                )
+            })
+            .collect()),
+    }
+}
+
+/// Spawn the meshes of every node in a glTF scene where the node hierarchy puts them.
+/// Nested nodes are flattened onto `parent`, since parents cannot have parents.
+fn spawn_gltf_model(
+    graphics: &mut ResMut<Graphics>,
+    commands: &mut Commands,
+    parent: Entity,
+    path: &Path,
+) -> Result<Vec<(Entity, CPUMesh)>> {
+    let scene = GltfScene::load(path)?;
+
+    let mut parts = vec![];
+    for (node, position, rotation, scale) in get_gltf_node_transforms(&scene) {
+        let Some(mesh) = scene.nodes[node].mesh else {
+            continue;
+        };
+        for primitive in &scene.meshes[mesh].primitives {
+            let mesh_rendering_bundle =
+                construct_mesh_with_gltf_primitive(graphics, &scene, primitive)?;
+            let part = spawn_model_part(commands, parent, mesh_rendering_bundle);
+            commands.entity(part).insert((
+                RelativePosition(position),
+                RelativeRotation(rotation),
+                RelativeScale(scale),
+            ));
+            parts.push((part, primitive.cpu_mesh.clone()));
+        }
+    }
+
+    if parts.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Provided glTF file at path {:?} does not have a mesh",
+            path
+        ));
+    }
+    Ok(parts)
+}
+
+/// Every node of the scene with its position, rotation and scale relative to the
+/// scene root, composed the way a child is placed relative to its parent.
+fn get_gltf_node_transforms(scene: &GltfScene) -> Vec<(usize, Vec3, Quat, Vec3)> {
+    let mut transforms = vec![];
+    let mut pending: Vec<(usize, Vec3, Quat, Vec3)> = scene
+        .root_nodes
+        .iter()
+        .map(|&node| (node, vec3(0.0, 0.0, 0.0), Quat::one(), vec3(1.0, 1.0, 1.0)))
+        .collect();
+
+    while let Some((node, parent_position, parent_rotation, parent_scale)) = pending.pop() {
+        let local = &scene.nodes[node];
+        let position =
+            parent_position + parent_rotation * parent_scale.mul_element_wise(local.translation);
+        let rotation = parent_rotation * local.rotation;
+        let scale = parent_scale.mul_element_wise(local.scale);
+
+        pending.extend(
+            local
+                .children
+                .iter()
+                .map(|&child| (child, position, rotation, scale)),
+        );
+        transforms.push((node, position, rotation, scale));
+    }
+    transforms
+}
+
+/// Spawn one part of a model, placed relative to `parent`.
+fn spawn_model_part(
+    commands: &mut Commands,
+    parent: Entity,
+    mesh_rendering_bundle: MeshRenderingBundle,
+) -> Entity {
+    commands
+        .spawn((
+            Parent(parent),
+            RelativePosition(cgmath::vec3(0.0, 0.0, 0.0)),
+            RelativeRotation(Quat::one()),
+            Position(cgmath::vec3(0.0, 0.0, 0.0)),
+            Rotation(Quat::one()),
+            Scale(cgmath::vec3(1.0, 1.0, 1.0)),
+            mesh_rendering_bundle,
+        ))
+        .id()
 }
 
 mod doomclone_game {
@@ -330,51 +775,66 @@ mod doomclone_game {
         collections::{HashMap, HashSet},
         marker::PhantomData,
         ops::Not,
-        path::PathBuf,
-        time::Duration,
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::{Duration, SystemTime},
     };
 
     use super::{
-        construct_mesh, construct_mesh_with_cpu_mesh,
+        construct_mesh_with_main_texture, construct_mesh_with_render_target,
         saga_audio::{AudioEmitter, AudioRuntimeManager},
         saga_collision::{raycast, KnockbackEvent, Knockbackable, MeshCollider},
         saga_combat::{DamageEvent, DeathEvent, Health, IFrame},
-        saga_input::{ButtonInput, MouseButtonEvent, MouseChangeEvent},
-        saga_renderer::{self, CameraUniformBufferObject, MeshFragmentData},
+        saga_decals::Decal,
+        saga_input::{ButtonInput, KeyboardEvent, MouseButtonEvent, MouseChangeEvent},
+        saga_particles::{
+            create_soft_dot_image, Emission, ParticleEmitter, ParticleSettings, Particles,
+        },
+        saga_renderer::{
+            self, ComputeDispatch, Fog, FogMode, HeightFog, MeshFragmentData, MeshRenderingBundle,
+            ParticleBatch, Skybox, SpriteSheet, TextureRegion, TransformPropagationSet,
+        },
         saga_window::Window,
-        MainTexture, Mesh, MeshRenderingInfo, MovementSpeed, Position, RelativePosition,
-        RelativeRotation, Rotation, Scale, TurnSpeed,
+        spawn_model, Billboard, CameraLayer, MainTexture, MovementSpeed, Parent, Position,
+        Projection, RelativePosition, RelativeRotation, RenderLayer, Rotation, Scale, TurnSpeed,
     };
     use crate::{
-        core::graphics::{CPUMesh, Graphics, UniformBufferSeries},
+        core::graphics::{
+            get_group_count, AlphaMode, CPUMesh, ComputeBarrier, ComputePipeline, CubemapImage,
+            Graphics, Image, ImageSampler, LoadedImage, Owned, Palette, SamplerDescription,
+            TextureAtlas, TextureAtlasBuilder, ViewportRect,
+        },
         doomclone::app::{
             saga_collision::{self, CircleCollider, Movable, Velocity},
             saga_combat,
             saga_renderer::RebuildCommand,
-            Camera, CameraRenderingInfo,
+            Camera,
         },
     };
+    use anyhow::Result;
     use bevy_app::{App, Plugin};
     use bevy_ecs::{
+        bundle::Bundle,
         component::Component,
         entity::Entity,
         event::{Event, EventReader, EventWriter},
         query::{Changed, With, Without},
         schedule::{
-            common_conditions::{in_state, on_event},
-            IntoSystemConfigs, NextState, OnEnter, OnExit, State, States, SystemSet,
+            common_conditions::{in_state, on_event, state_changed},
+            Condition, IntoSystemConfigs, NextState, OnEnter, OnExit, State, States, SystemSet,
         },
         system::{Commands, Local, ParamSet, Query, Res, ResMut, Resource},
     };
     use bevy_time::{Time, Timer, TimerMode};
     use cgmath::{
-        Array, Deg, Euler, InnerSpace, One, Quaternion, Rad, Rotation as _, Rotation3, Vector2,
-        Vector3, Vector4, Zero,
+        Array, Deg, Euler, InnerSpace, One, Quaternion, Rad, Rotation3, Vector2, Vector3, Vector4,
+        Zero,
     };
     use itertools::Itertools;
     use kira::sound::static_sound::StaticSoundSettings;
     use noise::{NoiseFn, Perlin};
     use rand::Rng;
+    use vulkanalia::vk;
     use winit::event::{ElementState, MouseButton, VirtualKeyCode as Key};
 
     type Quat = cgmath::Quaternion<f32>;
@@ -418,6 +878,11 @@ mod doomclone_game {
                     ),
                 )
                 .add_systems(bevy_app::Startup, spawn_music)
+                .add_systems(bevy_app::Startup, spawn_security_camera)
+                .add_systems(bevy_app::Startup, spawn_skybox)
+                .add_systems(bevy_app::Startup, spawn_particle_effects)
+                .add_systems(bevy_app::Startup, spawn_portal_dispatch)
+                .add_systems(bevy_app::Startup, load_sprite_atlas)
                 .add_systems(
                     bevy_app::Update,
                     (
@@ -429,14 +894,15 @@ mod doomclone_game {
                             .run_if(on_event::<SpawnEnemy>())
                             .after(system_enemy_spawning),
                         system_flash_on_damage,
+                        system_reload_sprite_atlas,
                         animate_gun_shot,
-                        animate_look_at_player,
                         system_animate_wavy,
                         system_enemy_ai,
                         system_animate_camera,
                         system_gun_update,
                         system_player_shooting,
                         on_player_shot,
+                        system_muzzle_smoke,
                         on_entity_death.run_if(on_event::<DeathEvent>()),
                         player_movement,
                         system_player_rotate_with_mouse_x,
@@ -449,6 +915,11 @@ mod doomclone_game {
                             .run_if(on_event::<DeathEvent>()),
                     ),
                 )
+                .add_systems(
+                    bevy_app::Update,
+                    system_apply_stage_fog
+                        .run_if(state_changed::<GameplayStage>.or_else(state_changed::<AppState>)),
+                )
                 .add_systems(
                     OnExit(AppState::Gameplay),
                     (
@@ -457,7 +928,17 @@ mod doomclone_game {
                         spawn_restart_ui.after(system_cleanup_everything),
                     ),
                 )
-                .add_systems(bevy_app::PostUpdate, animate_gun)
+                .add_systems(
+                    bevy_app::Update,
+                    (
+                        system_toggle_debug_view,
+                        system_follow_player_with_debug_camera,
+                    ),
+                )
+                .add_systems(
+                    bevy_app::PostUpdate,
+                    animate_gun.before(TransformPropagationSet),
+                )
                 .add_event::<GunFire>()
                 .add_event::<SpawnEnemy>()
                 .add_event::<Restart>()
@@ -476,6 +957,267 @@ mod doomclone_game {
         scale: f32,
         knockback_resistance: f32,
         path_to_texture: PathBuf,
+        palette: EnemyPalette,
+    }
+
+    /// Recolorings of the colors an enemy texture was drawn in, so one texture serves
+    /// several enemies. The discriminants are the rows of the palette texture made
+    /// for every page of the sprite atlas.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    #[repr(u32)]
+    enum EnemyPalette {
+        Base = 0,
+        Rebirth = 1,
+        /// Shown briefly when the enemy takes damage
+        Flash = 2,
+    }
+
+    impl EnemyPalette {
+        const ALL: [EnemyPalette; 3] = [
+            EnemyPalette::Base,
+            EnemyPalette::Rebirth,
+            EnemyPalette::Flash,
+        ];
+
+        fn recolor(&self, base: &Palette) -> Palette {
+            match self {
+                EnemyPalette::Base => base.clone(),
+                // The same flesh with its hues turned a third of the way around
+                EnemyPalette::Rebirth => base.recolor(|[r, g, b]| [b, r, g]),
+                EnemyPalette::Flash => base.recolor(|[r, g, b]| {
+                    let brightness = r.max(g).max(b);
+                    [u8::MAX, brightness / 4, brightness / 4]
+                }),
+            }
+        }
+    }
+
+    /// Sprites are packed into pages this many pixels wide
+    const ATLAS_PAGE_SIZE: u32 = 512;
+    const ATLAS_PADDING: u32 = 2;
+
+    /// How often the images of the sprite atlas are checked for changes
+    const ATLAS_RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+    const BLOOD_SPRITE: &str = "blood.png";
+    /// Generated rather than loaded, so never reloaded
+    const BULLET_HOLE_SPRITE: &str = "bullet_hole";
+
+    /// An image file packed into the sprite atlas, by its file name.
+    struct AtlasSource {
+        key: String,
+        path: PathBuf,
+        sheet: SpriteSheet,
+        modified: Option<SystemTime>,
+    }
+
+    impl AtlasSource {
+        fn new(path: PathBuf, sheet: SpriteSheet) -> Self {
+            Self {
+                key: get_sprite_key(&path),
+                modified: get_modified_time(&path),
+                path,
+                sheet,
+            }
+        }
+    }
+
+    fn get_sprite_key(path: &Path) -> String {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    fn get_modified_time(path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Every sprite of the game, enemies, decals and the restart message, packed into
+    /// as few textures as possible. Pages are indexed, so enemies on them can be
+    /// recolored with an [`EnemyPalette`].
+    #[derive(Resource)]
+    struct SpriteAtlas {
+        atlas: TextureAtlas,
+        pages: Vec<MainTexture>,
+        sources: Vec<AtlasSource>,
+        reload_timer: Timer,
+    }
+
+    /// A mesh showing a sprite of the atlas, moved to its new place whenever the atlas
+    /// is rebuilt.
+    #[derive(Component)]
+    struct AtlasSprite {
+        key: String,
+        frame: usize,
+    }
+
+    #[derive(Bundle)]
+    struct AtlasSpriteBundle {
+        sprite: AtlasSprite,
+        region: TextureRegion,
+        mesh_rendering_bundle: MeshRenderingBundle,
+    }
+
+    impl SpriteAtlas {
+        fn create(graphics: &mut Graphics, sources: Vec<AtlasSource>) -> Result<Self> {
+            let (atlas, pages) = Self::pack(graphics, &sources)?;
+            Ok(Self {
+                atlas,
+                pages,
+                sources,
+                reload_timer: Timer::new(ATLAS_RELOAD_INTERVAL, TimerMode::Repeating),
+            })
+        }
+
+        fn pack(
+            graphics: &mut Graphics,
+            sources: &[AtlasSource],
+        ) -> Result<(TextureAtlas, Vec<MainTexture>)> {
+            let mut builder = TextureAtlasBuilder::new(ATLAS_PAGE_SIZE, ATLAS_PADDING);
+            for source in sources {
+                let image = Image::load(&source.path)?;
+                let SpriteSheet { columns, rows } = source.sheet;
+                builder.add_sprite_sheet(source.key.clone(), &image, columns, rows)?;
+            }
+            builder.add(
+                BULLET_HOLE_SPRITE,
+                &create_soft_dot_image(16, SpriteSheet::SINGLE)?,
+            )?;
+            let atlas = builder.build()?;
+
+            let pages = atlas
+                .get_pages()
+                .iter()
+                .enumerate()
+                .map(|(index, page)| {
+                    Palette::extract(page)
+                        .map(|base| EnemyPalette::ALL.map(|palette| palette.recolor(&base)))
+                        .and_then(|palettes| MainTexture::create_indexed(graphics, page, &palettes))
+                        .or_else(|error| {
+                            // Still drawn, but always in its own colors
+                            log::warn!("Sprite atlas page {} can't be indexed: {}", index, error);
+                            MainTexture::create(graphics, page, &SamplerDescription::nearest())
+                        })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok((atlas, pages))
+        }
+
+        /// The page a sprite is on, drawn the way the sprite itself needs, and where
+        /// on the page it is.
+        fn get_texture(&self, key: &str, frame: usize) -> Option<(MainTexture, TextureRegion)> {
+            let region = self.atlas.get_frame(key, frame)?;
+            let texture = MainTexture {
+                alpha_mode: region.alpha_mode,
+                ..self.pages[region.page].clone()
+            };
+            Some((texture, TextureRegion(region.uv_rect)))
+        }
+
+        /// A plane showing the first frame of the sprite added as `key`.
+        fn construct_sprite(
+            &self,
+            graphics: &mut ResMut<Graphics>,
+            key: &str,
+        ) -> Result<AtlasSpriteBundle> {
+            let (texture, region) = self
+                .get_texture(key, 0)
+                .ok_or_else(|| anyhow::anyhow!("{:?} is not in the sprite atlas", key))?;
+            let mesh_rendering_bundle =
+                construct_mesh_with_main_texture(graphics, texture, &CPUMesh::get_simple_plane())?;
+
+            Ok(AtlasSpriteBundle {
+                sprite: AtlasSprite {
+                    key: key.to_owned(),
+                    frame: 0,
+                },
+                region,
+                mesh_rendering_bundle,
+            })
+        }
+    }
+
+    fn load_sprite_atlas(
+        mut graphics: ResMut<Graphics>,
+        enemy_templates: Res<AllEnemyTemplates>,
+        mut commands: Commands,
+    ) {
+        let png_folder = std::env::current_dir().unwrap().join("assets").join("png");
+        let paths = enemy_templates
+            .0
+            .iter()
+            .map(|template| template.path_to_texture.clone())
+            .chain(["blood.png", "win.png", "loss.png"].map(|name| png_folder.join(name)))
+            .unique();
+        let sources = paths
+            .map(|path| AtlasSource::new(path, SpriteSheet::SINGLE))
+            .collect();
+
+        commands.insert_resource(SpriteAtlas::create(&mut graphics, sources).unwrap());
+    }
+
+    /// Rebuild the atlas when any of its images changed on disk, and point every
+    /// sprite at its new place. A broken image keeps the previous atlas.
+    fn system_reload_sprite_atlas(
+        time: Res<Time>,
+        mut graphics: ResMut<Graphics>,
+        mut sprite_atlas: ResMut<SpriteAtlas>,
+        mut sprites: Query<(
+            &AtlasSprite,
+            &mut MainTexture,
+            &mut TextureRegion,
+            &mut MeshFragmentData,
+        )>,
+    ) {
+        let sprite_atlas = sprite_atlas.as_mut();
+        sprite_atlas.reload_timer.tick(time.delta());
+        if !sprite_atlas.reload_timer.just_finished() {
+            return;
+        }
+
+        let mut any_changed = false;
+        for source in &mut sprite_atlas.sources {
+            let modified = get_modified_time(&source.path);
+            if modified != source.modified {
+                log::info!("Reloading {:?} into the sprite atlas", source.path);
+                source.modified = modified;
+                any_changed = true;
+            }
+        }
+        if !any_changed {
+            return;
+        }
+
+        match SpriteAtlas::pack(&mut graphics, &sprite_atlas.sources) {
+            Ok((atlas, pages)) => {
+                sprite_atlas.atlas = atlas;
+                sprite_atlas.pages = pages;
+            }
+            Err(error) => {
+                log::warn!("Keeping the previous sprite atlas: {}", error);
+                return;
+            }
+        }
+
+        for (sprite, mut main_texture, mut region, mut fragment_data) in &mut sprites {
+            let Some((texture, new_region)) = sprite_atlas.get_texture(&sprite.key, sprite.frame)
+            else {
+                continue;
+            };
+            // A page that could not be indexed has no palette to pick a row of
+            let palette_row = match (&texture.palette, fragment_data.palette_row) {
+                (None, _) => None,
+                (Some(_), row) => row.or(Some(EnemyPalette::Base as u32)),
+            };
+            if fragment_data.palette_row != palette_row {
+                fragment_data.palette_row = palette_row;
+            }
+            *main_texture = texture;
+            *region = new_region;
+        }
     }
 
     #[derive(Resource)]
@@ -516,6 +1258,7 @@ mod doomclone_game {
                     .join("png")
                     .join("fleshling.png"),
                 max_health: 1,
+                palette: EnemyPalette::Base,
             },
             EnemyTemplate {
                 radius: 1.0,
@@ -529,6 +1272,7 @@ mod doomclone_game {
                     .join("png")
                     .join("gug.png"),
                 max_health: 2,
+                palette: EnemyPalette::Base,
             },
             EnemyTemplate {
                 radius: 0.8,
@@ -542,6 +1286,7 @@ mod doomclone_game {
                     .join("png")
                     .join("owlcat.png"),
                 max_health: 4,
+                palette: EnemyPalette::Base,
             },
             EnemyTemplate {
                 radius: 1.0,
@@ -555,6 +1300,7 @@ mod doomclone_game {
                     .join("png")
                     .join("butterfly.png"),
                 max_health: 1,
+                palette: EnemyPalette::Base,
             },
             EnemyTemplate {
                 radius: 0.5,
@@ -568,6 +1314,35 @@ mod doomclone_game {
                     .join("png")
                     .join("akunohana.png"),
                 max_health: 8,
+                palette: EnemyPalette::Base,
+            },
+            EnemyTemplate {
+                radius: 1.0,
+                damage_radius: 2.2,
+                movement_speed: 2.5,
+                scale: 4.0,
+                knockback_resistance: 0.8,
+                path_to_texture: std::env::current_dir()
+                    .unwrap()
+                    .join("assets")
+                    .join("png")
+                    .join("gug.png"),
+                max_health: 3,
+                palette: EnemyPalette::Rebirth,
+            },
+            EnemyTemplate {
+                radius: 0.5,
+                damage_radius: 1.7,
+                movement_speed: 3.5,
+                scale: 4.0,
+                knockback_resistance: 1.0,
+                path_to_texture: std::env::current_dir()
+                    .unwrap()
+                    .join("assets")
+                    .join("png")
+                    .join("fleshling.png"),
+                max_health: 2,
+                palette: EnemyPalette::Rebirth,
             },
         ]);
 
@@ -633,8 +1408,16 @@ mod doomclone_game {
                     enemy_id: 4,
                     weight: 0.3,
                 },
-            ],
-            enemy_count: 100,
+                EnemyWaveData {
+                    enemy_id: 5,
+                    weight: 0.8,
+                },
+                EnemyWaveData {
+                    enemy_id: 6,
+                    weight: 1.5,
+                },
+            ],
+            enemy_count: 100,
             enemy_cap: 25,
             spawning_interval: Duration::from_millis(200),
         };
@@ -669,12 +1452,13 @@ mod doomclone_game {
         damage_radius: f32,
     }
 
-    #[derive(Component)]
-    struct LookAtPlayer;
-
     enum FlashState {
         Inactive,
-        Active { timer: Timer },
+        Active {
+            timer: Timer,
+            /// Palette to go back to, for meshes with an indexed texture
+            palette_row: Option<u32>,
+        },
     }
 
     #[derive(Component)]
@@ -843,42 +1627,69 @@ mod doomclone_game {
         }
     }
 
-    fn spawn_blood_pool(
+    /// Blood splattered on walls behind an enemy that is shot, at most this far behind it
+    const BLOOD_SPLATTER_RANGE: f32 = 3.0;
+
+    /// Top of the floor mesh
+    const FLOOR_HEIGHT: f32 = -0.2;
+
+    /// Lay a square `size` wide on the surface at `location` facing along `normal`,
+    /// randomly turned around it.
+    fn spawn_decal(
         graphics: &mut ResMut<Graphics>,
         commands: &mut Commands,
-        mut location: Vector3<f32>,
+        sprite_atlas: &SpriteAtlas,
+        sprite: &str,
+        tint: Vector4<f32>,
+        location: Vector3<f32>,
+        normal: Vector3<f32>,
+        size: f32,
+        decal: Decal,
     ) {
-        let cpu_mesh = CPUMesh::get_simple_plane();
-
-        let path_to_texture = std::env::current_dir()
-            .unwrap()
-            .join("assets")
-            .join("png")
-            .join("blood.png");
+        let mut sprite = sprite_atlas.construct_sprite(graphics, sprite).unwrap();
+        sprite.mesh_rendering_bundle.fragment_data.tint = tint;
 
-        let (mesh_rendering_bundle, _) =
-            construct_mesh_with_cpu_mesh(graphics, &path_to_texture, cpu_mesh).unwrap();
-
-        location.y = rand::thread_rng().gen_range(0.00001..0.0001) - 0.2;
-        let rotation = Quaternion::from(Euler {
-            x: Deg(-90.0),
-            y: Deg(0.0),
-            z: Deg(rand::thread_rng().gen_range(0.0..180.0)),
-        });
+        // The plane faces +z
+        let roll = Deg(rand::thread_rng().gen_range(0.0..360.0));
+        let rotation = Quaternion::from_arc(Vector3::unit_z(), normal.normalize(), None)
+            * Quaternion::from_angle_z(roll);
 
         commands.spawn((
             Position(location),
             Rotation(rotation),
-            Scale(Vector3::from_value(4.0)),
-            mesh_rendering_bundle,
+            Scale(Vector3::from_value(size)),
+            decal,
+            sprite,
         ));
     }
 
+    fn spawn_blood_pool(
+        graphics: &mut ResMut<Graphics>,
+        commands: &mut Commands,
+        sprite_atlas: &SpriteAtlas,
+        mut location: Vector3<f32>,
+    ) {
+        location.y = FLOOR_HEIGHT;
+        spawn_decal(
+            graphics,
+            commands,
+            sprite_atlas,
+            BLOOD_SPRITE,
+            Vector4::from_value(1.0),
+            location,
+            Vector3::unit_y(),
+            4.0,
+            Decal::new(Duration::from_secs(60), Duration::from_secs(5)),
+        );
+    }
+
     fn system_spawn_enemy_by_id(
         mut enemy_spawn_commands: EventReader<SpawnEnemy>,
         mut graphics: ResMut<Graphics>,
         mut commands: Commands,
         enemy_templates: Res<AllEnemyTemplates>,
+        sprite_atlas: Res<SpriteAtlas>,
+        particle_effects: Res<ParticleEffects>,
         player_position: Query<&Position, With<Player>>,
         spawn_points: Query<&Position, With<SpawnPoint<Enemy>>>,
     ) {
@@ -886,7 +1697,6 @@ mod doomclone_game {
         let total_spawn_points = spawn_points.iter().len();
 
         for spawn_enemy_command in enemy_spawn_commands.read() {
-            let cpu_mesh = CPUMesh::get_simple_plane();
             let spawn_point = if total_spawn_points == 0 {
                 cgmath::vec3(0.0, 2.0, 0.0)
             } else {
@@ -914,9 +1724,13 @@ mod doomclone_game {
 
             let template = &enemy_templates.0[spawn_enemy_command.0 as usize];
 
-            let (mesh_rendering_bundle, _) =
-                construct_mesh_with_cpu_mesh(&mut graphics, &template.path_to_texture, cpu_mesh)
-                    .unwrap();
+            let mut sprite = sprite_atlas
+                .construct_sprite(&mut graphics, &get_sprite_key(&template.path_to_texture))
+                .unwrap();
+            let fragment_data = &mut sprite.mesh_rendering_bundle.fragment_data;
+            if fragment_data.palette_row.is_some() {
+                fragment_data.palette_row = Some(template.palette as u32);
+            }
 
             commands.spawn((
                 Enemy {
@@ -928,18 +1742,63 @@ mod doomclone_game {
                 CircleCollider {
                     radius: template.radius,
                 },
-                LookAtPlayer,
+                Billboard::Cylindrical,
                 Movable,
                 Velocity(Vector2::zero()),
                 MovementSpeed(template.movement_speed),
                 Health::new(template.max_health),
                 FlashOnDamage::new(Duration::from_millis(100)),
                 Knockbackable::new(template.knockback_resistance),
-                mesh_rendering_bundle,
+                sprite,
             ));
+            commands.spawn((Position(spawn_point), particle_effects.enemy_spawn()));
+        }
+    }
+
+    /// Each wave closes in a little more, the last one hides enemies until they are close.
+    fn get_stage_fog(stage: &GameplayStage) -> Fog {
+        match stage {
+            GameplayStage::Wave1 => Fog {
+                mode: FogMode::Linear,
+                color: Vector3::new(0.35, 0.38, 0.45),
+                start: 20.0,
+                end: 80.0,
+                ..Default::default()
+            },
+            GameplayStage::Wave2 => Fog {
+                mode: FogMode::Exponential,
+                color: Vector3::new(0.3, 0.28, 0.32),
+                start: 5.0,
+                density: 0.04,
+                ..Default::default()
+            },
+            GameplayStage::Wave3 => Fog {
+                mode: FogMode::ExponentialSquared,
+                color: Vector3::new(0.18, 0.08, 0.08),
+                start: 2.0,
+                density: 0.09,
+                height: Some(HeightFog {
+                    base: 0.0,
+                    falloff: 0.8,
+                    density: 0.25,
+                }),
+                ..Default::default()
+            },
         }
     }
 
+    fn system_apply_stage_fog(
+        app_state: Res<State<AppState>>,
+        stage: Res<State<GameplayStage>>,
+        mut fog: ResMut<Fog>,
+    ) {
+        *fog = match app_state.get() {
+            AppState::Gameplay => get_stage_fog(stage.get()),
+            // The win and loss screens must stay readable
+            _ => Fog::default(),
+        };
+    }
+
     fn system_heal_player_to_full(mut player: Query<&mut Health, With<Player>>) {
         for mut player_health in player.iter_mut() {
             player_health.current_health = player_health.max_health;
@@ -953,39 +1812,41 @@ mod doomclone_game {
     fn system_cleanup_everything(
         graphics: Res<Graphics>,
         all_entities_to_clean_up: Query<
-            (
-                Entity,
-                Option<&Mesh>,
-                Option<&MainTexture>,
-                Option<&MeshRenderingInfo>,
-            ),
+            (Entity, Option<&Parent>),
             (
                 Without<Player>,
                 Without<Gun>,
                 Without<Camera>,
                 Without<Music>,
+                Without<Skybox>,
+                Without<ParticleBatch>,
+                Without<SecurityMonitor>,
+                Without<ComputeDispatch>,
             ),
         >,
         mut rebuild_command_writer: EventWriter<RebuildCommand>,
         mut commands: Commands,
     ) {
-        unsafe {
-            graphics.device_wait_idle().unwrap();
-        }
-        all_entities_to_clean_up.iter().for_each(
-            |(entity, mesh, main_texture, mesh_rendering_info)| {
-                if let (Some(mesh), Some(main_texture), Some(mesh_rendering_info)) =
-                    (mesh, main_texture, mesh_rendering_info)
-                {
-                    saga_renderer::remove_mesh(
-                        graphics.as_ref(),
-                        mesh,
-                        main_texture,
-                        mesh_rendering_info,
-                    );
+        let entities_to_clean_up: HashSet<Entity> = all_entities_to_clean_up
+            .iter()
+            .map(|(entity, ..)| entity)
+            .collect();
+
+        all_entities_to_clean_up
+            .iter()
+            .for_each(|(entity, parent)| {
+                // Parts of a model whose parent stays around, like the gun, stay too
+                let is_part_of_persistent_entity =
+                    parent.is_some_and(|parent| !entities_to_clean_up.contains(&parent.0));
+                if is_part_of_persistent_entity {
+                    return;
                 }
+
                 commands.entity(entity).despawn();
-            },
+            });
+        log::info!(
+            "[Saga] Mesh descriptor sets before cleanup: {}",
+            graphics.get_mesh_descriptor_stats()
         );
         rebuild_command_writer.send(RebuildCommand);
     }
@@ -1109,10 +1970,20 @@ mod doomclone_game {
         if *is_not_first_frame {
             flashing_entities.p0().iter_mut().for_each(
                 |(mut mesh_fragment_data, mut flash_on_damage)| {
+                    // Hit again while flashing, the palette is already switched
+                    let palette_row = match flash_on_damage.flashing {
+                        FlashState::Active { palette_row, .. } => palette_row,
+                        FlashState::Inactive => mesh_fragment_data.palette_row,
+                    };
                     flash_on_damage.flashing = FlashState::Active {
                         timer: Timer::new(flash_on_damage.duration, TimerMode::Once),
+                        palette_row,
                     };
-                    mesh_fragment_data.tint = Vector4::new(1.0, 0.0, 0.0, 1.0);
+                    if palette_row.is_some() {
+                        mesh_fragment_data.palette_row = Some(EnemyPalette::Flash as u32);
+                    } else {
+                        mesh_fragment_data.tint = Vector4::new(1.0, 0.0, 0.0, 1.0);
+                    }
                 },
             );
         }
@@ -1122,13 +1993,22 @@ mod doomclone_game {
             |(mut mesh_fragment_data, mut flash_on_damage)| {
                 let should_deactivate = match flash_on_damage.flashing {
                     FlashState::Inactive => false,
-                    FlashState::Active { ref mut timer } => {
+                    FlashState::Active { ref mut timer, .. } => {
                         timer.tick(time.delta());
                         timer.finished()
                     }
                 };
                 if should_deactivate {
-                    mesh_fragment_data.tint = Vector4::from_value(1.0);
+                    if let FlashState::Active {
+                        palette_row: Some(palette_row),
+                        ..
+                    } = flash_on_damage.flashing
+                    {
+                        mesh_fragment_data.palette_row = Some(palette_row);
+                    } else {
+                        mesh_fragment_data.tint = Vector4::from_value(1.0);
+                    }
+                    flash_on_damage.flashing = FlashState::Inactive;
                 }
             },
         );
@@ -1138,6 +2018,10 @@ mod doomclone_game {
         mut player_fire_event: EventReader<GunFire>,
         mut damage_event: EventWriter<DamageEvent>,
         mut knockback_event: EventWriter<KnockbackEvent>,
+        mut graphics: ResMut<Graphics>,
+        mut commands: Commands,
+        particle_effects: Res<ParticleEffects>,
+        sprite_atlas: Res<SpriteAtlas>,
         gun: Query<&Gun>,
         player: Query<(Entity, &Position, &Rotation), With<Player>>,
         movable_objects: Query<(Entity, &Position, &CircleCollider)>,
@@ -1157,41 +2041,99 @@ mod doomclone_game {
 
             let gun = gun.single();
 
-            if let Some(hit) = hit {
-                let (t, target_entity) = hit;
-                let collision_point = player_position.0 + t * player_rotation.forward();
+            // Where the shot would end up on a wall if nothing was in the way
+            let wall_hit = static_objects
+                .iter()
+                .filter_map(|(_, collider)| {
+                    collider.raycast(player_position.0.xz(), player_rotation.forward().xz())
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            let Some((t, target_entity)) = hit else {
+                continue;
+            };
+            let collision_point = player_position.0 + t * player_rotation.forward();
 
-                log::trace!(
-                    "Hiting entity {:?} at location {:?}",
-                    target_entity,
-                    collision_point
-                );
+            log::trace!(
+                "Hiting entity {:?} at location {:?}",
+                target_entity,
+                collision_point
+            );
 
-                saga_combat::deal_damage(&mut damage_event, 1, target_entity, player_entity);
-                saga_collision::apply_knockback(
-                    &mut knockback_event,
-                    target_entity,
-                    gun.knockback_force * player_rotation.forward().xz(),
+            saga_combat::deal_damage(&mut damage_event, 1, target_entity, player_entity);
+            saga_collision::apply_knockback(
+                &mut knockback_event,
+                target_entity,
+                gun.knockback_force * player_rotation.forward().xz(),
+            );
+
+            let wall_decal = if static_objects.contains(target_entity) {
+                Some((
+                    BULLET_HOLE_SPRITE,
+                    Vector4::new(0.05, 0.05, 0.05, 0.9),
+                    0.15,
+                    Decal::new(Duration::from_secs(20), Duration::from_secs(2)),
+                ))
+            } else {
+                commands.spawn((
+                    Position(collision_point),
+                    particle_effects.blood_spurt(-player_rotation.forward()),
+                ));
+                let is_wall_close_behind =
+                    wall_hit.is_some_and(|(wall_t, _)| wall_t - t < BLOOD_SPLATTER_RANGE);
+                is_wall_close_behind.then(|| {
+                    (
+                        BLOOD_SPRITE,
+                        Vector4::from_value(1.0),
+                        1.0,
+                        Decal::new(Duration::from_secs(30), Duration::from_secs(3)),
+                    )
+                })
+            };
+
+            if let (Some((sprite, tint, size, decal)), Some((wall_t, normal))) =
+                (wall_decal, wall_hit)
+            {
+                spawn_decal(
+                    &mut graphics,
+                    &mut commands,
+                    &sprite_atlas,
+                    sprite,
+                    tint,
+                    player_position.0 + wall_t * player_rotation.forward(),
+                    Vector3::new(normal.x, 0.0, normal.y),
+                    size,
+                    decal,
                 );
             }
         }
     }
 
+    fn system_muzzle_smoke(
+        mut player_fire_event: EventReader<GunFire>,
+        mut commands: Commands,
+        particle_effects: Res<ParticleEffects>,
+        player: Query<(&Position, &Rotation), With<Player>>,
+    ) {
+        for _ in player_fire_event.read() {
+            let (player_position, player_rotation) = player.single();
+            // Roughly where the barrel of the gun ends on screen
+            let muzzle = player_position.0 + player_rotation.forward() * 0.8
+                - player_rotation.right() * 0.25
+                - player_rotation.up() * 0.2;
+            commands.spawn((
+                Position(muzzle),
+                particle_effects.muzzle_smoke(player_rotation.forward()),
+            ));
+        }
+    }
+
     fn on_entity_death(
         mut graphics: ResMut<Graphics>,
         mut death_event_reader: EventReader<DeathEvent>,
         mut rebuild_command_writer: EventWriter<RebuildCommand>,
         mut trauma: ResMut<Trauma>,
-        entities_with_mesh: Query<
-            (
-                Entity,
-                &Position,
-                Option<&Mesh>,
-                Option<&MainTexture>,
-                Option<&MeshRenderingInfo>,
-            ),
-            Without<Player>,
-        >,
+        sprite_atlas: Res<SpriteAtlas>,
+        entities_with_mesh: Query<(Entity, &Position), Without<Player>>,
         mut player: Query<(&mut Health, &mut MultipleSounds), With<Player>>,
         mut commands: Commands,
         mut audio_manager: ResMut<AudioRuntimeManager>,
@@ -1201,36 +2143,20 @@ mod doomclone_game {
             .read()
             .map(|death_event| death_event.target)
             .collect();
-        unsafe {
-            graphics.device_wait_idle().unwrap();
-        }
         entities_with_mesh
             .iter()
-            .filter(|(entity, _, _, _, _)| all_dead_targets.contains(entity))
-            .for_each(
-                |(entity, position, mesh, main_texture, mesh_rendering_info)| {
-                    if let (Some(mesh), Some(main_texture), Some(mesh_rendering_info)) =
-                        (mesh, main_texture, mesh_rendering_info)
-                    {
-                        saga_renderer::remove_mesh(
-                            graphics.as_ref(),
-                            mesh,
-                            main_texture,
-                            mesh_rendering_info,
-                        );
-                    }
-                    commands.entity(entity).despawn();
-                    spawn_blood_pool(&mut graphics, &mut commands, position.0);
-                    let (mut player_health, mut sfx) = player.single_mut();
-                    let player_full_heatlh =
-                        player_health.current_health == player_health.max_health;
-                    if !player_full_heatlh {
-                        player_health.current_health += 1;
-                    }
-                    sfx.0[1].play(audio_manager.as_mut()).unwrap();
-                    trauma.0 += 0.4;
-                },
-            );
+            .filter(|(entity, _)| all_dead_targets.contains(entity))
+            .for_each(|(entity, position)| {
+                commands.entity(entity).despawn();
+                spawn_blood_pool(&mut graphics, &mut commands, &sprite_atlas, position.0);
+                let (mut player_health, mut sfx) = player.single_mut();
+                let player_full_heatlh = player_health.current_health == player_health.max_health;
+                if !player_full_heatlh {
+                    player_health.current_health += 1;
+                }
+                sfx.0[1].play(audio_manager.as_mut()).unwrap();
+                trauma.0 += 0.4;
+            });
         rebuild_command_writer.send(RebuildCommand);
     }
 
@@ -1298,28 +2224,6 @@ mod doomclone_game {
         }
     }
 
-    fn animate_look_at_player(
-        mut meshes: Query<(&Position, &mut Rotation), (With<LookAtPlayer>, Without<Player>)>,
-        player: Query<&Position, With<Player>>,
-    ) {
-        for player_position in player.iter() {
-            for (position, mut rotation) in meshes.iter_mut() {
-                let mut look_direction = player_position.0 - position.0;
-                look_direction.y = 0.0;
-
-                let up = cgmath::vec3(0.0, 1.0, 0.0);
-
-                if look_direction.is_zero() {
-                    continue;
-                }
-                look_direction = -look_direction.normalize();
-                look_direction.x = -look_direction.x;
-
-                rotation.0 = Quaternion::look_at(-look_direction, up);
-            }
-        }
-    }
-
     fn system_enemy_ai(
         mut player: Query<(Entity, &Position, Option<&IFrame>, &mut MultipleSounds), With<Player>>,
         mut damage_event_writer: EventWriter<DamageEvent>,
@@ -1441,7 +2345,7 @@ mod doomclone_game {
         perlin: Local<Perlin>,
         button_input: Res<ButtonInput>,
         mut camera_z_rotation: Local<f32>,
-        mut camera: Query<(&mut Position, &mut Rotation), With<Camera>>,
+        mut camera: Query<(&mut Position, &mut Rotation), With<PlayerCamera>>,
         player: Query<(&Position, &Rotation), (With<Player>, Without<Camera>)>,
     ) {
         if player.is_empty() || camera.is_empty() {
@@ -1518,14 +2422,6 @@ mod doomclone_game {
             .join("assets")
             .join("meshes")
             .join("gun.obj");
-        let path_to_texture = std::env::current_dir()
-            .unwrap()
-            .join("assets")
-            .join("png")
-            .join("gun_texture.png");
-
-        let (mesh_rendering_bundle, _) =
-            construct_mesh(&mut graphics, &path_to_obj, &path_to_texture).unwrap();
 
         let position = cgmath::vec3(0.0, 0.0, 0.0);
         let rotation = Quat::one();
@@ -1575,8 +2471,20 @@ mod doomclone_game {
             RelativePosition(position),
             RelativeRotation(rotation),
             MultipleSounds(vec![shoot_audio_emitter, reload_audio_emitter]),
-            mesh_rendering_bundle,
         ));
+
+        let gun = spawn.id();
+        let parts = spawn_model(
+            &mut graphics,
+            &mut commands,
+            gun,
+            &path_to_obj,
+            &SamplerDescription::nearest(),
+        )
+        .unwrap();
+        for (part, _) in parts {
+            commands.entity(part).insert(RenderLayer::VIEWMODEL);
+        }
     }
 
     #[derive(Event)]
@@ -1594,23 +2502,18 @@ mod doomclone_game {
     fn spawn_restart_ui(
         app_state: Res<State<AppState>>,
         mut graphics: ResMut<Graphics>,
+        sprite_atlas: Res<SpriteAtlas>,
         mut commands: Commands,
     ) {
         let state = app_state.get();
-        let path_to_texture = std::env::current_dir()
-            .unwrap()
-            .join("assets")
-            .join("png")
-            .join(match app_state.get() {
-                AppState::Gameplay => "win.png",
-                AppState::Win => "win.png",
-                AppState::Loss => "loss.png",
-            });
-
-        let cpu_mesh = CPUMesh::get_simple_plane();
+        let key = match app_state.get() {
+            AppState::Gameplay => "win.png",
+            AppState::Win => "win.png",
+            AppState::Loss => "loss.png",
+        };
 
-        let (mesh_rendering_bundle, _) =
-            construct_mesh_with_cpu_mesh(&mut graphics, &path_to_texture, cpu_mesh).unwrap();
+        // The atlas padding keeps the edges of the message from bleeding into its neighbours
+        let sprite = sprite_atlas.construct_sprite(&mut graphics, key).unwrap();
 
         let spawn = commands.spawn((
             RestartUI,
@@ -1618,9 +2521,10 @@ mod doomclone_game {
             Rotation(Quat::one()),
             Scale((4.0 as f32) * cgmath::vec3(1.0, 1.0, 1.0)),
             CircleCollider { radius: 1.0 },
-            LookAtPlayer,
+            // Readable from the debug view above as well
+            Billboard::Spherical,
             Health::new(1),
-            mesh_rendering_bundle,
+            sprite,
             Wavy(0.2, 1.5),
         ));
 
@@ -1670,29 +2574,127 @@ mod doomclone_game {
         spawn_floor(&mut graphics, &mut commands);
     }
 
+    /// The particle batches of the game, each effect emits into one of them.
+    #[derive(Resource)]
+    struct ParticleEffects {
+        dots: Entity,
+        sparks: Entity,
+    }
+
+    impl ParticleEffects {
+        fn blood_spurt(&self, direction: Vector3<f32>) -> ParticleEmitter {
+            let settings = ParticleSettings {
+                lifetime: 0.4..=0.8,
+                speed: 2.0..=4.0,
+                direction: direction + Vector3::unit_y() * 0.5,
+                spread: Deg(35.0).into(),
+                gravity: 9.8,
+                drag: 0.5,
+                start_color: Vector4::new(0.6, 0.0, 0.0, 1.0),
+                end_color: Vector4::new(0.3, 0.0, 0.0, 0.0),
+                start_size: 0.12,
+                end_size: 0.05,
+                ..Default::default()
+            };
+            ParticleEmitter::new(self.dots, Emission::Burst(16), Arc::new(settings))
+        }
+
+        fn muzzle_smoke(&self, direction: Vector3<f32>) -> ParticleEmitter {
+            let settings = ParticleSettings {
+                lifetime: 0.6..=1.0,
+                speed: 0.3..=0.8,
+                direction: direction + Vector3::unit_y(),
+                spread: Deg(25.0).into(),
+                // Smoke rises
+                gravity: -0.5,
+                drag: 1.5,
+                start_color: Vector4::new(0.7, 0.7, 0.7, 0.35),
+                end_color: Vector4::new(0.5, 0.5, 0.5, 0.0),
+                start_size: 0.08,
+                end_size: 0.35,
+                ..Default::default()
+            };
+            ParticleEmitter::new(self.dots, Emission::Burst(8), Arc::new(settings))
+        }
+
+        fn enemy_spawn(&self) -> ParticleEmitter {
+            let settings = ParticleSettings {
+                lifetime: 0.5..=0.9,
+                speed: 1.0..=2.5,
+                direction: Vector3::unit_y(),
+                spread: Deg(60.0).into(),
+                gravity: 1.0,
+                start_color: Vector4::new(0.6, 0.9, 1.0, 1.0),
+                end_color: Vector4::new(0.3, 0.4, 1.0, 0.0),
+                start_size: 0.15,
+                end_size: 0.15,
+                frames: 0..4,
+                ..Default::default()
+            };
+            let emission = Emission::Continuous {
+                rate: 80.0,
+                duration: Some(Duration::from_millis(500)),
+            };
+            ParticleEmitter::new(self.sparks, emission, Arc::new(settings))
+        }
+    }
+
+    fn spawn_particle_effects(mut graphics: ResMut<Graphics>, mut commands: Commands) {
+        let sampler =
+            SamplerDescription::linear().with_address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let mut spawn_batch = |sheet: SpriteSheet, capacity: usize| {
+            let image = create_soft_dot_image(32, sheet).unwrap();
+            let batch =
+                ParticleBatch::create(&mut graphics, &image, sheet, &sampler, capacity).unwrap();
+            commands.spawn((batch, Particles::default())).id()
+        };
+
+        let dots = spawn_batch(SpriteSheet::SINGLE, 1024);
+        let sparks = spawn_batch(
+            SpriteSheet {
+                columns: 2,
+                rows: 2,
+            },
+            512,
+        );
+        commands.insert_resource(ParticleEffects { dots, sparks });
+    }
+
+    fn spawn_skybox(mut graphics: ResMut<Graphics>, mut commands: Commands) {
+        let path_to_cubemap = std::env::current_dir()
+            .unwrap()
+            .join("assets")
+            .join("png")
+            .join("sky.png");
+
+        let cubemap = CubemapImage::load_cross(&path_to_cubemap).unwrap();
+        let skybox = Skybox::create(graphics.as_mut(), &cubemap).unwrap();
+
+        commands.spawn(skybox);
+    }
+
     fn spawn_floor(graphics: &mut ResMut<Graphics>, commands: &mut Commands) {
         let path_to_obj = std::env::current_dir()
             .unwrap()
             .join("assets")
             .join("meshes")
             .join("map_ground.obj");
-        let path_to_texture = std::env::current_dir()
-            .unwrap()
-            .join("assets")
-            .join("png")
-            .join("floor.png");
-
-        let (mesh_rendering_bundle, _) =
-            construct_mesh(graphics, &path_to_obj, &path_to_texture).unwrap();
 
         let position = cgmath::vec3(0.0, 0.0, 0.0);
         let rotation = Quat::one();
 
-        let spawn = commands.spawn((
-            Position(position),
-            Rotation(rotation),
-            mesh_rendering_bundle,
-        ));
+        let floor = commands
+            .spawn((Position(position), Rotation(rotation)))
+            .id();
+        // the floor is seen at grazing angles, where nearest filtering shimmers
+        spawn_model(
+            graphics,
+            commands,
+            floor,
+            &path_to_obj,
+            &SamplerDescription::linear(),
+        )
+        .unwrap();
     }
 
     fn spawn_walls(graphics: &mut ResMut<Graphics>, commands: &mut Commands) {
@@ -1701,26 +2703,25 @@ mod doomclone_game {
             .join("assets")
             .join("meshes")
             .join("map_walls.obj");
-        let path_to_texture = std::env::current_dir()
-            .unwrap()
-            .join("assets")
-            .join("png")
-            .join("walls.png");
-
-        let (mesh_rendering_bundle, cpu_mesh) =
-            construct_mesh(graphics, &path_to_obj, &path_to_texture).unwrap();
-
-        let mesh_collider: MeshCollider = MeshCollider::from(cpu_mesh);
 
         let position = cgmath::vec3(0.0, 0.0, 0.0);
         let rotation = Quat::one();
 
-        let spawn = commands.spawn((
-            Position(position),
-            Rotation(rotation),
-            mesh_rendering_bundle,
-            mesh_collider,
-        ));
+        let walls = commands
+            .spawn((Position(position), Rotation(rotation)))
+            .id();
+        let parts = spawn_model(
+            graphics,
+            commands,
+            walls,
+            &path_to_obj,
+            &SamplerDescription::nearest(),
+        )
+        .unwrap();
+        for (part, cpu_mesh) in parts {
+            let mesh_collider: MeshCollider = MeshCollider::from(cpu_mesh);
+            commands.entity(part).insert(mesh_collider);
+        }
     }
 
     fn spawn_player(mut commands: Commands, mut audio_manager: ResMut<AudioRuntimeManager>) {
@@ -1781,90 +2782,314 @@ mod doomclone_game {
         ));
     }
 
+    /// The camera that follows the player.
+    #[derive(Component)]
+    struct PlayerCamera;
+
+    /// Looks straight down on the player in the split screen debug view.
+    #[derive(Component)]
+    struct DebugCamera;
+
+    /// A screen showing what a security camera sees. Stays around between rounds.
+    #[derive(Component)]
+    struct SecurityMonitor;
+
+    const DEBUG_VIEW_KEY: Key = Key::F9;
+    const DEBUG_CAMERA_HEIGHT: f32 = 20.0;
+    const DEBUG_CAMERA_VIEW_SIZE: f32 = 30.0;
+    const SECURITY_CAMERA_RESOLUTION: u32 = 256;
+    const VIEWMODEL_FIELD_OF_VIEW: f32 = 70.0;
+
     fn spawn_camera(window: Res<Window>, mut graphics: ResMut<Graphics>, mut commands: Commands) {
         log::info!("Spawn camera");
         let position = Position(cgmath::vec3(0.0, 2.0, -4.0));
         let rotation = Rotation(Quat::one());
         let size = window.window.inner_size();
 
-        let uniform_buffers = unsafe {
-            UniformBufferSeries::create_from_graphics::<CameraUniformBufferObject>(&graphics)
-                .unwrap()
+        let mut camera = Camera {
+            projection: Projection::Perspective {
+                field_of_view: Deg(90.0).into(),
+                near_plane_distance: 0.1,
+                far_plane_distance: 100.0,
+            },
+            width: 0,
+            height: 0,
+            viewport: ViewportRect::FULL,
+            order: 0,
+            layers: vec![
+                CameraLayer::new(RenderLayer::WORLD),
+                // The gun gets its own field of view and never clips into walls
+                CameraLayer {
+                    layer: RenderLayer::VIEWMODEL,
+                    clear_depth: true,
+                    projection: Some(Projection::Perspective {
+                        field_of_view: Deg(VIEWMODEL_FIELD_OF_VIEW).into(),
+                        near_plane_distance: 0.01,
+                        far_plane_distance: 10.0,
+                    }),
+                },
+            ],
         };
+        camera.fit_to_target(size.width, size.height);
+
+        let rendering_info = saga_renderer::create_camera_rendering_info(
+            graphics.as_mut(),
+            &camera,
+            &position,
+            &rotation,
+            None,
+        )
+        .unwrap();
 
-        uniform_buffers
-            .get_buffers()
-            .iter()
-            .cloned()
-            .enumerate()
-            .for_each(|(index, uniform_buffer)| {
-                let descriptor_set = graphics.global_descriptor_sets[index];
-                let device = graphics.get_device().clone();
-                graphics
-                    .descriptor_writer
-                    .queue_write_buffers::<CameraUniformBufferObject>(
-                        &device,
-                        uniform_buffer,
-                        &[descriptor_set],
-                        0,
-                    )
-            });
+        commands.spawn((position, rotation, camera, rendering_info, PlayerCamera));
+    }
+
+    fn spawn_security_camera(mut graphics: ResMut<Graphics>, mut commands: Commands) {
+        log::info!("Spawn security camera");
+        // Overlooking the starting area
+        let position = Position(cgmath::vec3(0.0, 6.0, -16.0));
+        let rotation = Rotation(Quaternion::from(Euler {
+            x: Deg(20.0),
+            y: Deg(0.0),
+            z: Deg(0.0),
+        }));
 
         let camera = Camera {
-            field_of_view: Deg(90.0).into(),
-            far_plane_distance: 100.0,
-            near_plane_distance: 0.1,
-            width: size.width,
-            height: size.height,
+            projection: Projection::Perspective {
+                field_of_view: Deg(70.0).into(),
+                near_plane_distance: 0.1,
+                far_plane_distance: 100.0,
+            },
+            width: SECURITY_CAMERA_RESOLUTION,
+            height: SECURITY_CAMERA_RESOLUTION,
+            viewport: ViewportRect::FULL,
+            order: 0,
+            layers: vec![CameraLayer::new(RenderLayer::WORLD)],
         };
 
-        let view = Camera::calculate_view_matrix(&position, &rotation);
-        let projection = camera.calculate_projection_matrix();
+        let target = graphics
+            .create_render_target(SECURITY_CAMERA_RESOLUTION, SECURITY_CAMERA_RESOLUTION)
+            .unwrap();
 
-        let spawn = commands.spawn((
-            position,
-            rotation,
-            camera,
-            CameraRenderingInfo {
-                view,
-                projection,
-                uniform_buffers,
-            },
-        ));
-    }
+        let camera_entity = commands.spawn_empty().id();
+        let sampler =
+            SamplerDescription::linear().with_address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let monitor = construct_mesh_with_render_target(
+            &mut graphics,
+            camera_entity,
+            &target,
+            &sampler,
+            &CPUMesh::get_simple_plane(),
+        )
+        .unwrap();
 
-    fn spawn_spawn_points(mut commands: Commands) {
-        let spawn_points: Vec<Vector3<f32>> = vec![
-            cgmath::vec3(-17.3, 2.0, 4.3),
-            cgmath::vec3(12.3, 2.0, 4.6),
-            cgmath::vec3(-2.0, 2.0, 0.0),
-            cgmath::vec3(17.7, 2.0, -34.5),
-            cgmath::vec3(1.2, 2.0, -33.9),
-            cgmath::vec3(7.2, 2.0, -53.9),
-            cgmath::vec3(-11.5, 2.0, -51.0),
-            cgmath::vec3(-18.65, 2.0, -38.0),
-            cgmath::vec3(2.4, 2.0, 5.4),
-            cgmath::vec3(-13.0, 2.0, 6.8),
-        ];
+        let rendering_info = saga_renderer::create_camera_rendering_info(
+            graphics.as_mut(),
+            &camera,
+            &position,
+            &rotation,
+            Some(target),
+        )
+        .unwrap();
+        commands
+            .entity(camera_entity)
+            .insert((position, rotation, camera, rendering_info));
 
-        spawn_points.iter().for_each(|&point| {
-            commands.spawn((Position(point), SpawnPoint::<Enemy>::new()));
-        });
+        commands.spawn((
+            SecurityMonitor,
+            Position(cgmath::vec3(3.0, 3.0, -6.0)),
+            Rotation(Quat::one()),
+            Scale(cgmath::vec3(1.5, 1.5, 1.5)),
+            Billboard::Cylindrical,
+            monitor,
+        ));
     }
-}
 
-mod saga_combat {
-    use std::time::Duration;
+    /// Split the window between the player's view and a view from above the player.
+    fn system_toggle_debug_view(
+        window: Res<Window>,
+        mut graphics: ResMut<Graphics>,
+        mut keyboard_events: EventReader<KeyboardEvent>,
+        mut player_cameras: Query<&mut Camera, With<PlayerCamera>>,
+        debug_cameras: Query<Entity, With<DebugCamera>>,
+        mut commands: Commands,
+    ) {
+        let toggle_count = keyboard_events
+            .read()
+            .filter(|event| event.state == ElementState::Pressed && event.keycode == DEBUG_VIEW_KEY)
+            .count();
+        if toggle_count % 2 == 0 {
+            return;
+        }
 
-    use bevy_app::Plugin;
-    use bevy_ecs::{
-        component::Component,
-        entity::Entity,
-        event::{Event, EventReader, EventWriter},
-        query::{Changed, QueryEntityError},
-        system::{ParamSet, Query, Res},
-    };
-    use bevy_time::{Time, Timer};
+        let Ok(mut player_camera) = player_cameras.get_single_mut() else {
+            return;
+        };
+        let size = window.window.inner_size();
+
+        if debug_cameras.is_empty() {
+            log::info!("Enable split screen debug view");
+            player_camera.viewport = ViewportRect::new(0.0, 0.0, 0.5, 1.0);
+            player_camera.fit_to_target(size.width, size.height);
+
+            let position = Position(cgmath::vec3(0.0, DEBUG_CAMERA_HEIGHT, 0.0));
+            let rotation = Rotation(Quaternion::from(Euler {
+                x: Deg(90.0),
+                y: Deg(0.0),
+                z: Deg(0.0),
+            }));
+            // A map of the surroundings, so no perspective
+            let mut camera = Camera {
+                projection: Projection::Orthographic {
+                    size: DEBUG_CAMERA_VIEW_SIZE,
+                    near_plane_distance: 0.1,
+                    far_plane_distance: 100.0,
+                },
+                width: 0,
+                height: 0,
+                viewport: ViewportRect::new(0.5, 0.0, 0.5, 1.0),
+                order: 1,
+                layers: vec![CameraLayer::new(RenderLayer::WORLD)],
+            };
+            camera.fit_to_target(size.width, size.height);
+
+            let rendering_info = saga_renderer::create_camera_rendering_info(
+                graphics.as_mut(),
+                &camera,
+                &position,
+                &rotation,
+                None,
+            )
+            .unwrap();
+            commands.spawn((position, rotation, camera, rendering_info, DebugCamera));
+        } else {
+            log::info!("Disable split screen debug view");
+            player_camera.viewport = ViewportRect::FULL;
+            player_camera.fit_to_target(size.width, size.height);
+
+            for entity in &debug_cameras {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+
+    fn system_follow_player_with_debug_camera(
+        player: Query<&Position, (With<Player>, Without<DebugCamera>)>,
+        mut debug_cameras: Query<&mut Position, With<DebugCamera>>,
+    ) {
+        let Ok(player_position) = player.get_single() else {
+            return;
+        };
+        for mut position in debug_cameras.iter_mut() {
+            position.0 = player_position.0 + cgmath::vec3(0.0, DEBUG_CAMERA_HEIGHT, 0.0);
+        }
+    }
+
+    const PORTAL_TEXTURE_SIZE: u32 = 128;
+    /// Fills an `rgba8` storage image at binding 0 with a swirling disc, transparent
+    /// outside of it, animated by the time in the uniform buffer at binding 1.
+    const SWIRL_SHADER: &[u8] = include_bytes!("../../shaders_compiled/swirl_comp.spv");
+    const SWIRL_BINDINGS: &[vk::DescriptorType] = &[
+        vk::DescriptorType::STORAGE_IMAGE,
+        vk::DescriptorType::UNIFORM_BUFFER,
+    ];
+    /// Both `local_size_x` and `local_size_y` of [`SWIRL_SHADER`].
+    const SWIRL_GROUP_SIZE: u32 = 8;
+
+    /// The swirl drawn at every spawn point, redrawn each frame by a compute dispatch.
+    #[derive(Resource)]
+    struct PortalTexture(Arc<Owned<LoadedImage>>);
+
+    fn spawn_portal_dispatch(mut graphics: ResMut<Graphics>, mut commands: Commands) {
+        let extent = vk::Extent2D {
+            width: PORTAL_TEXTURE_SIZE,
+            height: PORTAL_TEXTURE_SIZE,
+        };
+        let texture =
+            LoadedImage::create_storage(&graphics, vk::Format::R8G8B8A8_UNORM, extent).unwrap();
+        let pipeline =
+            ComputePipeline::create_from_graphics(&graphics, SWIRL_SHADER, SWIRL_BINDINGS).unwrap();
+
+        let group_count = get_group_count(PORTAL_TEXTURE_SIZE, SWIRL_GROUP_SIZE);
+        let dispatch = ComputeDispatch::create(
+            graphics.as_mut(),
+            pipeline,
+            [group_count, group_count, 1],
+            ComputeBarrier::Fragment,
+        )
+        .and_then(|dispatch| dispatch.with_frame_uniforms(graphics.as_mut(), 1));
+        let dispatch = match dispatch {
+            Ok(dispatch) => dispatch,
+            Err(error) => {
+                log::warn!("Spawn points are left without portals: {}", error);
+                return;
+            }
+        };
+        graphics.queue_write_storage_image(&texture, dispatch.get_descriptor_sets(), 0);
+
+        commands.spawn(dispatch);
+        commands.insert_resource(PortalTexture(Arc::new(texture)));
+    }
+
+    fn spawn_spawn_points(
+        mut graphics: ResMut<Graphics>,
+        portal_texture: Option<Res<PortalTexture>>,
+        mut commands: Commands,
+    ) {
+        let spawn_points: Vec<Vector3<f32>> = vec![
+            cgmath::vec3(-17.3, 2.0, 4.3),
+            cgmath::vec3(12.3, 2.0, 4.6),
+            cgmath::vec3(-2.0, 2.0, 0.0),
+            cgmath::vec3(17.7, 2.0, -34.5),
+            cgmath::vec3(1.2, 2.0, -33.9),
+            cgmath::vec3(7.2, 2.0, -53.9),
+            cgmath::vec3(-11.5, 2.0, -51.0),
+            cgmath::vec3(-18.65, 2.0, -38.0),
+            cgmath::vec3(2.4, 2.0, 5.4),
+            cgmath::vec3(-13.0, 2.0, 6.8),
+        ];
+
+        let portal_texture = portal_texture.map(|portal_texture| MainTexture {
+            texture: portal_texture.0.clone(),
+            sampler: ImageSampler::get_from_graphics(&mut graphics, &SamplerDescription::linear())
+                .unwrap(),
+            alpha_mode: AlphaMode::Cutout,
+            render_target: None,
+            palette: None,
+        });
+
+        spawn_points.iter().for_each(|&point| {
+            let mut spawn_point = commands.spawn((Position(point), SpawnPoint::<Enemy>::new()));
+            if let Some(portal_texture) = &portal_texture {
+                let portal = construct_mesh_with_main_texture(
+                    &mut graphics,
+                    portal_texture.clone(),
+                    &CPUMesh::get_simple_plane(),
+                )
+                .unwrap();
+                spawn_point.insert((
+                    Rotation(Quat::one()),
+                    Scale(cgmath::vec3(2.0, 2.0, 2.0)),
+                    Billboard::Cylindrical,
+                    portal,
+                ));
+            }
+        });
+    }
+}
+
+mod saga_combat {
+    use std::time::Duration;
+
+    use bevy_app::Plugin;
+    use bevy_ecs::{
+        component::Component,
+        entity::Entity,
+        event::{Event, EventReader, EventWriter},
+        query::{Changed, QueryEntityError},
+        system::{ParamSet, Query, Res},
+    };
+    use bevy_time::{Time, Timer};
 
     pub struct CombatPlugin;
 
@@ -1982,26 +3207,424 @@ mod saga_combat {
     }
 }
 
+mod saga_particles {
+    use std::{
+        f32::consts::{PI, TAU},
+        ops::{Range, RangeInclusive},
+        sync::Arc,
+        time::Duration,
+    };
+
+    use anyhow::Result;
+    use bevy_app::Plugin;
+    use bevy_ecs::{
+        component::Component,
+        entity::Entity,
+        schedule::IntoSystemConfigs,
+        system::{Commands, Query, Res},
+    };
+    use bevy_time::Time;
+    use cgmath::{InnerSpace, Rad, Vector3, Vector4, VectorSpace};
+    use rand::Rng;
+
+    use super::{
+        saga_renderer::{ParticleBatch, SpriteSheet},
+        Position,
+    };
+    use crate::core::graphics::{Image, ParticleInstance};
+
+    pub struct ParticlePlugin;
+
+    impl Plugin for ParticlePlugin {
+        fn build(&self, app: &mut bevy_app::App) {
+            app.add_systems(
+                bevy_app::Update,
+                (
+                    system_emit_particles,
+                    system_simulate_particles.after(system_emit_particles),
+                ),
+            );
+        }
+    }
+
+    /// How the particles of an emitter start out and change over their lifetime.
+    /// Ranges are sampled once per particle.
+    #[derive(Clone, Debug)]
+    pub struct ParticleSettings {
+        /// Seconds
+        pub lifetime: RangeInclusive<f32>,
+        pub speed: RangeInclusive<f32>,
+        pub direction: Vector3<f32>,
+        /// Largest angle between `direction` and the initial velocity
+        pub spread: Rad<f32>,
+        /// Downwards acceleration
+        pub gravity: f32,
+        /// Fraction of the velocity lost every second
+        pub drag: f32,
+        pub start_color: Vector4<f32>,
+        pub end_color: Vector4<f32>,
+        pub start_size: f32,
+        pub end_size: f32,
+        /// Sprite sheet frames played once over the lifetime
+        pub frames: Range<u32>,
+    }
+
+    impl Default for ParticleSettings {
+        fn default() -> Self {
+            Self {
+                lifetime: 1.0..=1.0,
+                speed: 1.0..=1.0,
+                direction: Vector3::unit_y(),
+                spread: Rad(0.0),
+                gravity: 0.0,
+                drag: 0.0,
+                start_color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+                end_color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+                start_size: 0.1,
+                end_size: 0.1,
+                frames: 0..1,
+            }
+        }
+    }
+
+    #[derive(Copy, Clone, Debug)]
+    pub enum Emission {
+        /// Everything at once, after which the emitter is despawned
+        Burst(u32),
+        /// `rate` particles a second, despawned once `duration` has passed if it has one
+        Continuous {
+            rate: f32,
+            duration: Option<Duration>,
+        },
+    }
+
+    /// Spawns particles at its `Position` into the [`ParticleBatch`] on `batch`.
+    #[derive(Component)]
+    pub struct ParticleEmitter {
+        pub batch: Entity,
+        pub emission: Emission,
+        pub settings: Arc<ParticleSettings>,
+        accumulated: f32,
+        elapsed: Duration,
+    }
+
+    impl ParticleEmitter {
+        pub fn new(batch: Entity, emission: Emission, settings: Arc<ParticleSettings>) -> Self {
+            Self {
+                batch,
+                emission,
+                settings,
+                accumulated: 0.0,
+                elapsed: Duration::ZERO,
+            }
+        }
+    }
+
+    /// The live particles of the [`ParticleBatch`] on the same entity.
+    #[derive(Component, Default)]
+    pub struct Particles(Vec<Particle>);
+
+    struct Particle {
+        position: Vector3<f32>,
+        velocity: Vector3<f32>,
+        age: f32,
+        lifetime: f32,
+        settings: Arc<ParticleSettings>,
+    }
+
+    impl Particle {
+        fn spawn(
+            rng: &mut impl Rng,
+            position: Vector3<f32>,
+            settings: &Arc<ParticleSettings>,
+        ) -> Self {
+            let direction = random_direction_in_cone(rng, settings.direction, settings.spread);
+            Self {
+                position,
+                velocity: direction * rng.gen_range(settings.speed.clone()),
+                age: 0.0,
+                lifetime: rng.gen_range(settings.lifetime.clone()),
+                settings: settings.clone(),
+            }
+        }
+
+        fn get_instance(&self, sheet: SpriteSheet) -> ParticleInstance {
+            let settings = &self.settings;
+            let t = (self.age / self.lifetime).clamp(0.0, 1.0);
+
+            let frame_count = settings.frames.len() as u32;
+            let frame = settings.frames.start
+                + ((t * frame_count as f32) as u32).min(frame_count.saturating_sub(1));
+
+            ParticleInstance {
+                position: self.position,
+                size: settings.start_size + (settings.end_size - settings.start_size) * t,
+                color: settings.start_color.lerp(settings.end_color, t),
+                uv_rect: sheet.get_uv_rect(frame),
+            }
+        }
+    }
+
+    /// A random direction at most `spread` away from `axis`, spread evenly over the cone.
+    fn random_direction_in_cone(
+        rng: &mut impl Rng,
+        axis: Vector3<f32>,
+        spread: Rad<f32>,
+    ) -> Vector3<f32> {
+        let axis = axis.normalize();
+        let cos_theta = rng.gen_range(spread.0.clamp(0.0, PI).cos()..=1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = rng.gen_range(0.0..TAU);
+
+        // Any vector not parallel to the axis gives a basis around it
+        let helper = if axis.y.abs() < 0.99 {
+            Vector3::unit_y()
+        } else {
+            Vector3::unit_x()
+        };
+        let tangent = axis.cross(helper).normalize();
+        let bitangent = axis.cross(tangent);
+
+        axis * cos_theta + (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta
+    }
+
+    /// White discs fading out towards their edge, one per frame of `sheet` and each
+    /// smaller than the last. Particles tint them with their color.
+    pub fn create_soft_dot_image(frame_size: u32, sheet: SpriteSheet) -> Result<Image> {
+        let width = frame_size * sheet.columns;
+        let height = frame_size * sheet.rows;
+        let frame_count = sheet.get_frame_count();
+
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let frame = (y / frame_size) * sheet.columns + x / frame_size;
+                let radius = 0.5 * (1.0 - 0.5 * frame as f32 / frame_count as f32);
+
+                let half = frame_size as f32 / 2.0;
+                let dx = ((x % frame_size) as f32 + 0.5 - half) / frame_size as f32;
+                let dy = ((y % frame_size) as f32 + 0.5 - half) / frame_size as f32;
+                let distance = (dx * dx + dy * dy).sqrt();
+
+                let alpha = (1.0 - distance / radius).clamp(0.0, 1.0);
+                pixels.extend_from_slice(&[u8::MAX, u8::MAX, u8::MAX, (alpha * 255.0) as u8]);
+            }
+        }
+
+        Image::from_rgba(width, height, pixels)
+    }
+
+    fn system_emit_particles(
+        time: Res<Time>,
+        mut commands: Commands,
+        mut emitters: Query<(Entity, &mut ParticleEmitter, &Position)>,
+        mut batches: Query<(&ParticleBatch, &mut Particles)>,
+    ) {
+        let mut rng = rand::thread_rng();
+        for (entity, mut emitter, position) in &mut emitters {
+            let count = match emitter.emission {
+                Emission::Burst(count) => {
+                    commands.entity(entity).despawn();
+                    count
+                }
+                Emission::Continuous { rate, duration } => {
+                    emitter.elapsed += time.delta();
+                    if duration.is_some_and(|duration| emitter.elapsed >= duration) {
+                        commands.entity(entity).despawn();
+                    }
+
+                    emitter.accumulated += rate * time.delta_seconds();
+                    let count = emitter.accumulated.floor();
+                    emitter.accumulated -= count;
+                    count as u32
+                }
+            };
+
+            let Ok((batch, mut particles)) = batches.get_mut(emitter.batch) else {
+                log::warn!("Particle emitter without a batch to emit into");
+                continue;
+            };
+            // Particles that would not be drawn are not spawned either
+            let room = batch.get_capacity().saturating_sub(particles.0.len());
+            for _ in 0..(count as usize).min(room) {
+                particles
+                    .0
+                    .push(Particle::spawn(&mut rng, position.0, &emitter.settings));
+            }
+        }
+    }
+
+    fn system_simulate_particles(
+        time: Res<Time>,
+        mut batches: Query<(&mut ParticleBatch, &mut Particles)>,
+    ) {
+        let delta = time.delta_seconds();
+        for (mut batch, mut particles) in &mut batches {
+            particles.0.retain_mut(|particle| {
+                particle.age += delta;
+                if particle.age >= particle.lifetime {
+                    return false;
+                }
+
+                let settings = &particle.settings;
+                particle.velocity.y -= settings.gravity * delta;
+                particle.velocity *= (1.0 - settings.drag * delta).max(0.0);
+                particle.position += particle.velocity * delta;
+                true
+            });
+
+            let sheet = batch.get_sheet();
+            batch.instances.clear();
+            batch.instances.extend(
+                particles
+                    .0
+                    .iter()
+                    .map(|particle| particle.get_instance(sheet)),
+            );
+        }
+    }
+}
+
+mod saga_decals {
+    use std::time::Duration;
+
+    use bevy_app::Plugin;
+    use bevy_ecs::{
+        component::Component,
+        entity::Entity,
+        event::EventWriter,
+        system::{Commands, Query, Res, Resource},
+    };
+    use bevy_time::Time;
+
+    use super::saga_renderer::{MeshFragmentData, RebuildCommand};
+
+    pub struct DecalPlugin {
+        pub max_decals: usize,
+    }
+
+    impl Plugin for DecalPlugin {
+        fn build(&self, app: &mut bevy_app::App) {
+            app.insert_resource(DecalLimit(self.max_decals))
+                .add_systems(bevy_app::Update, system_expire_decals);
+        }
+    }
+
+    /// Most decals alive at once. Past it the oldest ones are despawned to make room.
+    #[derive(Resource)]
+    pub struct DecalLimit(pub usize);
+
+    /// A mesh lying on other geometry, like a blood pool or a bullet hole. It is drawn
+    /// after the opaque geometry with a depth bias, so it can sit right on the surface.
+    /// Over the last `fade_duration` of its lifetime the alpha of its tint goes to 0,
+    /// then it is despawned.
+    #[derive(Component)]
+    pub struct Decal {
+        lifetime: Duration,
+        fade_duration: Duration,
+        age: Duration,
+    }
+
+    impl Decal {
+        pub fn new(lifetime: Duration, fade_duration: Duration) -> Self {
+            Self {
+                lifetime,
+                fade_duration: fade_duration.min(lifetime),
+                age: Duration::ZERO,
+            }
+        }
+
+        /// Older decals are drawn first, so newer ones cover them
+        pub fn get_age(&self) -> Duration {
+            self.age
+        }
+    }
+
+    fn system_expire_decals(
+        time: Res<Time>,
+        limit: Res<DecalLimit>,
+        mut decals: Query<(Entity, &mut Decal, &mut MeshFragmentData)>,
+        mut rebuild_command_writer: EventWriter<RebuildCommand>,
+        mut commands: Commands,
+    ) {
+        let mut alive: Vec<(Duration, Entity)> = vec![];
+        let mut expired: Vec<Entity> = vec![];
+        for (entity, mut decal, mut fragment_data) in &mut decals {
+            decal.age += time.delta();
+            if decal.age >= decal.lifetime {
+                expired.push(entity);
+                continue;
+            }
+            alive.push((decal.age, entity));
+
+            let remaining = decal.lifetime - decal.age;
+            if remaining < decal.fade_duration {
+                fragment_data.tint.w = remaining.as_secs_f32() / decal.fade_duration.as_secs_f32();
+            }
+        }
+
+        // Recycle the oldest decals past the limit
+        if alive.len() > limit.0 {
+            alive.sort_by_key(|(age, _)| std::cmp::Reverse(*age));
+            let over_limit = alive.len() - limit.0;
+            expired.extend(alive.iter().take(over_limit).map(|(_, entity)| *entity));
+        }
+
+        if expired.is_empty() {
+            return;
+        }
+        for entity in expired {
+            commands.entity(entity).despawn();
+        }
+        rebuild_command_writer.send(RebuildCommand);
+    }
+}
+
 mod saga_renderer {
     use anyhow::Result;
     use bevy_app::Plugin as BevyPlugin;
     use bevy_ecs::system::ResMut;
     use bevy_ecs::{prelude::*, schedule::ScheduleLabel};
-    use cgmath::{Matrix3, Matrix4, SquareMatrix, Vector4};
+    use cgmath::{ElementWise, InnerSpace, Matrix3, Matrix4, SquareMatrix, Vector3, Vector4};
     use vulkanalia::vk;
 
-    use crate::core::graphics::{graphics_utility, Graphics, StartRenderResult};
+    use std::collections::HashMap;
+
+    use crate::core::graphics::{
+        get_group_count, graphics_utility, AlphaMode, BlendMode, CPUMesh, ComputeBarrier,
+        ComputePipeline, CubemapImage, DescriptorSets, FrameRecorder, GPUMesh, Graphics, Image,
+        ImageSampler, LoadedImage, Owned, ParticleBufferSeries, ParticleInstance, RenderTarget,
+        SamplerDescription, StartRenderResult, UniformBufferSeries, ValidationCounts,
+        ValidationMessage,
+    };
 
-    use super::{saga_window::Window, Camera, CameraRenderingInfo, MainTexture, Mesh};
-    use super::{MeshRenderingInfo, Position, Rotation, Scale};
+    use super::{
+        queue_write_main_texture, queue_write_mesh_uniform_buffers, saga_decals::Decal,
+        saga_window::Window, Billboard, Camera, CameraLayerRenderingInfo, CameraRenderingInfo,
+        MainTexture, Mat4, Mesh, Projection, RenderLayer,
+    };
+    use super::{
+        MeshRenderingInfo, Parent, Position, RelativePosition, RelativeRotation, RelativeScale,
+        Rotation, Scale,
+    };
 
     pub struct Plugin;
 
+    /// Derives the transforms of children from their parents. Systems moving a parent
+    /// run before it, so its children follow in the same frame.
+    #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct TransformPropagationSet;
+
     impl BevyPlugin for Plugin {
         fn build(&self, app: &mut bevy_app::App) {
             app.add_event::<Resize>()
                 .init_schedule(Cleanup)
                 .add_event::<RebuildCommand>()
+                .add_event::<SurfaceFormatChanged>()
+                .add_event::<ValidationEvent>()
+                .init_resource::<ValidationStats>()
+                .init_resource::<Fog>()
+                .init_resource::<RecordedTranslucentOrders>()
                 .add_systems(
                     bevy_app::PostStartup,
                     (
@@ -2015,8 +3638,32 @@ mod saga_renderer {
                     bevy_app::PostUpdate,
                     system_update_mesh_fragment_information,
                 )
+                .add_systems(
+                    bevy_app::PostUpdate,
+                    system_propagate_parent_transforms
+                        .in_set(TransformPropagationSet)
+                        .before(system_update_camera_view),
+                )
                 .add_systems(bevy_app::PostUpdate, system_update_camera_view)
+                .add_systems(bevy_app::PostUpdate, system_update_camera_projection)
                 .add_systems(bevy_app::PostUpdate, system_signal_rebuild_on_mesh_added)
+                .add_systems(
+                    bevy_app::PostUpdate,
+                    system_bind_environment_to_meshes.before(system_signal_rebuild_on_mesh_added),
+                )
+                .add_systems(
+                    bevy_app::PostUpdate,
+                    system_recreate_render_targets
+                        .pipe(system_log_error_result)
+                        .run_if(on_event::<SurfaceFormatChanged>())
+                        .before(system_rebind_changed_main_textures),
+                )
+                .add_systems(
+                    bevy_app::PostUpdate,
+                    system_rebind_changed_main_textures
+                        .pipe(system_log_error_result)
+                        .before(system_signal_rebuild_on_mesh_added),
+                )
                 .add_systems(
                     bevy_app::Last,
                     system_draw
@@ -2030,8 +3677,11 @@ mod saga_renderer {
                         .run_if(on_event::<RebuildCommand>())
                         .before(system_draw),
                 )
-                .add_systems(Cleanup, system_cleanup_camera)
-                .add_systems(Cleanup, system_cleanup_meshes);
+                .add_systems(
+                    bevy_app::Last,
+                    system_collect_validation_messages.after(system_draw),
+                )
+                .add_systems(Cleanup, system_release_gpu_resources);
         }
     }
 
@@ -2042,6 +3692,37 @@ mod saga_renderer {
     #[derive(bevy_ecs::event::Event)]
     pub struct RebuildCommand;
 
+    /// The swapchain was recreated in another format, which render targets must follow.
+    #[derive(bevy_ecs::event::Event)]
+    pub struct SurfaceFormatChanged;
+
+    /// A validation layer message, sent in the frame it was reported in.
+    #[derive(bevy_ecs::event::Event)]
+    pub struct ValidationEvent(pub ValidationMessage);
+
+    // Resources
+    #[derive(Resource, Default)]
+    pub struct ValidationStats {
+        /// Messages reported during the last frame
+        pub last_frame: ValidationCounts,
+        pub total: ValidationCounts,
+    }
+
+    /// The order translucent meshes were recorded in by each swapchain image, see
+    /// [`system_draw`]. Images recorded again on their own since the last full build
+    /// override `every_image`.
+    #[derive(Resource, Default)]
+    pub struct RecordedTranslucentOrders {
+        every_image: TranslucentOrders,
+        images: HashMap<usize, TranslucentOrders>,
+    }
+
+    impl RecordedTranslucentOrders {
+        fn get(&self, image_index: usize) -> &TranslucentOrders {
+            self.images.get(&image_index).unwrap_or(&self.every_image)
+        }
+    }
+
     // Schedules
     #[derive(Clone, Debug, PartialEq, Eq, Hash, ScheduleLabel)]
     pub struct Cleanup;
@@ -2051,31 +3732,452 @@ mod saga_renderer {
     pub struct CameraUniformBufferObject {
         pub view: Matrix4<f32>,
         pub proj: Matrix4<f32>,
+        pub fog: FogUniformObject,
+    }
+
+    /// Fog over the world layer, blended in by the mesh fragment shader and toward the
+    /// horizon of the skybox.
+    #[derive(Resource, Copy, Clone, Debug)]
+    pub struct Fog {
+        pub mode: FogMode,
+        pub color: Vector3<f32>,
+        /// View depth where the fog begins
+        pub start: f32,
+        /// View depth where linear fog covers everything
+        pub end: f32,
+        /// How quickly exponential fog thickens past `start`
+        pub density: f32,
+        pub height: Option<HeightFog>,
+    }
+
+    impl Default for Fog {
+        fn default() -> Self {
+            Self {
+                mode: FogMode::None,
+                color: Vector3::new(0.0, 0.0, 0.0),
+                start: 0.0,
+                end: 0.0,
+                density: 0.0,
+                height: None,
+            }
+        }
+    }
+
+    /// The discriminants are the modes the mesh fragment shader expects.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    #[repr(u32)]
+    pub enum FogMode {
+        None = 0,
+        Linear = 1,
+        Exponential = 2,
+        ExponentialSquared = 3,
+    }
+
+    /// Fog lying on the ground, densest at `base` and thinning out above it.
+    #[derive(Copy, Clone, Debug)]
+    pub struct HeightFog {
+        pub base: f32,
+        /// How quickly the fog thins out with height
+        pub falloff: f32,
+        pub density: f32,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone, Debug)]
+    pub struct FogUniformObject {
+        pub color: Vector4<f32>,
+        pub start: f32,
+        pub end: f32,
+        pub density: f32,
+        pub mode: u32,
+        pub height_base: f32,
+        pub height_falloff: f32,
+        pub height_density: f32,
+        pub height_enabled: u32,
+    }
+
+    impl FogUniformObject {
+        pub fn new(fog: &Fog) -> Self {
+            let height = fog.height.unwrap_or(HeightFog {
+                base: 0.0,
+                falloff: 0.0,
+                density: 0.0,
+            });
+            Self {
+                color: fog.color.extend(1.0),
+                start: fog.start,
+                end: fog.end,
+                density: fog.density,
+                mode: fog.mode as u32,
+                height_base: height.base,
+                height_falloff: height.falloff,
+                height_density: height.density,
+                height_enabled: fog.height.is_some() as u32,
+            }
+        }
     }
 
     #[repr(C)]
     #[derive(Copy, Clone, Debug)]
     pub struct MeshVertexUniformObject {
         pub model: Matrix4<f32>,
+        /// Offset and size of the part of the main texture shown, in texture coordinates
+        pub uv_rect: Vector4<f32>,
+        /// 0 for regular meshes, otherwise a [`Billboard`] mode
+        pub billboard_mode: u32,
+    }
+
+    /// Part of its main texture a mesh shows, as offset and size in texture
+    /// coordinates. Meshes without one show all of it.
+    #[derive(Copy, Clone, Debug, PartialEq, Component)]
+    pub struct TextureRegion(pub Vector4<f32>);
+
+    impl TextureRegion {
+        pub const FULL: Self = Self(Vector4::new(0.0, 0.0, 1.0, 1.0));
     }
 
     #[derive(Copy, Clone, Debug, Component)]
     pub struct MeshFragmentData {
         pub tint: Vector4<f32>,
+        pub alpha_cutoff: f32,
+        /// How much of the environment cubemap shows through, from 0 to 1
+        pub reflectivity: f32,
+        /// Which palette of an indexed texture to draw with
+        pub palette_row: Option<u32>,
     }
 
     #[repr(C)]
     #[derive(Copy, Clone, Debug)]
     pub struct MeshFragmentUniformObject {
         pub tint: Vector4<f32>,
+        pub alpha_cutoff: f32,
+        pub reflectivity: f32,
+        /// -1 for textures that are not indexed
+        pub palette_row: i32,
     }
 
     impl MeshFragmentUniformObject {
         pub fn new(data: &MeshFragmentData) -> Self {
-            Self { tint: data.tint }
+            Self {
+                tint: data.tint,
+                alpha_cutoff: data.alpha_cutoff,
+                reflectivity: data.reflectivity,
+                palette_row: data.palette_row.map_or(-1, |row| row as i32),
+            }
+        }
+    }
+
+    /// A cubemap drawn behind everything else, using only the camera rotation.
+    /// It is also bound as the environment map of every mesh.
+    #[derive(Component)]
+    pub struct Skybox {
+        cube: Owned<GPUMesh>,
+        cubemap: Owned<LoadedImage>,
+        descriptor_sets: Owned<DescriptorSets>,
+    }
+
+    impl Skybox {
+        pub fn create(graphics: &mut Graphics, cubemap: &CubemapImage) -> Result<Self> {
+            let cube = GPUMesh::create(graphics, &CPUMesh::get_skybox_cube())?;
+            let cubemap = LoadedImage::create_cubemap(graphics, cubemap)?;
+
+            let descriptor_sets = graphics.allocate_skybox_descriptor_sets()?;
+            graphics.queue_write_cubemap(Some(&cubemap), &descriptor_sets, 0);
+
+            Ok(Self {
+                cube,
+                cubemap,
+                descriptor_sets,
+            })
+        }
+    }
+
+    /// How the frames of a particle texture are laid out, left to right and then
+    /// top to bottom.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct SpriteSheet {
+        pub columns: u32,
+        pub rows: u32,
+    }
+
+    impl SpriteSheet {
+        pub const SINGLE: Self = Self {
+            columns: 1,
+            rows: 1,
+        };
+
+        pub fn get_frame_count(&self) -> u32 {
+            self.columns * self.rows
+        }
+
+        /// Offset and size of `frame` in texture coordinates
+        pub fn get_uv_rect(&self, frame: u32) -> Vector4<f32> {
+            let frame = frame.min(self.get_frame_count() - 1);
+            let width = 1.0 / self.columns as f32;
+            let height = 1.0 / self.rows as f32;
+            Vector4::new(
+                (frame % self.columns) as f32 * width,
+                (frame / self.columns) as f32 * height,
+                width,
+                height,
+            )
+        }
+    }
+
+    /// Particles sharing one texture, drawn by every world layer with a single
+    /// instanced draw. Whatever simulates them fills `instances` each frame, anything
+    /// past the capacity is left out.
+    #[derive(Component)]
+    pub struct ParticleBatch {
+        texture: Owned<LoadedImage>,
+        sheet: SpriteSheet,
+        descriptor_sets: Owned<DescriptorSets>,
+        buffers: Owned<ParticleBufferSeries>,
+        pub instances: Vec<ParticleInstance>,
+    }
+
+    impl ParticleBatch {
+        pub fn create(
+            graphics: &mut Graphics,
+            texture: &Image,
+            sheet: SpriteSheet,
+            sampler: &SamplerDescription,
+            capacity: usize,
+        ) -> Result<Self> {
+            let texture = LoadedImage::create(graphics, texture)?;
+            let sampler = ImageSampler::get_from_graphics(graphics, sampler)?;
+            let buffers = ParticleBufferSeries::create_from_graphics(graphics, capacity)?;
+
+            let descriptor_sets = graphics.allocate_particle_descriptor_sets()?;
+            let device = graphics.get_device().clone();
+            graphics.descriptor_writer.queue_write_image(
+                &device,
+                &sampler,
+                &texture,
+                &descriptor_sets,
+                0,
+            );
+
+            Ok(Self {
+                texture,
+                sheet,
+                descriptor_sets,
+                buffers,
+                instances: Vec::with_capacity(capacity),
+            })
+        }
+
+        pub fn get_sheet(&self) -> SpriteSheet {
+            self.sheet
+        }
+
+        pub fn get_capacity(&self) -> usize {
+            self.buffers.get_capacity()
+        }
+    }
+
+    /// A compute shader run at the start of every frame, before any camera draws. What
+    /// it reads and writes is bound to its descriptor sets, one per swapchain image,
+    /// with [`Graphics::queue_write_storage_buffer`] and friends. Dispatches run in
+    /// `order`, each followed by `barrier` so the work after it sees what it wrote.
+    #[derive(Component)]
+    pub struct ComputeDispatch {
+        pipeline: Owned<ComputePipeline>,
+        descriptor_sets: Owned<DescriptorSets>,
+        frame_uniforms: Option<Owned<UniformBufferSeries>>,
+        pub group_counts: [u32; 3],
+        pub barrier: ComputeBarrier,
+        pub order: isize,
+    }
+
+    impl ComputeDispatch {
+        pub fn create(
+            graphics: &mut Graphics,
+            pipeline: Owned<ComputePipeline>,
+            group_counts: [u32; 3],
+            barrier: ComputeBarrier,
+        ) -> Result<Self> {
+            if !graphics.can_dispatch_in_frames() {
+                return Err(anyhow::anyhow!(
+                    "The graphics queue of this device can't dispatch compute shaders"
+                ));
+            }
+
+            let descriptor_sets = graphics.allocate_compute_descriptor_sets(&pipeline)?;
+            Ok(Self {
+                pipeline,
+                descriptor_sets,
+                frame_uniforms: None,
+                group_counts,
+                barrier,
+                order: 0,
+            })
+        }
+
+        /// Bind a [`ComputeFrameUniformObject`] to `binding`, updated every frame.
+        pub fn with_frame_uniforms(
+            mut self,
+            graphics: &mut Graphics,
+            binding: u32,
+        ) -> Result<Self> {
+            let frame_uniforms =
+                UniformBufferSeries::create_from_graphics::<ComputeFrameUniformObject>(graphics)?;
+            let device = graphics.get_device().clone();
+            for (uniform_buffer, descriptor_set) in frame_uniforms
+                .get_buffers()
+                .iter()
+                .zip(self.descriptor_sets.iter())
+            {
+                graphics
+                    .descriptor_writer
+                    .queue_write_buffers::<ComputeFrameUniformObject>(
+                        &device,
+                        *uniform_buffer,
+                        &[*descriptor_set],
+                        binding,
+                    );
+            }
+            self.frame_uniforms = Some(frame_uniforms);
+            Ok(self)
+        }
+
+        /// One invocation per item, for a shader declaring `local_size_x = group_size`.
+        pub fn over_items(
+            graphics: &mut Graphics,
+            pipeline: Owned<ComputePipeline>,
+            items: u32,
+            group_size: u32,
+            barrier: ComputeBarrier,
+        ) -> Result<Self> {
+            let group_counts = [get_group_count(items, group_size), 1, 1];
+            Self::create(graphics, pipeline, group_counts, barrier)
+        }
+
+        pub fn get_descriptor_sets(&self) -> &[vk::DescriptorSet] {
+            &self.descriptor_sets
         }
     }
 
+    /// What dispatches made [`ComputeDispatch::with_frame_uniforms`] read every frame.
+    #[repr(C)]
+    #[derive(Copy, Clone, Debug)]
+    pub struct ComputeFrameUniformObject {
+        /// Seconds since the start
+        pub time: f32,
+    }
+
+    pub type RenderableQuery<'w, 's> = Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Mesh,
+            &'static MainTexture,
+            &'static MeshRenderingInfo,
+            Option<&'static Position>,
+            Option<&'static RenderLayer>,
+        ),
+        Without<Decal>,
+    >;
+
+    pub type DecalQuery<'w, 's> = Query<
+        'w,
+        's,
+        (
+            &'static Mesh,
+            &'static MainTexture,
+            &'static MeshRenderingInfo,
+            &'static Decal,
+            Option<&'static RenderLayer>,
+        ),
+    >;
+
+    /// Every entity owning device resources through its components.
+    pub type GpuResourceHolderQuery<'w, 's> = Query<
+        'w,
+        's,
+        Entity,
+        Or<(
+            With<Mesh>,
+            With<MeshRenderingInfo>,
+            With<Skybox>,
+            With<ParticleBatch>,
+            With<ComputeDispatch>,
+            With<CameraRenderingInfo>,
+        )>,
+    >;
+
+    pub type MeshTransformQuery<'w, 's> = Query<
+        'w,
+        's,
+        (
+            &'static Position,
+            &'static Rotation,
+            &'static MeshRenderingInfo,
+            Option<&'static Scale>,
+            Option<&'static Billboard>,
+            Option<&'static TextureRegion>,
+        ),
+    >;
+
+    pub type CameraQuery<'w, 's> = Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Camera,
+            &'static Position,
+            &'static CameraRenderingInfo,
+        ),
+    >;
+
+    /// Translucent meshes ordered back to front relative to the camera,
+    /// so that they blend over whatever is behind them.
+    fn get_sorted_translucent_meshes(
+        meshes: &RenderableQuery,
+        camera_position: Vector3<f32>,
+    ) -> Vec<Entity> {
+        let mut translucent_meshes: Vec<(f32, Entity)> = meshes
+            .iter()
+            .filter(|(_, _, main_texture, ..)| main_texture.alpha_mode == AlphaMode::Translucent)
+            .map(|(entity, _, _, _, position, _)| {
+                let distance2 = position
+                    .map(|position| (position.0 - camera_position).magnitude2())
+                    .unwrap_or(0.0);
+                (distance2, entity)
+            })
+            .collect();
+
+        translucent_meshes.sort_by(|a, b| b.0.total_cmp(&a.0));
+        translucent_meshes
+            .into_iter()
+            .map(|(_, entity)| entity)
+            .collect()
+    }
+
+    /// The sorted translucent meshes of every camera.
+    type TranslucentOrders = HashMap<Entity, Vec<Entity>>;
+
+    fn get_translucent_orders(
+        meshes: &RenderableQuery,
+        cameras: &CameraQuery,
+    ) -> TranslucentOrders {
+        cameras
+            .iter()
+            .map(|(camera, _, position, _)| {
+                (camera, get_sorted_translucent_meshes(meshes, position.0))
+            })
+            .collect()
+    }
+
+    /// The command buffers [`build_command_buffer_from_graphics`] records.
+    enum RecordedImages {
+        /// After waiting for every frame in flight
+        Every,
+        /// Only the one acquired by the frame being drawn, without waiting
+        Acquired(usize),
+    }
+
     #[derive(Bundle)]
     pub struct MeshRenderingBundle {
         pub mesh: Mesh,
@@ -2088,11 +4190,21 @@ mod saga_renderer {
         graphics: ResMut<Graphics>,
         mut rebuild_command: EventWriter<RebuildCommand>,
         meshes_added: Query<(), Added<Mesh>>,
+        skyboxes_added: Query<(), Added<Skybox>>,
+        particle_batches_added: Query<(), Added<ParticleBatch>>,
+        compute_dispatches_changed: Query<(), Changed<ComputeDispatch>>,
+        cameras_changed: Query<(), Changed<Camera>>,
+        mut cameras_removed: RemovedComponents<Camera>,
     ) {
-        let did_any_mesh_added = meshes_added.iter().next().is_some();
-        unsafe {
-            graphics.device_wait_idle().unwrap();
-        }
+        // Viewports and the set of render passes are recorded along with the meshes
+        let did_any_camera_change =
+            cameras_changed.iter().next().is_some() || cameras_removed.read().next().is_some();
+        let did_any_mesh_added = meshes_added.iter().next().is_some()
+            || skyboxes_added.iter().next().is_some()
+            || particle_batches_added.iter().next().is_some()
+            || compute_dispatches_changed.iter().next().is_some()
+            || did_any_camera_change;
+        graphics.device_wait_idle().unwrap();
         if did_any_mesh_added {
             log::trace!("Mesh added");
             rebuild_command.send(RebuildCommand);
@@ -2100,78 +4212,312 @@ mod saga_renderer {
         }
     }
 
-    fn system_camera_on_screen_resize(
-        mut resize_event: EventReader<Resize>,
-        window: Res<Window>,
-        mut cameras: Query<(&mut Camera, &mut CameraRenderingInfo)>,
-    ) {
-        let resize_happens = resize_event.read().next().is_some();
-        if !resize_happens {
-            return;
-        }
+    fn system_camera_on_screen_resize(
+        mut resize_event: EventReader<Resize>,
+        window: Res<Window>,
+        mut cameras: Query<(&mut Camera, &CameraRenderingInfo)>,
+    ) {
+        let resize_happens = resize_event.read().next().is_some();
+        if !resize_happens {
+            return;
+        }
+
+        let size = window.window.inner_size();
+
+        for (mut camera, camera_rendering_info) in cameras.iter_mut() {
+            // Offscreen targets keep their size
+            if camera_rendering_info.target.is_some() {
+                continue;
+            }
+            log::info!("Resize camera to its part of the window");
+            camera.fit_to_target(size.width, size.height);
+        }
+    }
+
+    fn system_update_camera_projection(
+        mut cameras: Query<(&Camera, &mut CameraRenderingInfo), Changed<Camera>>,
+    ) {
+        for (camera, mut camera_rendering_info) in cameras.iter_mut() {
+            for (layer, layer_rendering_info) in
+                camera.layers.iter().zip(&mut camera_rendering_info.layers)
+            {
+                layer_rendering_info.projection =
+                    camera.calculate_projection_matrix(camera.get_layer_projection(layer));
+            }
+        }
+    }
+
+    /// Set up the uniform buffers and global descriptor sets a camera renders each of
+    /// its layers with. Cameras with a `target` render into its texture instead of
+    /// the swapchain.
+    pub fn create_camera_rendering_info(
+        graphics: &mut Graphics,
+        camera: &Camera,
+        position: &Position,
+        rotation: &Rotation,
+        target: Option<RenderTarget>,
+    ) -> Result<CameraRenderingInfo> {
+        let layers = camera
+            .layers
+            .iter()
+            .map(|layer| {
+                let projection =
+                    camera.calculate_projection_matrix(camera.get_layer_projection(layer));
+                create_camera_layer_rendering_info(graphics, projection)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(CameraRenderingInfo {
+            view: Camera::calculate_view_matrix(position, rotation),
+            layers,
+            target,
+        })
+    }
+
+    fn create_camera_layer_rendering_info(
+        graphics: &mut Graphics,
+        projection: Mat4,
+    ) -> Result<CameraLayerRenderingInfo> {
+        let uniform_buffers =
+            UniformBufferSeries::create_from_graphics::<CameraUniformBufferObject>(graphics)?;
 
-        let size = window.window.inner_size();
+        let descriptor_sets = graphics.allocate_global_descriptor_sets()?;
 
-        for (mut camera, mut camera_rendering_info) in cameras.iter_mut() {
-            log::info!("Resize camera and recalculated projection matrix");
-            camera.width = size.width;
-            camera.height = size.height;
-            camera_rendering_info.projection = camera.calculate_projection_matrix();
+        let device = graphics.get_device().clone();
+        for (uniform_buffer, descriptor_set) in uniform_buffers
+            .get_buffers()
+            .iter()
+            .zip(descriptor_sets.iter())
+        {
+            graphics
+                .descriptor_writer
+                .queue_write_buffers::<CameraUniformBufferObject>(
+                    &device,
+                    *uniform_buffer,
+                    &[*descriptor_set],
+                    0,
+                );
         }
-    }
 
-    fn system_build_command_buffer(
-        graphics: Res<Graphics>,
-        meshes: Query<(&Mesh, &MainTexture, &MeshRenderingInfo)>,
-    ) {
-        build_command_buffer_from_graphics(&graphics, meshes).unwrap()
+        Ok(CameraLayerRenderingInfo {
+            projection,
+            uniform_buffers,
+            descriptor_sets,
+        })
     }
 
-    /// Must be called before trying to queue up destroying the mesh
-    pub fn remove_mesh(
-        graphics: &Graphics,
-        mesh: &Mesh,
-        main_texture: &MainTexture,
-        rendering_info: &MeshRenderingInfo,
+    fn system_build_command_buffer(
+        mut graphics: ResMut<Graphics>,
+        mut recorded_translucent_orders: ResMut<RecordedTranslucentOrders>,
+        meshes: RenderableQuery,
+        decals: DecalQuery,
+        skyboxes: Query<&Skybox>,
+        particle_batches: Query<&ParticleBatch>,
+        compute_dispatches: Query<&ComputeDispatch>,
+        cameras: CameraQuery,
     ) {
-        unsafe {
-            rendering_info
-                .vertex_uniform_buffers
-                .destroy_uniform_buffer_series(&graphics);
-            rendering_info
-                .fragment_uniform_buffers
-                .destroy_uniform_buffer_series(&graphics);
-            mesh.gpu_mesh.destroy(&graphics);
-            main_texture.texture.destroy_with_graphics(&graphics);
-            main_texture.sampler.destroy_with_graphics(&graphics);
-        }
+        build_command_buffer_from_graphics(
+            &mut graphics,
+            RecordedImages::Every,
+            &mut recorded_translucent_orders,
+            &meshes,
+            &decals,
+            &skyboxes,
+            &particle_batches,
+            &compute_dispatches,
+            &cameras,
+        )
+        .unwrap()
     }
 
     fn build_command_buffer_from_graphics(
-        graphics: &Graphics,
-        meshes: Query<(&Mesh, &MainTexture, &MeshRenderingInfo)>,
+        graphics: &mut Graphics,
+        images: RecordedImages,
+        recorded_translucent_orders: &mut RecordedTranslucentOrders,
+        meshes: &RenderableQuery,
+        decals: &DecalQuery,
+        skyboxes: &Query<&Skybox>,
+        particle_batches: &Query<&ParticleBatch>,
+        compute_dispatches: &Query<&ComputeDispatch>,
+        cameras: &CameraQuery,
     ) -> Result<()> {
-        log::info!("Build command buffer");
-        unsafe {
-            graphics.record_command_buffers(
-                |graphics: &Graphics, command_buffer: vk::CommandBuffer, index: usize| unsafe {
-                    graphics.bind_descriptor_set(
-                        command_buffer,
-                        &[graphics.global_descriptor_sets[index]],
-                        0,
+        match images {
+            RecordedImages::Every => log::info!("Build command buffer"),
+            RecordedImages::Acquired(index) => {
+                log::trace!("Build command buffer of swapchain image {}", index)
+            }
+        }
+
+        let translucent_meshes = get_translucent_orders(meshes, cameras);
+
+        let mut compute_dispatches: Vec<_> = compute_dispatches.iter().collect();
+        compute_dispatches.sort_by_key(|dispatch| dispatch.order);
+
+        let mut cameras: Vec<_> = cameras.iter().collect();
+        cameras.sort_by_key(|(_, camera, _, _)| camera.order);
+
+        let targets: Vec<_> = cameras
+            .iter()
+            .filter_map(|(_, _, _, rendering_info)| rendering_info.target.as_ref())
+            .collect();
+        graphics.update_render_targets(&targets)?;
+        let graphics = &*graphics;
+
+        // Oldest first, so newer decals cover older ones
+        let mut decals: Vec<_> = decals.iter().collect();
+        decals.sort_by_key(|(_, _, _, decal, _)| std::cmp::Reverse(decal.get_age()));
+
+        let draw_mesh =
+            |recorder: &mut FrameRecorder, mesh: &Mesh, rendering_info: &MeshRenderingInfo| {
+                let index = recorder.get_index();
+                recorder.bind_descriptor_set(&[rendering_info.descriptor_sets[index]], 1);
+                recorder.draw_mesh(&mesh.gpu_mesh);
+            };
+
+        let draw_camera = |recorder: &mut FrameRecorder,
+                           camera_entity: Entity,
+                           camera: &Camera,
+                           camera_rendering_info: &CameraRenderingInfo| {
+            let index = recorder.get_index();
+            let target = camera_rendering_info.target.as_ref();
+            recorder.set_viewport(target, &camera.viewport);
+
+            // A camera can't sample the texture it is drawing into
+            let is_visible =
+                |main_texture: &MainTexture| main_texture.render_target != Some(camera_entity);
+
+            for (layer, layer_rendering_info) in
+                camera.layers.iter().zip(&camera_rendering_info.layers)
+            {
+                let global_descriptor_set = layer_rendering_info.descriptor_sets[index];
+                let is_in_layer = |render_layer: Option<&RenderLayer>| {
+                    render_layer.copied().unwrap_or_default() == layer.layer
+                };
+
+                if layer.clear_depth {
+                    recorder.clear_depth(target, &camera.viewport);
+                }
+                recorder.bind_pipeline(BlendMode::Opaque);
+                recorder.bind_descriptor_set(&[global_descriptor_set], 0);
+
+                // Opaque and cutout geometry first, in any order
+                for (_, mesh, main_texture, rendering_info, _, render_layer) in meshes {
+                    if main_texture.alpha_mode != AlphaMode::Translucent
+                        && is_visible(main_texture)
+                        && is_in_layer(render_layer)
+                    {
+                        draw_mesh(recorder, mesh, rendering_info);
+                    }
+                }
+
+                // Decals over the opaque geometry they lie on
+                recorder.bind_pipeline(BlendMode::Decal);
+                for (mesh, main_texture, rendering_info, _, render_layer) in &decals {
+                    if is_visible(main_texture) && is_in_layer(*render_layer) {
+                        draw_mesh(recorder, mesh, rendering_info);
+                    }
+                }
+
+                // The sky is part of the world and fills whatever the opaque geometry
+                // left uncovered. Without perspective there is no direction to look
+                // at it from.
+                let is_perspective = matches!(
+                    camera.get_layer_projection(layer),
+                    Projection::Perspective { .. }
+                );
+                let skybox = skyboxes.iter().next();
+                if let Some(skybox) =
+                    skybox.filter(|_| is_perspective && layer.layer == RenderLayer::WORLD)
+                {
+                    recorder.draw_skybox(
+                        global_descriptor_set,
+                        skybox.descriptor_sets[index],
+                        &skybox.cube,
                     );
-                    for (mesh, main_texture, rendering_info) in &meshes {
-                        graphics.bind_descriptor_set(
-                            command_buffer,
-                            &[rendering_info.descriptor_sets[index]],
-                            1,
+                }
+
+                // Then translucent geometry back to front, without depth writes
+                recorder.bind_pipeline(BlendMode::Translucent);
+                for entity in translucent_meshes[&camera_entity].iter() {
+                    if let Ok((_, mesh, main_texture, rendering_info, _, render_layer)) =
+                        meshes.get(*entity)
+                    {
+                        if is_visible(main_texture) && is_in_layer(render_layer) {
+                            draw_mesh(recorder, mesh, rendering_info);
+                        }
+                    }
+                }
+
+                // Particles blend over everything in the world and are not sorted
+                if layer.layer == RenderLayer::WORLD {
+                    for batch in particle_batches {
+                        recorder.draw_particles(
+                            global_descriptor_set,
+                            batch.descriptor_sets[index],
+                            &batch.buffers,
                         );
-                        mesh.gpu_mesh.bind(graphics, command_buffer);
-                        mesh.gpu_mesh.draw(graphics, command_buffer);
                     }
-                },
-            )
+                }
+            }
+        };
+
+        let record_frame = |recorder: &mut FrameRecorder| {
+            let index = recorder.get_index();
+
+            // Compute work feeds whatever is drawn this frame, and must not
+            // overwrite what the previous frame is still drawing from
+            if !compute_dispatches.is_empty() {
+                recorder.record_compute_barrier(ComputeBarrier::AfterRendering);
+            }
+            for dispatch in compute_dispatches.iter() {
+                recorder.dispatch_compute(
+                    &dispatch.pipeline,
+                    dispatch.descriptor_sets[index],
+                    dispatch.group_counts,
+                );
+                recorder.record_compute_barrier(dispatch.barrier);
+            }
+
+            // Offscreen targets first, so their textures are up to date by the
+            // time the cameras looking at them draw
+            for (entity, camera, _, rendering_info) in cameras.iter() {
+                let Some(target) = &rendering_info.target else {
+                    continue;
+                };
+                if recorder.begin_target_render_pass(target) {
+                    draw_camera(recorder, *entity, camera, rendering_info);
+                    recorder.end_render_pass();
+                }
+            }
+
+            recorder.begin_swapchain_render_pass();
+            let swapchain_cameras = cameras
+                .iter()
+                .filter(|(_, _, _, rendering_info)| rendering_info.target.is_none());
+            for (i, (entity, camera, _, rendering_info)) in swapchain_cameras.enumerate() {
+                // Overlapping viewports must not depth test against each other
+                if i > 0 {
+                    recorder.clear_depth(None, &camera.viewport);
+                }
+                draw_camera(recorder, *entity, camera, rendering_info);
+            }
+            recorder.end_render_pass();
+        };
+
+        match images {
+            RecordedImages::Every => {
+                graphics.record_command_buffers(record_frame)?;
+                recorded_translucent_orders.images.clear();
+                recorded_translucent_orders.every_image = translucent_meshes;
+            }
+            RecordedImages::Acquired(index) => {
+                graphics.record_acquired_command_buffer(record_frame)?;
+                recorded_translucent_orders
+                    .images
+                    .insert(index, translucent_meshes);
+            }
         }
+        Ok(())
     }
 
     fn system_finalize_descriptors(mut graphics: ResMut<Graphics>) {
@@ -2189,13 +4535,11 @@ mod saga_renderer {
             .get_number_of_buffers();
         let mesh_fragment_uniform_object = MeshFragmentUniformObject::new(mesh_fragment_info);
         for index in 0..number_of_buffers_to_update {
-            unsafe {
-                graphics.update_uniform_buffer_series(
-                    &mesh_rendering_info.fragment_uniform_buffers,
-                    index,
-                    &mesh_fragment_uniform_object,
-                )?;
-            }
+            graphics.update_uniform_buffer_series(
+                &mesh_rendering_info.fragment_uniform_buffers,
+                index,
+                &mesh_fragment_uniform_object,
+            )?;
         }
         Ok(())
     }
@@ -2212,9 +4556,9 @@ mod saga_renderer {
 
     fn update_mesh_transform_information(
         graphics: &ResMut<Graphics>,
-        mesh_query: Query<(&Position, &Rotation, &MeshRenderingInfo, Option<&Scale>)>,
+        mesh_query: MeshTransformQuery,
     ) -> Result<()> {
-        for (position, rotation, rendering_info, scale) in mesh_query.iter() {
+        for (position, rotation, rendering_info, scale, billboard, region) in mesh_query.iter() {
             let rotation_matrix = Matrix4::from(Matrix3::from(rotation.0));
             let translation_matrix = Matrix4::from_translation(position.0);
             let scale_matrix = if let Some(scale) = scale {
@@ -2224,24 +4568,79 @@ mod saga_renderer {
             };
             let model = translation_matrix * rotation_matrix * scale_matrix;
 
-            let ubo = MeshVertexUniformObject { model };
+            let ubo = MeshVertexUniformObject {
+                model,
+                uv_rect: region.map_or(TextureRegion::FULL.0, |region| region.0),
+                billboard_mode: billboard.map_or(0, |billboard| *billboard as u32),
+            };
 
             let number_of_buffers_to_update =
                 rendering_info.vertex_uniform_buffers.get_buffers().len();
             for index in 0..number_of_buffers_to_update {
-                unsafe {
-                    graphics.update_uniform_buffer_series(
-                        &rendering_info.vertex_uniform_buffers,
-                        index,
-                        &ubo,
-                    )?;
-                }
+                graphics.update_uniform_buffer_series(
+                    &rendering_info.vertex_uniform_buffers,
+                    index,
+                    &ubo,
+                )?;
             }
         }
 
         Ok(())
     }
 
+    pub type ParentTransformQuery<'w, 's> = Query<
+        'w,
+        's,
+        (&'static Position, &'static Rotation, Option<&'static Scale>),
+        Without<Parent>,
+    >;
+
+    pub type ChildTransformQuery<'w, 's> = Query<
+        'w,
+        's,
+        (
+            &'static Parent,
+            &'static RelativePosition,
+            &'static RelativeRotation,
+            Option<&'static RelativeScale>,
+            &'static mut Position,
+            &'static mut Rotation,
+            Option<&'static mut Scale>,
+        ),
+    >;
+
+    /// The parent's scale multiplies the child's along the child's own axes, so a child
+    /// rotated against a non-uniformly scaled parent is stretched rather than sheared.
+    fn system_propagate_parent_transforms(
+        parents: ParentTransformQuery,
+        mut children: ChildTransformQuery,
+    ) {
+        for (
+            parent,
+            relative_position,
+            relative_rotation,
+            relative_scale,
+            mut position,
+            mut rotation,
+            scale,
+        ) in children.iter_mut()
+        {
+            let Ok((parent_position, parent_rotation, parent_scale)) = parents.get(parent.0) else {
+                continue;
+            };
+            let parent_scale = parent_scale.map_or(Vector3::new(1.0, 1.0, 1.0), |scale| scale.0);
+
+            position.0 = parent_position.0
+                + parent_rotation.0 * parent_scale.mul_element_wise(relative_position.0);
+            rotation.0 = parent_rotation.0 * relative_rotation.0;
+            if let Some(mut scale) = scale {
+                let relative_scale =
+                    relative_scale.map_or(Vector3::new(1.0, 1.0, 1.0), |scale| scale.0);
+                scale.0 = parent_scale.mul_element_wise(relative_scale);
+            }
+        }
+    }
+
     fn system_update_camera_view(
         mut cameras: Query<(&Position, &Rotation, &mut CameraRenderingInfo)>,
     ) {
@@ -2253,49 +4652,107 @@ mod saga_renderer {
     fn update_camera_transform_information(
         graphics: &ResMut<Graphics>,
         camera_query: Query<(&Camera, &CameraRenderingInfo)>,
+        fog: &Fog,
         image_index: usize,
     ) -> Result<()> {
+        // Only the world is fogged, not what is drawn over it
+        let world_fog = FogUniformObject::new(fog);
+        let no_fog = FogUniformObject::new(&Fog::default());
+
         for (camera, camera_rendering_info) in camera_query.iter() {
             let view = camera_rendering_info.view;
-            let proj = camera_rendering_info.projection;
 
-            let ubo = CameraUniformBufferObject { view, proj };
+            for (camera_layer, layer) in camera.layers.iter().zip(&camera_rendering_info.layers) {
+                let proj = layer.projection;
+                let fog = if camera_layer.layer == RenderLayer::WORLD {
+                    world_fog
+                } else {
+                    no_fog
+                };
+
+                let ubo = CameraUniformBufferObject { view, proj, fog };
 
-            unsafe {
-                graphics.update_uniform_buffer_series(
-                    &camera_rendering_info.uniform_buffers,
-                    image_index,
-                    &ubo,
-                )?;
+                graphics.update_uniform_buffer_series(&layer.uniform_buffers, image_index, &ubo)?;
             }
         }
 
         Ok(())
     }
 
-    fn system_draw(
+    /// Translucent meshes blend back to front, so when any moved past each other since
+    /// the acquired image was recorded, its command buffer is recorded again in their
+    /// new order. The other images follow as they are acquired.
+    pub fn system_draw(
         window: Res<Window>,
         mut graphics: ResMut<Graphics>,
+        mut recorded_translucent_orders: ResMut<RecordedTranslucentOrders>,
         camera_query: Query<(&Camera, &CameraRenderingInfo)>,
-        mesh_query: Query<(&Position, &Rotation, &MeshRenderingInfo, Option<&Scale>)>,
+        mesh_query: MeshTransformQuery,
+        meshes: RenderableQuery,
+        decals: DecalQuery,
+        skyboxes: Query<&Skybox>,
+        particle_batches: Query<&ParticleBatch>,
+        compute_dispatches: Query<&ComputeDispatch>,
+        cameras: CameraQuery,
+        fog: Res<Fog>,
     ) -> Result<bool> {
-        let image_index = unsafe {
-            match graphics.start_render(&window.window) {
-                StartRenderResult::Normal(Ok(image_index)) => image_index,
-                StartRenderResult::Normal(Err(e)) => panic!("{}", e),
-                StartRenderResult::ShouldRecreateSwapchain => {
-                    return Ok(true);
-                }
+        let image_index = match graphics.start_render(&window.window) {
+            StartRenderResult::Normal(Ok(image_index)) => image_index,
+            StartRenderResult::Normal(Err(e)) => panic!("{}", e),
+            StartRenderResult::ShouldRecreateSwapchain => {
+                return Ok(true);
             }
         };
 
-        update_mesh_transform_information(&graphics, mesh_query)?;
-        update_camera_transform_information(&graphics, camera_query, image_index)?;
+        if *recorded_translucent_orders.get(image_index)
+            != get_translucent_orders(&meshes, &cameras)
+        {
+            build_command_buffer_from_graphics(
+                &mut graphics,
+                RecordedImages::Acquired(image_index),
+                &mut recorded_translucent_orders,
+                &meshes,
+                &decals,
+                &skyboxes,
+                &particle_batches,
+                &compute_dispatches,
+                &cameras,
+            )?;
+        }
 
-        unsafe {
-            let should_recreate_swapchain = graphics.end_render(&window.window, image_index);
-            should_recreate_swapchain
+        update_mesh_transform_information(&graphics, mesh_query)?;
+        update_camera_transform_information(&graphics, camera_query, &fog, image_index)?;
+        for batch in &particle_batches {
+            graphics.update_particle_buffer_series(
+                &batch.buffers,
+                image_index,
+                &batch.instances,
+            )?;
+        }
+        let frame_uniforms = ComputeFrameUniformObject {
+            time: graphics.get_start_time().elapsed().as_secs_f32(),
+        };
+        for dispatch in &compute_dispatches {
+            if let Some(series) = &dispatch.frame_uniforms {
+                graphics.update_uniform_buffer_series(series, image_index, &frame_uniforms)?;
+            }
         }
+
+        graphics.end_render(&window.window, image_index)
+    }
+
+    fn system_collect_validation_messages(
+        graphics: Res<Graphics>,
+        mut stats: ResMut<ValidationStats>,
+        mut validation_writer: EventWriter<ValidationEvent>,
+    ) {
+        // Strict mode fails the run here, on the frame that hit the error
+        let (messages, counts) = graphics
+            .drain_validation_messages()
+            .unwrap_or_else(|error| panic!("{}", error));
+        stats.last_frame = counts;
+        stats.total.accumulate(&counts);
+        validation_writer.send_batch(messages.into_iter().map(ValidationEvent));
     }
 
     fn system_handle_swapchain_recreate(In(should_recreate_swapchain): In<Result<bool>>) -> bool {
@@ -2313,7 +4770,14 @@ mod saga_renderer {
         In(should_recreate_swapchain): In<bool>,
         window: Res<Window>,
         mut graphics: ResMut<Graphics>,
-        meshes: Query<(&Mesh, &MainTexture, &MeshRenderingInfo)>,
+        mut recorded_translucent_orders: ResMut<RecordedTranslucentOrders>,
+        meshes: RenderableQuery,
+        decals: DecalQuery,
+        skyboxes: Query<&Skybox>,
+        particle_batches: Query<&ParticleBatch>,
+        compute_dispatches: Query<&ComputeDispatch>,
+        cameras: CameraQuery,
+        mut surface_format_changed: EventWriter<SurfaceFormatChanged>,
     ) -> Result<()> {
         if !should_recreate_swapchain {
             return Ok(());
@@ -2323,18 +4787,51 @@ mod saga_renderer {
 
         let window = &window.window;
 
-        unsafe {
-            // Must wait for rendering is done before swapchain init_resources
-            // can be released
-            graphics.device_wait_idle()?;
+        if graphics.recreate_swapchain(window)? {
+            // The render targets are recreated before anything is recorded again,
+            // which rebuilds the command buffers
+            surface_format_changed.send(SurfaceFormatChanged);
+        } else {
+            build_command_buffer_from_graphics(
+                &mut graphics,
+                RecordedImages::Every,
+                &mut recorded_translucent_orders,
+                &meshes,
+                &decals,
+                &skyboxes,
+                &particle_batches,
+                &compute_dispatches,
+                &cameras,
+            )?;
+        }
 
-            graphics.recreate_swapchain(&window)?;
+        Ok(())
+    }
 
-            build_command_buffer_from_graphics(&graphics, meshes)?;
+    /// Give every camera drawing into a render target a new one in the current surface
+    /// format, and show it on the meshes that showed the old one.
+    fn system_recreate_render_targets(
+        graphics: Res<Graphics>,
+        mut rebuild_command: EventWriter<RebuildCommand>,
+        mut cameras: Query<(Entity, &mut CameraRenderingInfo)>,
+        mut main_textures: Query<&mut MainTexture>,
+    ) -> Result<()> {
+        for (camera, mut rendering_info) in &mut cameras {
+            let Some(target) = &rendering_info.target else {
+                continue;
+            };
+            let extent = target.get_extent();
+            let target = graphics.create_render_target(extent.width, extent.height)?;
 
-            graphics.continue_after_swapchain_construction();
+            for mut main_texture in &mut main_textures {
+                if main_texture.render_target == Some(camera) {
+                    main_texture.texture = target.get_texture().clone();
+                }
+            }
+            rendering_info.target = Some(target);
         }
 
+        rebuild_command.send(RebuildCommand);
         Ok(())
     }
 
@@ -2346,37 +4843,82 @@ mod saga_renderer {
 
     fn discard_error_result(In(result): In<Result<()>>) {}
 
-    fn system_cleanup_meshes(
-        graphics: Res<Graphics>,
-        meshes: Query<(&Mesh, &MainTexture, &MeshRenderingInfo)>,
-    ) {
-        let graphics = graphics.as_ref();
-        for (mesh, main_texture, rendering_info) in &meshes {
-            unsafe {
-                rendering_info
-                    .vertex_uniform_buffers
-                    .destroy_uniform_buffer_series(&graphics);
-                rendering_info
-                    .fragment_uniform_buffers
-                    .destroy_uniform_buffer_series(&graphics);
-                mesh.gpu_mesh.destroy(&graphics);
-                main_texture.texture.destroy_with_graphics(&graphics);
-                main_texture.sampler.destroy_with_graphics(&graphics);
+    /// Despawn everything holding device resources, so they are handed back before
+    /// the graphics are destroyed.
+    fn system_release_gpu_resources(renderables: GpuResourceHolderQuery, mut commands: Commands) {
+        log::info!(
+            "[Saga] Releasing the resources of {} entities",
+            renderables.iter().count()
+        );
+        for entity in &renderables {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    /// Meshes given another texture after they were built, when an atlas is rebuilt
+    /// for example. Its alpha mode may have moved them to another pass as well.
+    /// Frames in flight may still read the descriptor sets of a mesh, so a changed
+    /// texture is written to new sets. The old ones go back to the allocator, which
+    /// frees them once those frames have finished.
+    fn system_rebind_changed_main_textures(
+        mut graphics: ResMut<Graphics>,
+        mut rebuild_command: EventWriter<RebuildCommand>,
+        skyboxes: Query<&Skybox>,
+        mut meshes: Query<(Ref<MainTexture>, &mut MeshRenderingInfo), Changed<MainTexture>>,
+    ) -> Result<()> {
+        let cubemap = skyboxes.iter().next().map(|skybox| &*skybox.cubemap);
+
+        let mut any_written = false;
+        for (main_texture, mut rendering_info) in &mut meshes {
+            // Written when the mesh was built
+            if main_texture.is_added() {
+                continue;
             }
+
+            let descriptor_sets = graphics.allocate_mesh_descriptor_sets()?;
+            queue_write_main_texture(&mut graphics, &main_texture, &descriptor_sets);
+            graphics.queue_write_cubemap(cubemap, &descriptor_sets, 3);
+            queue_write_mesh_uniform_buffers(
+                &mut graphics,
+                &rendering_info.vertex_uniform_buffers,
+                &rendering_info.fragment_uniform_buffers,
+                &descriptor_sets,
+            );
+
+            // The previous sets are released once no frame in flight uses them
+            rendering_info.descriptor_sets = descriptor_sets;
+            any_written = true;
+        }
+
+        if any_written {
+            graphics_utility::descriptor_writer_write(graphics.as_mut());
+            rebuild_command.send(RebuildCommand);
         }
-        log::info!("[Saga] Cleaning up all {} meshes", meshes.iter().count());
+
+        Ok(())
     }
 
-    fn system_cleanup_camera(graphics: Res<Graphics>, cameras: Query<&CameraRenderingInfo>) {
-        let graphics = graphics.as_ref();
-        for camera_rendering_info in &cameras {
-            unsafe {
-                camera_rendering_info
-                    .uniform_buffers
-                    .destroy_uniform_buffer_series(&graphics);
+    fn system_bind_environment_to_meshes(
+        mut graphics: ResMut<Graphics>,
+        skyboxes: Query<Ref<Skybox>>,
+        meshes: Query<Ref<MeshRenderingInfo>>,
+    ) {
+        let skybox = skyboxes.iter().next();
+        let skybox_added = skybox.as_ref().is_some_and(|skybox| skybox.is_added());
+
+        let mut any_written = false;
+        for rendering_info in &meshes {
+            if skybox_added || rendering_info.is_added() {
+                let cubemap = skybox.as_ref().map(|skybox| &*skybox.cubemap);
+                graphics.queue_write_cubemap(cubemap, &rendering_info.descriptor_sets, 3);
+                any_written = true;
             }
         }
-        log::info!("[Saga] Cleaning up all {} cameras", cameras.iter().count());
+
+        if any_written {
+            graphics.device_wait_idle().unwrap();
+            graphics_utility::descriptor_writer_write(graphics.as_mut());
+        }
     }
 }
 
@@ -2556,13 +5098,11 @@ mod saga_window {
                     // Wait for GPU idle. We won't be issuing any further commands anyways
                     // And this allows for cleanup to be done next frame without
                     // interference from working processes on the GPU
-                    unsafe {
-                        app.world
-                            .get_resource::<Graphics>()
-                            .expect("Resource missing: Graphics")
-                            .device_wait_idle()
-                            .unwrap();
-                    }
+                    app.world
+                        .get_resource::<Graphics>()
+                        .expect("Resource missing: Graphics")
+                        .device_wait_idle()
+                        .unwrap();
 
                     log::info!("[Cleanup] Running cleanup schedule");
                     app.world.run_schedule(Cleanup);
@@ -2780,6 +5320,26 @@ mod saga_collision {
         }
     }
 
+    impl MeshCollider {
+        /// Where a ray first hits this collider, along with the normal of the wall it
+        /// hits, facing back towards `position`.
+        pub fn raycast(
+            &self,
+            position: Vector2<f32>,
+            direction: Vector2<f32>,
+        ) -> Option<(f32, Vector2<f32>)> {
+            self.lines
+                .iter()
+                .filter_map(|&segment| {
+                    penetration_time_point_line(position, segment, direction).map(|t| (t, segment))
+                })
+                .min_by(sort_result)
+                .map(|(t, segment)| (t, segment.get_normal_scaled(position)))
+                .filter(|(_, normal)| !normal.is_zero())
+                .map(|(t, normal)| (t, normal.normalize()))
+        }
+    }
+
     #[derive(Component)]
     pub struct Movable;
 
@@ -3559,14 +6119,249 @@ mod saga_audio {
     }
 }
 
+mod saga_capture {
+    use anyhow::Result;
+    use bevy_app::{App, Plugin};
+    use bevy_ecs::prelude::*;
+    use bevy_time::TimeUpdateStrategy;
+    use std::{
+        path::PathBuf,
+        sync::mpsc::{self, Sender},
+        thread::JoinHandle,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+    use winit::event::ElementState;
+
+    use crate::core::graphics::{CapturedFrame, Graphics};
+
+    use super::saga_input::{Key, KeyboardEvent};
+    use super::saga_renderer::{system_draw, Cleanup};
+
+    const SCREENSHOT_KEY: Key = Key::F12;
+    const RECORDING_KEY: Key = Key::F10;
+    const SCREENSHOT_DIRECTORY: &str = "screenshots";
+    const RECORDING_DIRECTORY: &str = "recordings";
+    const RECORDING_FRAMES_PER_SECOND: u32 = 60;
+
+    pub struct CapturePlugin;
+
+    impl Plugin for CapturePlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(FrameCapture::new())
+                .add_systems(bevy_app::Update, system_capture_hotkeys)
+                .add_systems(bevy_app::Last, system_request_capture.before(system_draw))
+                .add_systems(bevy_app::Last, system_collect_capture.after(system_draw))
+                .add_systems(Cleanup, system_finish_writing);
+        }
+    }
+
+    struct Recording {
+        directory: PathBuf,
+        next_frame: u32,
+    }
+
+    /// Screenshot and frame sequence capture. Frames are encoded to PNG on a
+    /// writer thread so capturing does not stall the game loop.
+    #[derive(Resource)]
+    pub struct FrameCapture {
+        screenshot_requested: bool,
+        // requested from graphics, which hands the frame over once it finished
+        screenshot_in_flight: bool,
+        recording: Option<Recording>,
+        writer: Option<Sender<(CapturedFrame, PathBuf)>>,
+        writer_thread: Option<JoinHandle<()>>,
+    }
+
+    impl FrameCapture {
+        fn new() -> Self {
+            let (writer, receiver) = mpsc::channel::<(CapturedFrame, PathBuf)>();
+            let writer_thread = std::thread::spawn(move || {
+                for (frame, path) in receiver {
+                    match frame.save_png(&path) {
+                        Ok(()) => log::trace!("Wrote frame to {}", path.display()),
+                        Err(e) => log::error!("Failed to write {}: {}", path.display(), e),
+                    }
+                }
+            });
+
+            Self {
+                screenshot_requested: false,
+                screenshot_in_flight: false,
+                recording: None,
+                writer: Some(writer),
+                writer_thread: Some(writer_thread),
+            }
+        }
+
+        /// Save the next presented frame as a timestamped PNG
+        pub fn request_screenshot(&mut self) {
+            self.screenshot_requested = true;
+        }
+
+        pub fn is_recording(&self) -> bool {
+            self.recording.is_some()
+        }
+
+        /// Dump every presented frame into a numbered image sequence
+        pub fn start_recording(&mut self) -> Result<()> {
+            let directory =
+                PathBuf::from(RECORDING_DIRECTORY).join(format!("recording-{}", timestamp()));
+            std::fs::create_dir_all(&directory)?;
+            log::info!("Recording frames to {}", directory.display());
+
+            self.recording = Some(Recording {
+                directory,
+                next_frame: 0,
+            });
+            Ok(())
+        }
+
+        pub fn stop_recording(&mut self) {
+            if let Some(recording) = self.recording.take() {
+                log::info!(
+                    "Recorded {} frames to {}",
+                    recording.next_frame,
+                    recording.directory.display()
+                );
+            }
+        }
+
+        fn wants_frame(&self) -> bool {
+            self.screenshot_requested || self.recording.is_some()
+        }
+
+        fn next_paths(&mut self) -> Result<Vec<PathBuf>> {
+            let mut paths = vec![];
+
+            if self.screenshot_in_flight {
+                self.screenshot_in_flight = false;
+                std::fs::create_dir_all(SCREENSHOT_DIRECTORY)?;
+                paths.push(
+                    PathBuf::from(SCREENSHOT_DIRECTORY)
+                        .join(format!("screenshot-{}.png", timestamp())),
+                );
+            }
+
+            if let Some(recording) = &mut self.recording {
+                paths.push(
+                    recording
+                        .directory
+                        .join(format!("frame-{:06}.png", recording.next_frame)),
+                );
+                recording.next_frame += 1;
+            }
+
+            Ok(paths)
+        }
+    }
+
+    fn timestamp() -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or_default()
+    }
+
+    fn system_capture_hotkeys(
+        mut commands: Commands,
+        mut capture: ResMut<FrameCapture>,
+        mut keyboard_events: EventReader<KeyboardEvent>,
+    ) {
+        for event in keyboard_events.read() {
+            if event.state != ElementState::Pressed {
+                continue;
+            }
+
+            match event.keycode {
+                SCREENSHOT_KEY => capture.request_screenshot(),
+                RECORDING_KEY if capture.is_recording() => {
+                    capture.stop_recording();
+                    commands.insert_resource(TimeUpdateStrategy::Automatic);
+                }
+                RECORDING_KEY => {
+                    if let Err(e) = capture.start_recording() {
+                        log::error!("Failed to start recording: {}", e);
+                        continue;
+                    }
+                    // Advance the simulation by a fixed step so the sequence
+                    // plays back at a constant rate regardless of encode cost
+                    commands.insert_resource(TimeUpdateStrategy::ManualDuration(
+                        Duration::from_secs(1) / RECORDING_FRAMES_PER_SECOND,
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn system_request_capture(mut capture: ResMut<FrameCapture>, mut graphics: ResMut<Graphics>) {
+        if !capture.wants_frame() {
+            return;
+        }
+
+        if let Err(e) = graphics.request_frame_capture() {
+            log::error!("Cannot capture frame: {}", e);
+            capture.screenshot_requested = false;
+            capture.stop_recording();
+        } else if capture.screenshot_requested {
+            capture.screenshot_requested = false;
+            capture.screenshot_in_flight = true;
+        }
+    }
+
+    fn system_collect_capture(mut capture: ResMut<FrameCapture>, mut graphics: ResMut<Graphics>) {
+        let Some(frame) = graphics.take_captured_frame() else {
+            return;
+        };
+
+        let paths = match capture.next_paths() {
+            Ok(paths) => paths,
+            Err(e) => {
+                log::error!("Cannot prepare capture output: {}", e);
+                return;
+            }
+        };
+
+        let Some(writer) = &capture.writer else {
+            return;
+        };
+
+        // Only the last destination takes ownership, the rest get copies
+        let mut frame = Some(frame);
+        let count = paths.len();
+        for (index, path) in paths.into_iter().enumerate() {
+            let to_send = if index + 1 == count {
+                frame.take()
+            } else {
+                frame.clone()
+            };
+            if let Some(to_send) = to_send {
+                let _ = writer.send((to_send, path));
+            }
+        }
+    }
+
+    fn system_finish_writing(mut capture: ResMut<FrameCapture>) {
+        capture.stop_recording();
+        // Closing the channel lets the writer thread drain and exit
+        capture.writer = None;
+        if let Some(writer_thread) = capture.writer_thread.take() {
+            let _ = writer_thread.join();
+        }
+    }
+}
+
 pub fn construct_app() -> App {
     let mut app = App::new();
     app.add_plugins((
         saga_window::WindowPlugin,
         saga_renderer::Plugin,
+        saga_capture::CapturePlugin,
         saga_collision::CollisionPlugin,
         saga_audio::AudioPlugin,
         saga_combat::CombatPlugin,
+        saga_particles::ParticlePlugin,
+        saga_decals::DecalPlugin { max_decals: 64 },
         bevy_time::TimePlugin,
         doomclone_game::GamePlugin,
     ));